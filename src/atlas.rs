@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use image::RgbaImage;
+use crate::render_cache::RenderCache;
+use crate::texture;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 2048;
+/// Transparent border kept around every image so linear filtering doesn't bleed neighbours in.
+/// Mip levels stop while the border is still at least a texel wide.
+pub const DEFAULT_PADDING: u32 = 4;
+
+/// Position of one packed rectangle, excluding its padding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where a sprite lives in the atlas.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtlasRegion {
+    pub page: usize,
+    /// Texture coordinates of the top-left and bottom-right corner.
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    /// Size in texels.
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Packs `sizes` onto pages of `page_size` with shelf packing, tallest first.
+/// Returns the placements in the order of `sizes` and the size of every page;
+/// images larger than a page get a page of their own.
+pub fn pack(sizes: &[(u32, u32)], page_size: u32, padding: u32) -> (Vec<Placement>, Vec<(u32, u32)>) {
+    struct Page {
+        width: u32,
+        height: u32,
+        shelf_x: u32,
+        shelf_y: u32,
+        shelf_height: u32,
+    }
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&index| (std::cmp::Reverse(sizes[index].1), std::cmp::Reverse(sizes[index].0)));
+
+    let mut pages: Vec<Page> = Vec::new();
+    let mut placements = vec![Placement { page: 0, x: 0, y: 0, width: 0, height: 0 }; sizes.len()];
+    for index in order {
+        let (width, height) = sizes[index];
+        let (padded_width, padded_height) = (width + 2 * padding, height + 2 * padding);
+
+        let mut fit = None;
+        for (page_index, page) in pages.iter_mut().enumerate() {
+            if page.shelf_x + padded_width <= page.width && page.shelf_y + padded_height <= page.height {
+                fit = Some(page_index);
+                break;
+            }
+            let next_shelf_y = page.shelf_y + page.shelf_height;
+            if padded_width <= page.width && next_shelf_y + padded_height <= page.height {
+                // Start a new shelf below the current one
+                page.shelf_x = 0;
+                page.shelf_y = next_shelf_y;
+                page.shelf_height = 0;
+                fit = Some(page_index);
+                break;
+            }
+        }
+        let page_index = fit.unwrap_or_else(|| {
+            pages.push(Page {
+                width: page_size.max(padded_width),
+                height: page_size.max(padded_height),
+                shelf_x: 0,
+                shelf_y: 0,
+                shelf_height: 0,
+            });
+            pages.len() - 1
+        });
+
+        let page = &mut pages[page_index];
+        placements[index] = Placement {
+            page: page_index,
+            x: page.shelf_x + padding,
+            y: page.shelf_y + padding,
+            width,
+            height,
+        };
+        page.shelf_x += padded_width;
+        page.shelf_height = page.shelf_height.max(padded_height);
+    }
+
+    (placements, pages.iter().map(|page| (page.width, page.height)).collect())
+}
+
+/// Collects images and packs them into atlas pages.
+pub struct AtlasBuilder<K> {
+    pub page_size: u32,
+    pub padding: u32,
+    images: Vec<(K, RgbaImage)>,
+}
+
+impl<K: Hash + Eq + Clone> AtlasBuilder<K> {
+    pub fn new(page_size: u32, padding: u32) -> Self {
+        AtlasBuilder {
+            page_size,
+            padding,
+            images: Vec::new(),
+        }
+    }
+
+    /// Adds a straight-alpha image; it is premultiplied when packed.
+    pub fn add(&mut self, key: K, image: RgbaImage) {
+        self.images.push((key, image));
+    }
+
+    /// Packs the images into premultiplied-alpha pages without touching the GPU.
+    pub fn build_images(&self) -> (Vec<RgbaImage>, HashMap<K, AtlasRegion>) {
+        let sizes: Vec<(u32, u32)> = self.images.iter().map(|(_, image)| image.dimensions()).collect();
+        let (placements, page_sizes) = pack(&sizes, self.page_size, self.padding);
+
+        let mut pages: Vec<RgbaImage> = page_sizes
+            .iter()
+            .map(|(width, height)| RgbaImage::new(*width, *height))
+            .collect();
+        let mut regions = HashMap::new();
+        for ((key, image), placement) in self.images.iter().zip(&placements) {
+            let page = &mut pages[placement.page];
+            let mut image = image.clone();
+            texture::premultiply_alpha(&mut image);
+            for (x, y, pixel) in image.enumerate_pixels() {
+                page.put_pixel(placement.x + x, placement.y + y, *pixel);
+            }
+
+            let (page_width, page_height) = (page.width() as f32, page.height() as f32);
+            regions.insert(key.clone(), AtlasRegion {
+                page: placement.page,
+                uv_min: [placement.x as f32 / page_width, placement.y as f32 / page_height],
+                uv_max: [
+                    (placement.x + placement.width) as f32 / page_width,
+                    (placement.y + placement.height) as f32 / page_height,
+                ],
+                width: placement.width,
+                height: placement.height,
+            });
+        }
+        (pages, regions)
+    }
+
+    pub fn build(&self, device: &wgpu::Device, queue: &wgpu::Queue, cache: &mut RenderCache) -> anyhow::Result<Atlas<K>> {
+        let (images, regions) = self.build_images();
+        let options = texture::TextureOptions {
+            max_mip_levels: Some(self.padding.max(1).ilog2() + 1),
+            premultiply: false,
+            ..Default::default()
+        };
+        let pages = images
+            .into_iter()
+            .enumerate()
+            .map(|(index, image)| {
+                let label = format!("Atlas Page {}", index);
+                texture::Texture::from_image_with_options(device, queue, cache, &image::DynamicImage::ImageRgba8(image), Some(&label), &options)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Atlas { pages, regions })
+    }
+}
+
+/// Packed textures, one wgpu texture per page. Colours are premultiplied by alpha.
+pub struct Atlas<K> {
+    pub pages: Vec<texture::Texture>,
+    pub regions: HashMap<K, AtlasRegion>,
+}
+
+impl<K: Hash + Eq> Atlas<K> {
+    pub fn region(&self, key: &K) -> Option<&AtlasRegion> {
+        self.regions.get(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn overlaps(a: &Placement, b: &Placement, padding: u32) -> bool {
+        a.page == b.page
+            && a.x < b.x + b.width + padding
+            && b.x < a.x + a.width + padding
+            && a.y < b.y + b.height + padding
+            && b.y < a.y + a.height + padding
+    }
+
+    #[test]
+    fn test_pack_without_overlap() {
+        // Deterministic spread of sizes, including one bigger than a page
+        let mut sizes: Vec<(u32, u32)> = (0..200u32)
+            .map(|i| (8 + (i * 37) % 120, 8 + (i * 53) % 90))
+            .collect();
+        sizes.push((300, 40));
+        let (page_size, padding) = (256, 2);
+        let (placements, pages) = pack(&sizes, page_size, padding);
+
+        assert!(pages.len() > 1);
+        for (i, a) in placements.iter().enumerate() {
+            assert_eq!((a.width, a.height), sizes[i]);
+            let (page_width, page_height) = pages[a.page];
+            assert!(a.x >= padding && a.y >= padding);
+            assert!(a.x + a.width + padding <= page_width);
+            assert!(a.y + a.height + padding <= page_height);
+            for b in &placements[i + 1..] {
+                assert!(!overlaps(a, b, padding), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_images() {
+        let mut builder = AtlasBuilder::new(64, 1);
+        builder.add("opaque", RgbaImage::from_pixel(4, 2, Rgba([255, 0, 0, 255])));
+        builder.add("transparent", RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 0])));
+        let (pages, regions) = builder.build_images();
+        assert_eq!(pages.len(), 1);
+
+        let region = regions["opaque"];
+        assert_eq!((region.width, region.height), (4, 2));
+        let x = (region.uv_min[0] * 64.0) as u32;
+        let y = (region.uv_min[1] * 64.0) as u32;
+        assert_eq!(pages[0].get_pixel(x, y), &Rgba([255, 0, 0, 255]));
+        assert_eq!(region.uv_max[0] - region.uv_min[0], 4.0 / 64.0);
+
+        let region = regions["transparent"];
+        let x = (region.uv_min[0] * 64.0) as u32;
+        let y = (region.uv_min[1] * 64.0) as u32;
+        assert_eq!(pages[0].get_pixel(x, y), &Rgba([0, 0, 0, 0]));
+    }
+}
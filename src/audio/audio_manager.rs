@@ -1,20 +1,16 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::sync::{Arc, Mutex};
-use std::thread;
-use cpal::{BufferSize, Device, Stream, SampleFormat, StreamConfig, SupportedBufferSize};
+use cpal::{Device, Stream, SampleFormat, StreamConfig};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use creak::Decoder;
 
 
 pub struct AudioStreamManager {
-    device: Device,
     stream: Stream,
     samples: Arc<Mutex<AudioBufferTracker>>,
     sample_rate: u32,
     channels: u16,
-    sample_format: SampleFormat,
-    buffer_size: BufferSize,
 }
 
 unsafe impl Send for AudioStreamManager {}
@@ -27,24 +23,18 @@ struct AudioBufferTracker {
 
 impl AudioStreamManager {
     pub fn from_file(file_path: &str) -> Result<Self, AudioError> {
-        let decoder = Decoder::open(&file_path).ok().ok_or(AudioError::FileError)?;
+        let decoder = Decoder::open(file_path).ok().ok_or(AudioError::FileError)?;
 
         let sample_rate = decoder.info().sample_rate();
 
         let host = cpal::default_host();
         let device = host.default_output_device().ok_or(AudioError::DeviceError)?;
+        let config = device.default_output_config().ok().ok_or(AudioError::DeviceError)?;
+        // The stream plays at the file's rate, so positions are converted with it too
         let format = StreamConfig {
-            channels: device.default_output_config().ok().ok_or(AudioError::DeviceError)?.channels(),
+            channels: config.channels(),
             sample_rate: cpal::SampleRate(sample_rate),
-            buffer_size: BufferSize::Default
-        };
-        let config = device.default_output_config().ok().ok_or(AudioError::DeviceError)?;
-        let sample_rate = config.sample_rate().0;
-        let channels = config.channels();
-        let sample_format = config.sample_format();
-        let buffer_size = match config.buffer_size() {
-            SupportedBufferSize::Range { min, max} => BufferSize::Fixed(*max),
-            SupportedBufferSize::Unknown => BufferSize::Default
+            buffer_size: cpal::BufferSize::Default
         };
 
         let file_channels = decoder.info().channels();
@@ -61,7 +51,6 @@ impl AudioStreamManager {
                 Err(_) => return Err(AudioError::FileError),
             })
         };
-        let audio_duration = *(&samples.len()) as f32 / sample_rate as f32;
 
         // Upmix / Downmix the samples to match the device's channel count, if necessary
         // right now, only mono to stereo and stereo to mono are supported
@@ -79,20 +68,19 @@ impl AudioStreamManager {
             samples,
             position: 0
         }));
-        let samples_clone = Arc::clone(&samples_shared);
 
-        let stream = build_stream(sample_format, &format, &samples_shared, &device)?;
+        let stream = build_stream(config.sample_format(), &format, &samples_shared, &device)?;
+        // Streams may start playing as soon as they are built
+        stream.pause().map_err(|_| AudioError::StreamError)?;
 
         Ok(AudioStreamManager {
-            device,
             stream,
             sample_rate,
-            channels,
-            sample_format,
-            buffer_size,
+            channels: device_channels,
             samples: samples_shared,
         })
     }
+
     pub fn play(&mut self) -> Result<(), AudioError> {
         match self.stream.play() {
             Ok(_) => Ok(()),
@@ -107,8 +95,9 @@ impl AudioStreamManager {
         }
     }
 
+    /// Seeks to `time` in seconds.
     pub fn set_time(&mut self, time: f32) -> Result<(), AudioError> {
-        let sample_index = self.get_sample_index_from_time(time as f32);
+        let sample_index = self.get_sample_index_from_time(time);
         match self.samples.lock() {
             Ok(mut samples) => samples.position = sample_index,
             Err(poisoned) => {
@@ -121,28 +110,25 @@ impl AudioStreamManager {
         Ok(())
     }
 
-    pub fn skip_time(&mut self, time_to_skip: i64) -> Result<(), AudioError> {
-        let current_time = self.get_time().ok().ok_or(AudioError::StreamError)? as f32;
-        let new_time = current_time + time_to_skip as f32;
-        self.set_time(new_time)
-    }
-
+    /// Index of the first sample of the frame at `time`, so channels stay in step.
     fn get_sample_index_from_time(&self, time: f32) -> usize {
-        (time* self.sample_rate as f32) as usize
+        (time.max(0.0) * self.sample_rate as f32) as usize * self.channels as usize
     }
 
     fn get_time_from_sample_index(&self, sample_index: usize) -> f32 {
-        sample_index as f32 / self.sample_rate as f32
+        (sample_index / self.channels as usize) as f32 / self.sample_rate as f32
     }
 
+    /// Playback position in seconds.
     pub fn get_time(&self) -> Result<f32, AudioError> {
         let samples = self.samples.lock().ok().ok_or(AudioError::StreamError)?;
         Ok(self.get_time_from_sample_index(samples.position))
     }
 
-    pub fn current_sample_index(&self) -> Result<usize, AudioError> {
+    /// Whether every sample has been played.
+    pub fn is_finished(&self) -> Result<bool, AudioError> {
         let samples = self.samples.lock().ok().ok_or(AudioError::StreamError)?;
-        Ok(samples.position)
+        Ok(samples.position >= samples.samples.len())
     }
 }
 
@@ -171,7 +157,7 @@ impl Error for AudioError {
     }
 }
 
-fn upmix_mono_to_stereo(mono: &Vec<f32>) -> Vec<f32> {
+fn upmix_mono_to_stereo(mono: &[f32]) -> Vec<f32> {
     let mut stereo = Vec::new();
     for sample in mono.iter() {
         stereo.push(*sample);
@@ -194,7 +180,7 @@ fn fill_buffer<T>(
     data: &mut [T],
     samples_track: &mut AudioBufferTracker,
     convert_fn: impl Fn(f32) -> T
-) -> ()
+)
 {
     let samples = &mut samples_track.samples;
     let position = &mut samples_track.position;
@@ -217,95 +203,95 @@ fn build_stream(
 ) -> Result<cpal::Stream, AudioError>
 {
     let error_fn = |err| eprintln!("an error occurred on stream: {}", err);
-    let samples_clone = Arc::clone(&sample_track);
+    let samples_clone = Arc::clone(sample_track);
 
     let stream = match sample_format {
         SampleFormat::I16 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * i16::MAX as f32) as i16);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * i16::MAX as f32) as i16);
             },
             error_fn,
             None
         ),
         SampleFormat::U16 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [u16], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * u16::MAX as f32) as u16);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * u16::MAX as f32) as u16);
             },
             error_fn,
             None
         ),
         SampleFormat::F32 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| sample);
+                fill_buffer(data, &mut samples_and_pos, |sample| sample);
             },
             error_fn,
             None
         ),
         SampleFormat::I8 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [i8], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * i8::MAX as f32) as i8);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * i8::MAX as f32) as i8);
             },
             error_fn,
             None
         ),
         SampleFormat::U8 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [u8], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * u8::MAX as f32) as u8);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * u8::MAX as f32) as u8);
             },
             error_fn,
             None
         ),
         SampleFormat::F64 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [f64], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| sample as f64);
+                fill_buffer(data, &mut samples_and_pos, |sample| sample as f64);
             },
             error_fn,
             None
         ),
         SampleFormat::I32 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [i32], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * i32::MAX as f32) as i32);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * i32::MAX as f32) as i32);
             },
             error_fn,
             None
         ),
         SampleFormat::U32 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [u32], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * u32::MAX as f32) as u32);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * u32::MAX as f32) as u32);
             },
             error_fn,
             None
         ),
         SampleFormat::I64 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [i64], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * i64::MAX as f32) as i64);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * i64::MAX as f32) as i64);
             },
             error_fn,
             None
         ),
         SampleFormat::U64 => device.build_output_stream(
-            &audio_format,
+            audio_format,
             move |data: &mut [u64], _: &cpal::OutputCallbackInfo| {
                 let mut samples_and_pos = samples_clone.lock().unwrap();
-                fill_buffer(data, &mut *samples_and_pos, |sample| (sample * u64::MAX as f32) as u64);
+                fill_buffer(data, &mut samples_and_pos, |sample| (sample * u64::MAX as f32) as u64);
             },
             error_fn,
             None
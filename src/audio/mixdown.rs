@@ -0,0 +1,149 @@
+use creak::Decoder;
+use super::audio_manager::AudioError;
+
+/// Decoded audio held in memory as interleaved f32 samples, for offline mixing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioClip {
+    pub samples: Vec<f32>,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl AudioClip {
+    pub fn from_file(path: &str) -> Result<AudioClip, AudioError> {
+        let decoder = Decoder::open(path).map_err(|_| AudioError::FileError)?;
+        let channels = decoder.info().channels() as u16;
+        let sample_rate = decoder.info().sample_rate();
+        let samples = decoder
+            .into_samples()
+            .map_err(|_| AudioError::FileError)?
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| AudioError::FileError)?;
+        Ok(AudioClip { samples, channels, sample_rate })
+    }
+
+    pub fn silence(duration_ms: f64, channels: u16, sample_rate: u32) -> AudioClip {
+        let frames = (duration_ms / 1000.0 * sample_rate as f64).ceil() as usize;
+        AudioClip {
+            samples: vec![0.0; frames * channels as usize],
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Short decaying tone, used when the skin has no hit sound.
+    pub fn click(sample_rate: u32) -> AudioClip {
+        let frames = sample_rate as usize * 40 / 1000;
+        let samples = (0..frames)
+            .map(|frame| {
+                let t = frame as f32 / sample_rate as f32;
+                (t * 2200.0 * std::f32::consts::TAU).sin() * (-t * 120.0).exp() * 0.5
+            })
+            .collect();
+        AudioClip { samples, channels: 1, sample_rate }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration_ms(&self) -> f64 {
+        self.frames() as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    /// The clip with `channels` channels at `sample_rate`. Mono is spread to every channel,
+    /// anything else is mapped channel by channel; resampling is linear.
+    pub fn converted(&self, channels: u16, sample_rate: u32) -> AudioClip {
+        let source_channels = self.channels.max(1) as usize;
+        let source_frames = self.frames();
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let frames = (source_frames as f64 / ratio).floor() as usize;
+
+        let mut samples = Vec::with_capacity(frames * channels as usize);
+        for frame in 0..frames {
+            let position = frame as f64 * ratio;
+            let index = position.floor() as usize;
+            let fraction = (position - index as f64) as f32;
+            let next = (index + 1).min(source_frames - 1);
+            for channel in 0..channels as usize {
+                let source_channel = if source_channels == 1 { 0 } else { channel % source_channels };
+                let a = self.samples[index * source_channels + source_channel];
+                let b = self.samples[next * source_channels + source_channel];
+                samples.push(a + (b - a) * fraction);
+            }
+        }
+        AudioClip { samples, channels, sample_rate }
+    }
+
+    /// Adds `other` on top starting at `at_ms`, converting it to this clip's format first.
+    /// Whatever runs past the end of this clip is cut off.
+    pub fn mix(&mut self, other: &AudioClip, at_ms: f64, volume: f32) {
+        let other = if other.channels == self.channels && other.sample_rate == self.sample_rate {
+            std::borrow::Cow::Borrowed(other)
+        } else {
+            std::borrow::Cow::Owned(other.converted(self.channels, self.sample_rate))
+        };
+        let start_frame = (at_ms.max(0.0) / 1000.0 * self.sample_rate as f64).round() as usize;
+        let start = start_frame * self.channels as usize;
+        if start >= self.samples.len() {
+            return;
+        }
+        for (sample, added) in self.samples[start..].iter_mut().zip(&other.samples) {
+            *sample += added * volume;
+        }
+    }
+
+    /// Writes 16-bit PCM, clipping anything the mix pushed out of range.
+    pub fn write_wav(&self, path: &str) -> Result<(), AudioError> {
+        let spec = hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).map_err(|_| AudioError::FileError)?;
+        for sample in &self.samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            writer.write_sample(value).map_err(|_| AudioError::FileError)?;
+        }
+        writer.finalize().map_err(|_| AudioError::FileError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_and_convert() {
+        let mut track = AudioClip::silence(10.0, 2, 1000);
+        assert_eq!(track.samples.len(), 20);
+
+        let mono = AudioClip { samples: vec![0.5, 1.0, 0.5], channels: 1, sample_rate: 1000 };
+        track.mix(&mono, 2.0, 1.0);
+        track.mix(&mono, 8.0, 1.0);
+        track.mix(&mono, 3.0, 0.5);
+        assert_eq!(&track.samples[4..10], &[0.5, 0.5, 1.25, 1.25, 1.0, 1.0]);
+        // Cut off at the end of the track
+        assert_eq!(&track.samples[16..20], &[0.5, 0.5, 1.0, 1.0]);
+
+        let stereo = AudioClip { samples: vec![0.0, 1.0, 1.0, 0.0], channels: 2, sample_rate: 1000 };
+        let upsampled = stereo.converted(2, 2000);
+        assert_eq!(upsampled.samples, vec![0.0, 1.0, 0.5, 0.5, 1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(upsampled.duration_ms(), 2.0);
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let path = std::env::temp_dir().join(format!("mixdown_test_{}.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let clip = AudioClip { samples: vec![0.0, 0.5, -2.0, 1.0], channels: 2, sample_rate: 44100 };
+        clip.write_wav(path).unwrap();
+
+        let mut reader = hound::WavReader::open(path).unwrap();
+        assert_eq!((reader.spec().channels, reader.spec().sample_rate), (2, 44100));
+        let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).collect();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(samples, vec![0, 16384, -i16::MAX, i16::MAX]);
+    }
+}
@@ -1,3 +1,5 @@
 mod audio_manager;
+mod mixdown;
 
 pub use audio_manager::AudioStreamManager;
+pub use mixdown::AudioClip;
@@ -0,0 +1,128 @@
+use std::path::Path;
+use crate::atlas::AtlasRegion;
+use crate::osu::OsuMap;
+use crate::render_cache::{BindGroupLayoutId, RenderCache};
+use crate::sprite_batch::{Sprite, SpriteBatch, TextureId};
+use crate::texture::Texture;
+use crate::video::VideoPlayer;
+
+/// Size of an image scaled to cover the whole window while keeping its aspect ratio.
+pub fn cover_size(image: (f32, f32), window: (f32, f32)) -> (f32, f32) {
+    let scale = (window.0 / image.0).max(window.1 / image.1);
+    (image.0 * scale, image.1 * scale)
+}
+
+/// Region covering a whole texture.
+fn whole_texture(texture: &Texture) -> AtlasRegion {
+    let size = texture.texture.size();
+    AtlasRegion {
+        page: 0,
+        uv_min: [0.0, 0.0],
+        uv_max: [1.0, 1.0],
+        width: size.width,
+        height: size.height,
+    }
+}
+
+/// The beatmap's background behind the playfield: its video while there is a frame to show,
+/// its still image otherwise. Drawn into the post-processing background target, which dims and blurs it.
+pub struct Background {
+    batch: SpriteBatch,
+    image: Option<(Texture, TextureId)>,
+    video: Option<(VideoPlayer, TextureId)>,
+}
+
+impl Background {
+    /// `transform_bind_group_layout` is for a window pixel transform.
+    pub fn new(device: &wgpu::Device, cache: &mut RenderCache, format: wgpu::TextureFormat, transform_bind_group_layout: BindGroupLayoutId) -> Self {
+        Self {
+            // The background target isn't multisampled
+            batch: SpriteBatch::new(device, cache, format, 1, transform_bind_group_layout),
+            image: None,
+            video: None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.image.is_some() || self.video.is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.image = None;
+        self.video = None;
+        self.batch.clear_textures();
+    }
+
+    pub fn set_image(&mut self, device: &wgpu::Device, cache: &mut RenderCache, texture: Texture) {
+        let id = self.batch.register_texture(device, cache, &texture);
+        self.image = Some((texture, id));
+    }
+
+    pub fn set_video(&mut self, device: &wgpu::Device, cache: &mut RenderCache, video: VideoPlayer) {
+        let id = self.batch.register_texture(device, cache, &video.texture);
+        self.video = Some((video, id));
+    }
+
+    pub fn video_mut(&mut self) -> Option<&mut VideoPlayer> {
+        self.video.as_mut().map(|(video, _)| video)
+    }
+
+    /// Replaces the background with `map`'s image and video from `directory`. Whatever fails
+    /// to load is logged and left out.
+    pub fn load(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, cache: &mut RenderCache, directory: &Path, map: &OsuMap) {
+        self.clear();
+        if let Some(filename) = &map.background {
+            // Without mipmaps: it covers the window and gets blurred, so it's never drawn much smaller
+            let loaded = std::fs::read(directory.join(filename))
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Texture::from_bytes(device, queue, &bytes, "Background Texture"));
+            match loaded {
+                Ok(texture) => self.set_image(device, cache, texture),
+                Err(error) => log::warn!("Couldn't load background {}: {}", filename, error),
+            }
+        }
+        if let Some(video) = &map.video {
+            match VideoPlayer::open(device, &directory.join(&video.filename), video.start_time as f64) {
+                Ok(player) => self.set_video(device, cache, player),
+                Err(error) => log::warn!("Couldn't open video {}, using the still image: {}", video.filename, error),
+            }
+        }
+    }
+
+    /// Picks the video frame for `time` or the still image, scaled to cover a `width` x `height` window.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, time: f32, width: u32, height: u32) {
+        let mut shown = None;
+        if let Some((video, id)) = &mut self.video {
+            if video.update(queue, time as f64) {
+                shown = Some((*id, whole_texture(&video.texture)));
+            }
+        }
+        let shown = shown.or_else(|| self.image.as_ref().map(|(texture, id)| (*id, whole_texture(texture))));
+
+        if let Some((id, region)) = shown {
+            let window = (width as f32, height as f32);
+            let size = cover_size((region.width as f32, region.height as f32), window);
+            self.batch.push(Sprite::new(id, &region, (window.0 / 2.0, window.1 / 2.0), size));
+        }
+        self.batch.prepare(device, queue);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, cache: &'a RenderCache, transform_bind_group: &'a wgpu::BindGroup) {
+        self.batch.draw(render_pass, cache, transform_bind_group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cover_size() {
+        // Wider image: fits the height and overflows at the sides
+        let (width, height) = cover_size((1920.0, 1080.0), (800.0, 600.0));
+        assert!((width - 1066.67).abs() < 0.01 && height == 600.0, "{}x{}", width, height);
+        // Taller image: fits the width
+        assert_eq!(cover_size((1000.0, 1000.0), (1280.0, 720.0)), (1280.0, 1280.0));
+        assert_eq!(cover_size((640.0, 360.0), (1280.0, 720.0)), (1280.0, 720.0));
+    }
+}
@@ -0,0 +1,574 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::audio::AudioClip;
+use crate::cursor_trail::{CursorTrail, TrailSettings};
+use crate::headless::HeadlessRenderer;
+use crate::hit_circle::{circle_radius, CircleDrawable, CircleTiming};
+use crate::osu::{OsuMap, OsuObject};
+use crate::post_process::PostProcessSettings;
+use crate::playfield::{PLAYFIELD_HEIGHT, PLAYFIELD_WIDTH};
+use crate::renderer::{Hud, Scene};
+use crate::replay::{Keys, Mods, Replay, ReplayFrame, ReplayPlayer};
+use crate::skin::{normalize, Skin};
+use crate::slider::{SliderPath, SliderTiming};
+use crate::slider_renderer::SliderDrawable;
+use crate::spinner::SpinnerState;
+
+/// Rendered after the last object so it can fade out, in milliseconds.
+const LEAD_OUT_MS: f64 = 1000.0;
+/// How long autoplay holds a key for a circle, in milliseconds.
+const AUTOPLAY_HOLD_MS: i64 = 50;
+/// Autoplay's distance from a spinner's center, in osu!pixels.
+const AUTOPLAY_SPIN_RADIUS: f32 = 50.0;
+/// Time between autoplay frames while following a slider or spinning, in milliseconds.
+const AUTOPLAY_FRAME_MS: usize = 16;
+/// The ball is followed while the cursor is within this multiple of the circle radius.
+const FOLLOW_RADIUS_SCALE: f32 = 2.4;
+const HIT_SOUND: &str = "normal-hitnormal";
+
+/// Where rendered frames go.
+pub enum FrameOutput {
+    /// `frame_000000.png`, `frame_000001.png`, ... in this directory.
+    PngSequence(PathBuf),
+    /// Tightly packed RGBA8 frames back to back, e.g. stdout piped into
+    /// `ffmpeg -f rawvideo -pix_fmt rgba -s 1280x720 -r 60 -i - clip.mp4`.
+    Raw(Box<dyn Write>),
+}
+
+pub struct CaptureSettings {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub sample_count: u32,
+    pub post_process: PostProcessSettings,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        CaptureSettings {
+            width: 1280,
+            height: 720,
+            fps: 60,
+            sample_count: 4,
+            post_process: PostProcessSettings {
+                background_dim: 0.7,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Gameplay times of every frame from `start_ms` up to and including `end_ms`.
+pub fn frame_times(start_ms: f64, end_ms: f64, fps: u32) -> impl Iterator<Item = f64> {
+    let frame_ms = 1000.0 / fps.max(1) as f64;
+    // Nudged so 1000 / 60 * 60 still lands on the last frame
+    let count = ((end_ms - start_ms) / frame_ms + 1e-6).floor().max(-1.0) as i64 + 1;
+    (0..count).map(move |frame| start_ms + frame as f64 * frame_ms)
+}
+
+/// A replay that moves straight from object to object and hits each one on time.
+/// Holds are cut short where the next object starts, so the frames stay in time order
+/// and every object gets a fresh key press.
+pub fn autoplay(map: &OsuMap) -> Replay {
+    let mut replay = Replay::new("", "Autoplay", Mods::AUTOPLAY);
+    let center = (PLAYFIELD_WIDTH / 2.0, PLAYFIELD_HEIGHT / 2.0);
+    let circle_timing = CircleTiming::from_approach_rate(map.difficulty.approach_rate);
+    let mut push = |time: i64, (x, y): (f32, f32), keys: Keys| replay.frames.push(ReplayFrame { time, x, y, keys });
+
+    push(0, center, Keys::NONE);
+    let objects: Vec<&OsuObject> = map.objects.values().collect();
+    for (index, object) in objects.iter().enumerate() {
+        let start = object.time() as i64;
+        let next_start = objects.get(index + 1).map_or(i64::MAX, |next| next.time() as i64);
+        let release_at = |time: i64| time.min(next_start - 1).max(start);
+        let held = |time: &i64| *time < next_start;
+        match object {
+            OsuObject::Circle(circle) => {
+                push(start, (circle.x, circle.y), Keys::K1);
+                push(release_at(start + AUTOPLAY_HOLD_MS), (circle.x, circle.y), Keys::NONE);
+            }
+            OsuObject::Slider(slider) => {
+                let path = SliderPath::from_slider(slider);
+                let timing = SliderTiming::from_map(slider, map, &circle_timing);
+                let position_at = |time: i64| {
+                    let progress = timing.frame_at(time as f32).and_then(|frame| frame.ball_progress).unwrap_or(0.0);
+                    path.position_at(progress)
+                };
+                let end = timing.end_time() as i64;
+                for time in (start..=end).step_by(AUTOPLAY_FRAME_MS).take_while(held) {
+                    push(time, position_at(time), Keys::K1);
+                }
+                let release = release_at(end.max(start + AUTOPLAY_HOLD_MS));
+                push(release, position_at(release.min(end)), Keys::NONE);
+            }
+            OsuObject::Spinner(spinner) => {
+                let end = spinner.end_time as i64;
+                for time in (start..=end).step_by(AUTOPLAY_FRAME_MS).take_while(held) {
+                    let angle = (time - start) as f32 / 1000.0 * std::f32::consts::TAU * 5.0;
+                    let position = (center.0 + angle.cos() * AUTOPLAY_SPIN_RADIUS, center.1 + angle.sin() * AUTOPLAY_SPIN_RADIUS);
+                    push(time, position, Keys::K1);
+                }
+                push(release_at(end + 1), center, Keys::NONE);
+            }
+        }
+    }
+    replay
+}
+
+/// Times at which a key goes down, for placing hit sounds.
+pub fn press_times(replay: &Replay) -> Vec<i64> {
+    let mut previous = Keys::NONE;
+    let mut times = Vec::new();
+    for frame in &replay.frames {
+        if frame.keys.pressed_since(previous) {
+            times.push(frame.time);
+        }
+        previous = frame.keys;
+    }
+    times
+}
+
+/// Gameplay time span to render: from the first object appearing until the last one has faded.
+fn play_span(map: &OsuMap, replay: &Replay, timing: &CircleTiming) -> (f64, f64) {
+    let start = map.objects.values().map(|object| object.time()).min().unwrap_or(0) as f64 - timing.preempt as f64;
+    let last_object = map
+        .objects
+        .values()
+        .map(|object| match object {
+            OsuObject::Slider(slider) => SliderTiming::from_map(slider, map, timing).end_time() as u32,
+            OsuObject::Spinner(spinner) => spinner.end_time,
+            _ => object.time(),
+        })
+        .max()
+        .unwrap_or(0);
+    let end = (last_object as f64).max(replay.duration() as f64) + LEAD_OUT_MS;
+    (start.max(0.0), end)
+}
+
+/// Renders `replay` over `map` at a fixed timestep into `output`. Returns the number of frames.
+/// The background and video are looked up in `map_directory`.
+pub fn render_replay(
+    map: &OsuMap,
+    map_directory: &Path,
+    replay: Replay,
+    skin: Skin,
+    settings: &CaptureSettings,
+    output: &mut FrameOutput,
+) -> Result<usize, CaptureError> {
+    let mut headless = pollster::block_on(HeadlessRenderer::new(settings.width, settings.height, settings.sample_count, skin))
+        .map_err(|_| CaptureError::DeviceError)?;
+    let renderer = &mut headless.renderer;
+    renderer.post_process.settings = settings.post_process;
+    renderer.background.load(&renderer.device, &renderer.queue, &mut renderer.cache, map_directory, map);
+    if let Some(video) = renderer.background.video_mut() {
+        video.wait_for_frames = true;
+    }
+    if let FrameOutput::PngSequence(directory) = output {
+        std::fs::create_dir_all(directory).map_err(|_| CaptureError::FileError)?;
+    }
+
+    let timing = CircleTiming::from_approach_rate(map.difficulty.approach_rate);
+    let radius = circle_radius(map.difficulty.circle_size);
+    let (start, end) = play_span(map, &replay, &timing);
+    let combos = map.combos();
+    let mut spinners: Vec<SpinnerState> = map
+        .objects
+        .values()
+        .filter_map(|object| match object {
+            OsuObject::Spinner(spinner) => Some(SpinnerState::new(spinner, map.difficulty.overall_difficulty)),
+            _ => None,
+        })
+        .collect();
+    let sliders: Vec<(SliderPath, SliderTiming, [f32; 4])> = map
+        .objects
+        .values()
+        .zip(&combos)
+        .filter_map(|(object, combo)| match object {
+            OsuObject::Slider(slider) => Some((
+                SliderPath::from_slider(slider),
+                SliderTiming::from_map(slider, map, &timing),
+                normalize(renderer.skin.ini.combo_colour(combo.colour)),
+            )),
+            _ => None,
+        })
+        .collect();
+
+    let player = ReplayPlayer::new(replay);
+    let mut trail = CursorTrail::new(TrailSettings::for_skin(&headless.renderer.skin));
+    // The trail fades by wall-clock time, so give it one that follows gameplay time
+    let epoch = Instant::now();
+    let mut frames = 0;
+    for time in frame_times(start, end, settings.fps) {
+        let cursor = player.cursor_at(time as i64).unwrap_or((PLAYFIELD_WIDTH / 2.0, PLAYFIELD_HEIGHT / 2.0));
+        let holding = player.keys_at(time as i64) != Keys::NONE;
+        for spinner in &mut spinners {
+            spinner.update(time as f32, cursor, holding);
+        }
+        let now = epoch + Duration::from_secs_f64(time / 1000.0);
+        trail.push(now, cursor);
+        trail.prune(now);
+
+        let circles: Vec<CircleDrawable> = map
+            .objects
+            .values()
+            .zip(&combos)
+            .enumerate()
+            .filter_map(|(index, (object, combo))| {
+                let circle = match object {
+                    OsuObject::Circle(circle) => CircleDrawable::new(circle, index, time as f32, &timing, radius),
+                    OsuObject::Slider(slider) => CircleDrawable::new(&slider.head(), index, time as f32, &timing, radius),
+                    OsuObject::Spinner(_) => None,
+                };
+                circle.map(|drawable| CircleDrawable {
+                    combo_number: combo.number,
+                    combo_colour: combo.colour,
+                    ..drawable
+                })
+            })
+            .collect();
+        let slider_drawables: Vec<SliderDrawable> = sliders
+            .iter()
+            .filter_map(|(path, slider_timing, colour)| {
+                let frame = slider_timing.frame_at(time as f32)?;
+                let drawable = SliderDrawable::new(path, &frame, radius).with_track_colour(*colour);
+                let tracking = holding && drawable.ball.is_some_and(|ball| distance(ball, cursor) <= radius * FOLLOW_RADIUS_SCALE);
+                Some(drawable.with_tracking(tracking))
+            })
+            .collect();
+
+        let scene = Scene {
+            time: time as f32,
+            circles: &circles,
+            sliders: &slider_drawables,
+            spinners: &spinners,
+            cursor,
+            cursor_trail: &trail,
+            cursor_pressed: holding,
+            now,
+            hud: Hud::default(),
+            profile: None,
+        };
+        let frame = headless.render(&scene);
+        match output {
+            FrameOutput::PngSequence(directory) => {
+                let path = directory.join(format!("frame_{:06}.png", frames));
+                frame.save(path).map_err(|_| CaptureError::FileError)?;
+            }
+            FrameOutput::Raw(writer) => writer.write_all(frame.as_raw()).map_err(|_| CaptureError::FileError)?,
+        }
+        frames += 1;
+    }
+    if let FrameOutput::Raw(writer) = output {
+        writer.flush().map_err(|_| CaptureError::FileError)?;
+    }
+    log::info!("Rendered {} frames", frames);
+    Ok(frames)
+}
+
+fn distance((x1, y1): (f32, f32), (x2, y2): (f32, f32)) -> f32 {
+    ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt()
+}
+
+/// The song with a hit sound at every key press, trimmed to the rendered span.
+pub fn mix_audio(song: Option<&AudioClip>, hit_sound: &AudioClip, replay: &Replay, start_ms: f64, end_ms: f64) -> AudioClip {
+    let mut track = AudioClip::silence(end_ms - start_ms, 2, 44100);
+    if let Some(song) = song {
+        track = AudioClip::silence(end_ms - start_ms, song.channels, song.sample_rate);
+        let skip = (start_ms / 1000.0 * song.sample_rate as f64) as usize * song.channels as usize;
+        let song = AudioClip {
+            samples: song.samples.get(skip..).unwrap_or_default().to_vec(),
+            ..song.clone()
+        };
+        track.mix(&song, 0.0, 1.0);
+    }
+    for time in press_times(replay) {
+        track.mix(hit_sound, time as f64 - start_ms, 0.6);
+    }
+    track
+}
+
+/// Arguments of the `render` command.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureArgs {
+    pub map: String,
+    /// `None` for autoplay.
+    pub replay: Option<String>,
+    /// Directory for a PNG sequence, or "-" for raw frames on stdout.
+    pub output: String,
+    pub song: Option<String>,
+    pub wav: Option<String>,
+    pub skin: Option<String>,
+    pub fps: u32,
+    pub width: u32,
+    pub height: u32,
+    pub sample_count: u32,
+    /// Background dim and blur, from 0 to 1.
+    pub dim: f32,
+    pub blur: f32,
+}
+
+pub const USAGE: &str = "usage: render <map.osu> <replay.osr|autoplay> <output-dir|-> \
+[--fps N] [--size WxH] [--msaa N] [--dim PERCENT] [--blur PERCENT] [--skin DIR] [--song FILE] [--wav FILE]";
+
+impl CaptureArgs {
+    pub fn parse(args: &[String]) -> Result<CaptureArgs, CaptureError> {
+        let defaults = CaptureSettings::default();
+        let mut positional = Vec::new();
+        let mut parsed = CaptureArgs {
+            map: String::new(),
+            replay: None,
+            output: String::new(),
+            song: None,
+            wav: None,
+            skin: None,
+            fps: defaults.fps,
+            width: defaults.width,
+            height: defaults.height,
+            sample_count: defaults.sample_count,
+            dim: defaults.post_process.background_dim,
+            blur: defaults.post_process.background_blur,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or(CaptureError::UsageError);
+            match arg.as_str() {
+                "--fps" => parsed.fps = value()?.parse().map_err(|_| CaptureError::UsageError)?,
+                "--msaa" => parsed.sample_count = value()?.parse().map_err(|_| CaptureError::UsageError)?,
+                "--dim" | "--blur" => {
+                    let percent: f32 = value()?.parse().map_err(|_| CaptureError::UsageError)?;
+                    if !(0.0..=100.0).contains(&percent) {
+                        return Err(CaptureError::UsageError);
+                    }
+                    *if arg == "--dim" { &mut parsed.dim } else { &mut parsed.blur } = percent / 100.0;
+                }
+                "--song" => parsed.song = Some(value()?),
+                "--wav" => parsed.wav = Some(value()?),
+                "--skin" => parsed.skin = Some(value()?),
+                "--size" => {
+                    let size = value()?;
+                    let (width, height) = size.split_once('x').ok_or(CaptureError::UsageError)?;
+                    parsed.width = width.parse().map_err(|_| CaptureError::UsageError)?;
+                    parsed.height = height.parse().map_err(|_| CaptureError::UsageError)?;
+                }
+                _ if arg.starts_with("--") => return Err(CaptureError::UsageError),
+                _ => positional.push(arg.clone()),
+            }
+        }
+
+        let [map, replay, output] = <[String; 3]>::try_from(positional).map_err(|_| CaptureError::UsageError)?;
+        if parsed.fps == 0 || parsed.width == 0 || parsed.height == 0 {
+            return Err(CaptureError::UsageError);
+        }
+        parsed.map = map;
+        parsed.replay = (replay != "autoplay").then_some(replay);
+        parsed.output = output;
+        Ok(parsed)
+    }
+}
+
+/// Entry point of `wgpu_test_app render ...`.
+pub fn run(args: &[String]) -> Result<(), CaptureError> {
+    let args = CaptureArgs::parse(args)?;
+    let map = OsuMap::from_file(&args.map).map_err(|_| CaptureError::MapError)?;
+    let replay = match &args.replay {
+        Some(path) => Replay::from_file(path).map_err(|_| CaptureError::ReplayError)?,
+        None => autoplay(&map),
+    };
+    let skin = args.skin.as_deref().map(Skin::load_or_default).unwrap_or_else(Skin::new);
+    let settings = CaptureSettings {
+        width: args.width,
+        height: args.height,
+        fps: args.fps,
+        sample_count: args.sample_count,
+        post_process: PostProcessSettings {
+            background_dim: args.dim,
+            background_blur: args.blur,
+            ..Default::default()
+        },
+    };
+
+    if let Some(wav) = &args.wav {
+        let song = args.song.as_deref().map(AudioClip::from_file).transpose().map_err(|_| CaptureError::AudioError)?;
+        let hit_sound = args
+            .skin
+            .as_deref()
+            .and_then(|skin| ["wav", "ogg", "mp3"].iter().find_map(|extension| {
+                AudioClip::from_file(&format!("{}/{}.{}", skin, HIT_SOUND, extension)).ok()
+            }))
+            .unwrap_or_else(|| AudioClip::click(44100));
+        let (start, end) = play_span(&map, &replay, &CircleTiming::from_approach_rate(map.difficulty.approach_rate));
+        let track = mix_audio(song.as_ref(), &hit_sound, &replay, start, end);
+        track.write_wav(wav).map_err(|_| CaptureError::AudioError)?;
+        log::info!("Wrote {:.1}s of audio to {}", track.duration_ms() / 1000.0, wav);
+    }
+
+    let mut output = if args.output == "-" {
+        FrameOutput::Raw(Box::new(std::io::BufWriter::new(std::io::stdout().lock())))
+    } else {
+        FrameOutput::PngSequence(PathBuf::from(&args.output))
+    };
+    let map_directory = Path::new(&args.map).parent().unwrap_or(Path::new("."));
+    render_replay(&map, map_directory, replay, skin, &settings, &mut output)?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    UsageError,
+    MapError,
+    ReplayError,
+    DeviceError,
+    FileError,
+    AudioError,
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::UsageError => write!(f, "UsageError"),
+            CaptureError::MapError => write!(f, "MapError"),
+            CaptureError::ReplayError => write!(f, "ReplayError"),
+            CaptureError::DeviceError => write!(f, "DeviceError"),
+            CaptureError::FileError => write!(f, "FileError"),
+            CaptureError::AudioError => write!(f, "AudioError"),
+        }
+    }
+}
+
+impl Error for CaptureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osu::{OsuCircle, OsuSlider, OsuSpinner};
+
+    fn test_map() -> OsuMap {
+        let mut map = OsuMap::new();
+        map.objects.insert(1000, OsuObject::Circle(OsuCircle { x: 100.0, y: 100.0, time: 1000, new_combo: true, combo_skip: 0 }));
+        map.objects.insert(1500, OsuObject::Circle(OsuCircle { x: 300.0, y: 200.0, time: 1500, new_combo: false, combo_skip: 0 }));
+        map.objects.insert(2000, OsuObject::Spinner(OsuSpinner { time: 2000, end_time: 3000 }));
+        map
+    }
+
+    #[test]
+    fn test_frame_times() {
+        let times: Vec<f64> = frame_times(1000.0, 1100.0, 20).collect();
+        assert_eq!(times, vec![1000.0, 1050.0, 1100.0]);
+        assert_eq!(frame_times(0.0, 1000.0, 60).count(), 61);
+        assert_eq!(frame_times(10.0, 0.0, 60).count(), 0);
+    }
+
+    #[test]
+    fn test_autoplay_hits_objects() {
+        let replay = autoplay(&test_map());
+        let player = ReplayPlayer::new(replay.clone());
+        assert_eq!(player.cursor_at(1000), Some((100.0, 100.0)));
+        assert_eq!(player.keys_at(1000), Keys::K1);
+        assert_eq!(player.keys_at(1100), Keys::NONE);
+        assert_eq!(player.cursor_at(1500), Some((300.0, 200.0)));
+        // Spinning around the center, cutting corners between frames
+        let (x, y) = player.cursor_at(2500).unwrap();
+        let distance = ((x - PLAYFIELD_WIDTH / 2.0).powi(2) + (y - PLAYFIELD_HEIGHT / 2.0).powi(2)).sqrt();
+        assert!((distance - AUTOPLAY_SPIN_RADIUS).abs() < 3.0, "{}", distance);
+        assert_eq!(press_times(&replay), vec![1000, 1500, 2000]);
+
+        let (start, end) = play_span(&test_map(), &replay, &CircleTiming { preempt: 600.0, fade_in: 400.0 });
+        assert_eq!((start, end), (400.0, 3000.0 + 1.0 + LEAD_OUT_MS));
+    }
+
+    #[test]
+    fn test_autoplay_follows_sliders() {
+        let mut map = OsuMap::new();
+        // 100 osu!pixels at 1.4 * 100 pixels per 500ms beat, there and back
+        let slider = OsuSlider {
+            x: 0.0,
+            y: 0.0,
+            time: 1000,
+            curve_type: "L".to_string(),
+            curve_points: vec![(140.0, 0.0)],
+            repeat: 2,
+            pixel_length: 140.0,
+            new_combo: true,
+            combo_skip: 0,
+        };
+        map.objects.insert(1000, OsuObject::Slider(slider));
+        let replay = autoplay(&map);
+        let player = ReplayPlayer::new(replay.clone());
+        let (x, _) = player.cursor_at(1248).unwrap();
+        assert!((x - 70.0).abs() < 3.0, "{}", x);
+        assert_eq!(player.keys_at(1900), Keys::K1);
+        assert_eq!(player.keys_at(2100), Keys::NONE);
+
+        let (_, end) = play_span(&map, &replay, &CircleTiming { preempt: 600.0, fade_in: 400.0 });
+        assert_eq!(end, 2000.0 + LEAD_OUT_MS);
+    }
+
+    #[test]
+    fn test_autoplay_frames_stay_in_order() {
+        let mut map = OsuMap::new();
+        // A 30ms stream, shorter than a hold, into a slider the spinner starts in the middle of
+        for time in [1000, 1030, 1060, 1090] {
+            map.objects.insert(time, OsuObject::Circle(OsuCircle { x: 100.0, y: 100.0, time: time as u32, new_combo: false, combo_skip: 0 }));
+        }
+        let slider = OsuSlider {
+            x: 0.0,
+            y: 0.0,
+            time: 1120,
+            curve_type: "L".to_string(),
+            curve_points: vec![(140.0, 0.0)],
+            repeat: 2,
+            pixel_length: 140.0,
+            new_combo: true,
+            combo_skip: 0,
+        };
+        map.objects.insert(1120, OsuObject::Slider(slider));
+        map.objects.insert(1500, OsuObject::Spinner(OsuSpinner { time: 1500, end_time: 2500 }));
+        map.objects.insert(2200, OsuObject::Circle(OsuCircle { x: 300.0, y: 200.0, time: 2200, new_combo: true, combo_skip: 0 }));
+
+        let replay = autoplay(&map);
+        assert!(replay.frames.windows(2).all(|pair| pair[0].time <= pair[1].time));
+        assert_eq!(press_times(&replay), vec![1000, 1030, 1060, 1090, 1120, 1500, 2200]);
+
+        let player = ReplayPlayer::new(replay.clone());
+        assert_eq!(player.keys_at(1029), Keys::NONE);
+        assert_eq!(player.keys_at(1030), Keys::K1);
+        assert_eq!(player.cursor_at(2200), Some((300.0, 200.0)));
+        assert_eq!(player.keys_at(2260), Keys::NONE);
+    }
+
+    #[test]
+    fn test_mix_audio() {
+        let song = AudioClip { samples: vec![0.25; 100], channels: 1, sample_rate: 100 };
+        let hit_sound = AudioClip { samples: vec![0.5], channels: 1, sample_rate: 100 };
+        let mut replay = Replay::new("", "", Mods::NONE);
+        replay.frames.push(ReplayFrame { time: 300, x: 0.0, y: 0.0, keys: Keys::K1 });
+        // The track starts at 200ms into the song
+        let track = mix_audio(Some(&song), &hit_sound, &replay, 200.0, 500.0);
+        assert_eq!(track.samples.len(), 30);
+        assert_eq!(track.samples[9], 0.25);
+        assert_eq!(track.samples[10], 0.25 + 0.5 * 0.6);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+        let parsed = CaptureArgs::parse(&args("map.osu autoplay out --fps 30 --size 640x480 --wav out.wav --dim 50")).unwrap();
+        assert_eq!((parsed.dim, parsed.blur), (0.5, 0.0));
+        assert_eq!(parsed.replay, None);
+        assert_eq!((parsed.fps, parsed.width, parsed.height), (30, 640, 480));
+        assert_eq!(parsed.wav.as_deref(), Some("out.wav"));
+        assert_eq!(CaptureArgs::parse(&args("map.osu play.osr -")).unwrap().replay.as_deref(), Some("play.osr"));
+        assert!(CaptureArgs::parse(&args("map.osu play.osr")).is_err());
+        assert!(CaptureArgs::parse(&args("map.osu play.osr - --size 640")).is_err());
+        assert!(CaptureArgs::parse(&args("map.osu play.osr - --fps")).is_err());
+        assert!(CaptureArgs::parse(&args("map.osu play.osr - --blur 150")).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Instant;
+
+/// Drift (ms) above which the clock snaps to the audio position instead of easing towards it.
+const RESYNC_THRESHOLD_MS: f64 = 40.0;
+/// Fraction of the remaining drift corrected per audio sync.
+const DRIFT_CORRECTION_RATE: f64 = 0.1;
+
+/// Tracks the current position in the beatmap in milliseconds.
+/// The user offset is added on top so early/late hardware can be compensated.
+pub struct GameplayClock {
+    started_at: Option<Instant>,
+    elapsed_before_pause: f64,
+    drift_correction_ms: f64,
+    pub offset_ms: f64,
+}
+
+impl GameplayClock {
+    pub fn new() -> GameplayClock {
+        GameplayClock {
+            started_at: None,
+            elapsed_before_pause: 0.0,
+            drift_correction_ms: 0.0,
+            offset_ms: 0.0,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.start_at(Instant::now());
+    }
+
+    pub fn start_at(&mut self, instant: Instant) {
+        if self.started_at.is_none() {
+            self.started_at = Some(instant);
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.pause_at(Instant::now());
+    }
+
+    pub fn pause_at(&mut self, instant: Instant) {
+        if let Some(started_at) = self.started_at.take() {
+            self.elapsed_before_pause += signed_ms_between(started_at, instant);
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.is_running() {
+            self.pause();
+        } else {
+            self.start();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.started_at = None;
+        self.elapsed_before_pause = 0.0;
+        self.drift_correction_ms = 0.0;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    pub fn time_ms(&self) -> f64 {
+        self.time_at(Instant::now())
+    }
+
+    /// Gameplay time at an arbitrary instant, e.g. when an input event arrived.
+    /// Instants before the clock was started map to negative times.
+    pub fn time_at(&self, instant: Instant) -> f64 {
+        self.raw_time_at(instant) + self.offset_ms
+    }
+
+    /// Pulls the clock towards the audio stream's position.
+    /// The audio position only advances once per output buffer, so small drift is
+    /// eased out over several syncs instead of making the clock jump back and forth.
+    pub fn sync_to_audio(&mut self, audio_ms: f64, now: Instant) {
+        if !self.is_running() {
+            return;
+        }
+        let drift = audio_ms - self.raw_time_at(now);
+        if drift.abs() > RESYNC_THRESHOLD_MS {
+            self.drift_correction_ms += drift;
+        } else {
+            self.drift_correction_ms += drift * DRIFT_CORRECTION_RATE;
+        }
+    }
+
+    fn raw_time_at(&self, instant: Instant) -> f64 {
+        let running = self
+            .started_at
+            .map(|started_at| signed_ms_between(started_at, instant))
+            .unwrap_or(0.0);
+        self.elapsed_before_pause + running + self.drift_correction_ms
+    }
+}
+
+fn signed_ms_between(from: Instant, to: Instant) -> f64 {
+    match to.checked_duration_since(from) {
+        Some(duration) => duration.as_secs_f64() * 1000.0,
+        None => -from.duration_since(to).as_secs_f64() * 1000.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_time_at_input_instant() {
+        let start = Instant::now();
+        let mut clock = GameplayClock::new();
+        clock.start_at(start);
+        clock.offset_ms = 10.0;
+
+        assert!((clock.time_at(start + Duration::from_millis(250)) - 260.0).abs() < 1e-6);
+        assert!((clock.time_at(start) - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_time_at_while_paused() {
+        let start = Instant::now();
+        let mut clock = GameplayClock::new();
+        clock.start_at(start);
+        clock.pause_at(start + Duration::from_millis(100));
+
+        assert!((clock.time_at(start + Duration::from_millis(500)) - 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sync_to_audio() {
+        let start = Instant::now();
+        let now = start + Duration::from_millis(1000);
+        let mut clock = GameplayClock::new();
+        clock.start_at(start);
+
+        // Large drift snaps straight to the audio position
+        clock.sync_to_audio(1500.0, now);
+        assert!((clock.time_at(now) - 1500.0).abs() < 1e-6);
+
+        // Small drift is only partially corrected
+        clock.sync_to_audio(1510.0, now);
+        let time = clock.time_at(now);
+        assert!(time > 1500.0 && time < 1510.0);
+    }
+}
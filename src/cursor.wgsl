@@ -0,0 +1,39 @@
+// Maps osu!pixels to clip space, see Playfield::uniform
+struct PlayfieldUniform {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+};
+@group(0) @binding(0)
+var<uniform> playfield: PlayfieldUniform;
+
+const TRAIL_COLOUR: vec3<f32> = vec3<f32>(1.0, 0.85, 0.4);
+
+// Ribbon trail
+
+struct RibbonInput {
+    @location(0) position: vec2<f32>,
+    @location(1) alpha: f32,
+    @location(2) edge: f32,
+}
+
+struct RibbonOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) alpha: f32,
+    @location(1) edge: f32,
+};
+
+@vertex
+fn vs_ribbon(in: RibbonInput) -> RibbonOutput {
+    var out: RibbonOutput;
+    out.clip_position = vec4<f32>(in.position * playfield.scale + playfield.offset, 0.0, 1.0);
+    out.alpha = in.alpha;
+    out.edge = in.edge;
+    return out;
+}
+
+@fragment
+fn fs_ribbon(in: RibbonOutput) -> @location(0) vec4<f32> {
+    // Bright core fading out towards both edges
+    let alpha = in.alpha * (1.0 - in.edge * in.edge) * 0.8;
+    return vec4<f32>(TRAIL_COLOUR * alpha, alpha);
+}
@@ -0,0 +1,61 @@
+use std::time::Instant;
+use crate::cursor_trail::{ribbon_mesh, CursorTrail, TrailMode, TrailVertex};
+use crate::gpu::create_vertex_buffer;
+use crate::render_cache::{BindGroupLayoutId, PipelineDescriptor, PipelineId, RenderCache};
+
+/// Draws the continuous ribbon trail. The cursor and sprite trails are skin sprites.
+pub struct CursorRenderer {
+    ribbon_pipeline: PipelineId,
+    ribbon_vertices: Option<wgpu::Buffer>,
+    num_ribbon_vertices: u32,
+}
+
+impl CursorRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        cache: &mut RenderCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        playfield_bind_group_layout: BindGroupLayoutId,
+    ) -> Self {
+        let descriptor = PipelineDescriptor::new("cursor.wgsl", &[playfield_bind_group_layout], format)
+            .with_entry_points("vs_ribbon", "fs_ribbon")
+            .with_vertex_layouts(&[TrailVertex::desc()])
+            .with_sample_count(sample_count);
+
+        Self {
+            ribbon_pipeline: cache.pipeline_id(device, &descriptor),
+            ribbon_vertices: None,
+            num_ribbon_vertices: 0,
+        }
+    }
+
+    /// Builds the ribbon for this frame, if the trail is one. `cursor` is in osu!pixels.
+    pub fn prepare(&mut self, device: &wgpu::Device, trail: &CursorTrail, cursor: (f32, f32), now: Instant) {
+        let ribbon = match trail.settings.mode {
+            TrailMode::Ribbon => {
+                // The ribbon ends at the cursor even if the last sample is older than this frame
+                let points: Vec<((f32, f32), f32)> = trail
+                    .points()
+                    .iter()
+                    .map(|point| (point.position, trail.fade(point, now)))
+                    .chain(std::iter::once((cursor, 1.0)))
+                    .collect();
+                ribbon_mesh(&points, trail.settings.width)
+            }
+            TrailMode::None | TrailMode::Sprites => Vec::new(),
+        };
+
+        self.num_ribbon_vertices = ribbon.len() as u32;
+        self.ribbon_vertices = create_vertex_buffer(device, "Cursor Ribbon Vertex Buffer", &ribbon);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, cache: &'a RenderCache, playfield_bind_group: &'a wgpu::BindGroup) {
+        if let Some(vertices) = &self.ribbon_vertices {
+            render_pass.set_pipeline(cache.pipeline(self.ribbon_pipeline));
+            render_pass.set_bind_group(0, playfield_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertices.slice(..));
+            render_pass.draw(0..self.num_ribbon_vertices, 0..1);
+        }
+    }
+}
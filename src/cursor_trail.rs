@@ -0,0 +1,233 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::skin::Skin;
+
+/// Minimum time between two trail sprites, so the trail looks the same at any polling rate.
+const SPRITE_INTERVAL: Duration = Duration::from_micros(16_667);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrailMode {
+    None,
+    /// Separate fading cursor sprites, about one per frame.
+    Sprites,
+    /// A continuous ribbon, interpolated between cursor samples.
+    Ribbon,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrailSettings {
+    pub mode: TrailMode,
+    /// How long a trail point stays visible.
+    pub lifetime: Duration,
+    /// Distance between interpolated ribbon points, in osu!pixels.
+    pub spacing: f32,
+    /// Width of the ribbon at its head, in osu!pixels.
+    pub width: f32,
+}
+
+impl TrailSettings {
+    /// A ribbon for skins with a `cursormiddle`, like osu!'s long trail, and sprites otherwise.
+    pub fn for_skin(skin: &Skin) -> Self {
+        let mode = if skin.has_own("cursormiddle") { TrailMode::Ribbon } else { TrailMode::Sprites };
+        TrailSettings { mode, ..TrailSettings::default() }
+    }
+}
+
+impl Default for TrailSettings {
+    fn default() -> Self {
+        TrailSettings {
+            mode: TrailMode::Sprites,
+            lifetime: Duration::from_millis(150),
+            spacing: 2.0,
+            width: 16.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrailPoint {
+    pub position: (f32, f32),
+    pub at: Instant,
+}
+
+/// Recent cursor positions in osu!pixels, oldest first.
+pub struct CursorTrail {
+    pub settings: TrailSettings,
+    points: VecDeque<TrailPoint>,
+}
+
+impl CursorTrail {
+    pub fn new(settings: TrailSettings) -> Self {
+        CursorTrail {
+            settings,
+            points: VecDeque::new(),
+        }
+    }
+
+    /// Adds a cursor sample. Call this for every motion event, not once per frame.
+    pub fn push(&mut self, at: Instant, position: (f32, f32)) {
+        let last = match self.points.back() {
+            Some(last) => *last,
+            None => {
+                if self.settings.mode != TrailMode::None {
+                    self.points.push_back(TrailPoint { position, at });
+                }
+                return;
+            }
+        };
+
+        match self.settings.mode {
+            TrailMode::None => {}
+            TrailMode::Sprites => {
+                if at.saturating_duration_since(last.at) >= SPRITE_INTERVAL {
+                    self.points.push_back(TrailPoint { position, at });
+                }
+            }
+            TrailMode::Ribbon => {
+                let (dx, dy) = (position.0 - last.position.0, position.1 - last.position.1);
+                let distance = (dx * dx + dy * dy).sqrt();
+                let steps = (distance / self.settings.spacing.max(0.01)).ceil().max(1.0) as u32;
+                let elapsed = at.saturating_duration_since(last.at);
+                for step in 1..=steps {
+                    let t = step as f32 / steps as f32;
+                    self.points.push_back(TrailPoint {
+                        position: (last.position.0 + dx * t, last.position.1 + dy * t),
+                        at: last.at + elapsed.mul_f32(t),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Drops points older than the trail lifetime.
+    pub fn prune(&mut self, now: Instant) {
+        while let Some(point) = self.points.front() {
+            if now.saturating_duration_since(point.at) > self.settings.lifetime {
+                self.points.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn points(&self) -> &VecDeque<TrailPoint> {
+        &self.points
+    }
+
+    /// Opacity of `point` at `now`, 1 when fresh and 0 once it's `lifetime` old.
+    pub fn fade(&self, point: &TrailPoint, now: Instant) -> f32 {
+        let age = now.saturating_duration_since(point.at).as_secs_f32();
+        (1.0 - age / self.settings.lifetime.as_secs_f32()).clamp(0.0, 1.0)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TrailVertex {
+    pub position: [f32; 2],
+    pub alpha: f32,
+    /// -1 on one side of the ribbon, 1 on the other, for soft edges.
+    pub edge: f32,
+}
+
+impl TrailVertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+            wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32, 2 => Float32];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<TrailVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Triangle list for a ribbon through `points` (position, alpha), tapering with the alpha.
+pub fn ribbon_mesh(points: &[((f32, f32), f32)], width: f32) -> Vec<TrailVertex> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+
+    // Left and right edge of the ribbon at every point
+    let edges: Vec<(TrailVertex, TrailVertex)> = (0..points.len())
+        .map(|i| {
+            let before = points[i.saturating_sub(1)].0;
+            let after = points[(i + 1).min(points.len() - 1)].0;
+            let (dx, dy) = (after.0 - before.0, after.1 - before.1);
+            let length = (dx * dx + dy * dy).sqrt();
+            let normal = if length > 0.0 { (-dy / length, dx / length) } else { (0.0, 0.0) };
+
+            let ((x, y), alpha) = points[i];
+            let half_width = width / 2.0 * alpha;
+            (
+                TrailVertex { position: [x + normal.0 * half_width, y + normal.1 * half_width], alpha, edge: -1.0 },
+                TrailVertex { position: [x - normal.0 * half_width, y - normal.1 * half_width], alpha, edge: 1.0 },
+            )
+        })
+        .collect();
+
+    edges
+        .windows(2)
+        .flat_map(|pair| {
+            let ((a_left, a_right), (b_left, b_right)) = (pair[0], pair[1]);
+            [a_left, a_right, b_right, a_left, b_right, b_left]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trail(mode: TrailMode) -> CursorTrail {
+        CursorTrail::new(TrailSettings { mode, ..TrailSettings::default() })
+    }
+
+    #[test]
+    fn test_ribbon_is_interpolated() {
+        let mut trail = trail(TrailMode::Ribbon);
+        let start = Instant::now();
+        trail.push(start, (0.0, 0.0));
+        trail.push(start + Duration::from_millis(10), (10.0, 0.0));
+        // 2 osu!pixel spacing: the first sample plus 5 interpolated points
+        assert_eq!(trail.points().len(), 6);
+        let middle = trail.points()[3];
+        assert_eq!(middle.position, (6.0, 0.0));
+        assert_eq!(middle.at, start + Duration::from_millis(6));
+    }
+
+    #[test]
+    fn test_sprites_ignore_polling_rate() {
+        let mut trail = trail(TrailMode::Sprites);
+        let start = Instant::now();
+        // 1000 Hz mouse for 100 ms
+        for i in 0..100 {
+            trail.push(start + Duration::from_millis(i), (i as f32, 0.0));
+        }
+        assert_eq!(trail.points().len(), 6);
+    }
+
+    #[test]
+    fn test_prune_and_fade() {
+        let mut trail = trail(TrailMode::Ribbon);
+        let start = Instant::now();
+        trail.push(start, (0.0, 0.0));
+        trail.push(start + Duration::from_millis(100), (4.0, 0.0));
+        let now = start + Duration::from_millis(200);
+        trail.prune(now);
+        assert_eq!(trail.points().len(), 2);
+        let newest = *trail.points().back().unwrap();
+        assert!((trail.fade(&newest, now) - (1.0 - 100.0 / 150.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_ribbon_mesh() {
+        let points = [((0.0, 0.0), 0.5), ((10.0, 0.0), 1.0), ((20.0, 0.0), 1.0)];
+        let mesh = ribbon_mesh(&points, 10.0);
+        assert_eq!(mesh.len(), 12);
+        // Horizontal ribbon, so the edges are offset vertically by half the width
+        assert_eq!(mesh[0].position, [0.0, 2.5]);
+        assert_eq!(mesh[1].position, [0.0, -2.5]);
+        assert!(ribbon_mesh(&points[..1], 10.0).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+/// Draw layers, bottom to top. Everything in a layer covers everything in the layers below it,
+/// whichever renderer draws it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    /// Drawn by `SliderRenderer`.
+    SliderBody,
+    /// Drawn by `SpinnerRenderer`.
+    Spinner,
+    /// Hit circles, slider heads and tails, reverse arrows.
+    #[default]
+    HitObject,
+    ApproachCircle,
+    /// Drawn by `SliderRenderer`.
+    SliderBall,
+    Judgement,
+    /// Cursor and trail, drawn in the final cursor pass.
+    Cursor,
+    Hud,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 8] = [
+        Layer::SliderBody,
+        Layer::Spinner,
+        Layer::HitObject,
+        Layer::ApproachCircle,
+        Layer::SliderBall,
+        Layer::Judgement,
+        Layer::Cursor,
+        Layer::Hud,
+    ];
+}
+
+/// Sort key of a sprite; compared by layer, then depth, then part, lowest drawn first.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DrawOrder {
+    pub layer: Layer,
+    /// Position within the layer, see `DrawOrder::hit_object`.
+    pub depth: i32,
+    /// Position within one object, e.g. body, overlay, then number.
+    pub part: u8,
+}
+
+impl DrawOrder {
+    pub fn new(layer: Layer) -> DrawOrder {
+        DrawOrder { layer, depth: 0, part: 0 }
+    }
+
+    /// Order for `part` of the beatmap's `index`th hit object. Earlier objects are drawn on top
+    /// of later ones, as they have to be hit first.
+    pub fn hit_object(layer: Layer, index: usize, part: u8) -> DrawOrder {
+        DrawOrder {
+            layer,
+            depth: -(index.min(i32::MAX as usize) as i32),
+            part,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_object_order() {
+        let body = |index| DrawOrder::hit_object(Layer::HitObject, index, 0);
+        let number = |index| DrawOrder::hit_object(Layer::HitObject, index, 2);
+        let approach = |index| DrawOrder::hit_object(Layer::ApproachCircle, index, 0);
+
+        // The first object's body covers the second object's number
+        assert!(body(0) > number(1));
+        assert!(number(0) > body(0));
+        // Approach circles go over every body, and everything goes over slider bodies
+        assert!(approach(5) > number(0));
+        assert!(DrawOrder::new(Layer::SliderBody) < body(1000));
+        assert!(Layer::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
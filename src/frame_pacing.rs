@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Refresh rate assumed when the monitor doesn't report one.
+pub const DEFAULT_REFRESH_HZ: f64 = 60.0;
+/// Frames kept for frame-time statistics.
+const STATS_WINDOW: usize = 240;
+
+/// Present modes to try for a preferred one, best first. Fifo is always supported.
+fn fallbacks(preferred: wgpu::PresentMode) -> &'static [wgpu::PresentMode] {
+    use wgpu::PresentMode::*;
+    match preferred {
+        Immediate => &[Immediate, Mailbox, FifoRelaxed, Fifo],
+        Mailbox => &[Mailbox, Immediate, Fifo],
+        FifoRelaxed => &[FifoRelaxed, Fifo],
+        _ => &[Fifo],
+    }
+}
+
+/// `preferred` if the surface supports it, otherwise the closest supported mode in latency.
+pub fn select_present_mode(preferred: wgpu::PresentMode, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    fallbacks(preferred)
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+/// Cap on how often frames are started.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FrameLimit {
+    Unlimited,
+    /// A multiple of the monitor's refresh rate, to keep latency low without running flat out.
+    RefreshMultiple(u32),
+    /// Fixed frames per second.
+    Fixed(u32),
+}
+
+impl FrameLimit {
+    /// Time between frame starts, `None` if frames aren't limited.
+    pub fn interval(self, refresh_hz: f64) -> Option<Duration> {
+        let fps = match self {
+            FrameLimit::Unlimited => return None,
+            FrameLimit::RefreshMultiple(multiple) => refresh_hz * multiple.max(1) as f64,
+            FrameLimit::Fixed(fps) => fps.max(1) as f64,
+        };
+        Some(Duration::from_secs_f64(1.0 / fps))
+    }
+}
+
+/// Present mode and frame limit, chosen by the player to trade input latency against tearing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PacingSettings {
+    pub present_mode: wgpu::PresentMode,
+    pub frame_limit: FrameLimit,
+}
+
+impl Default for PacingSettings {
+    /// Presenting with Mailbox doesn't block the event loop, so input keeps being stamped
+    /// as it arrives; the limiter keeps frames from running flat out instead of vsync.
+    fn default() -> Self {
+        PacingSettings {
+            present_mode: wgpu::PresentMode::Mailbox,
+            frame_limit: FrameLimit::RefreshMultiple(2),
+        }
+    }
+}
+
+/// Decides when the next frame may start. Deadlines advance by whole intervals so the
+/// average rate stays on target, unless rendering falls a full frame behind.
+pub struct FrameLimiter {
+    interval: Option<Duration>,
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new(limit: FrameLimit, refresh_hz: f64) -> Self {
+        FrameLimiter {
+            interval: limit.interval(refresh_hz),
+            next_frame: None,
+        }
+    }
+
+    /// `None` if a frame should start at `now`, otherwise when to check again.
+    pub fn poll(&mut self, now: Instant) -> Option<Instant> {
+        let interval = self.interval?;
+        match self.next_frame {
+            Some(next_frame) if now < next_frame => Some(next_frame),
+            Some(next_frame) if now < next_frame + interval => {
+                self.next_frame = Some(next_frame + interval);
+                None
+            }
+            _ => {
+                self.next_frame = Some(now + interval);
+                None
+            }
+        }
+    }
+}
+
+/// Fixed-size window of samples in milliseconds, oldest dropped first.
+#[derive(Clone, Debug)]
+pub struct RollingStats {
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl RollingStats {
+    pub fn new(capacity: usize) -> Self {
+        RollingStats {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, sample: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.samples.iter().copied().reduce(f64::min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.samples.iter().copied().reduce(f64::max)
+    }
+
+    pub fn average(&self) -> Option<f64> {
+        (!self.samples.is_empty()).then(|| self.samples.iter().sum::<f64>() / self.samples.len() as f64)
+    }
+
+    /// Nearest-rank percentile, `percent` from 0 to 100.
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (percent.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1)])
+    }
+}
+
+/// Times between presented frames.
+pub struct FrameStats {
+    pub frame_times: RollingStats,
+    last_frame: Option<Instant>,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        FrameStats {
+            frame_times: RollingStats::new(STATS_WINDOW),
+            last_frame: None,
+        }
+    }
+
+    pub fn record(&mut self, now: Instant) {
+        if let Some(last_frame) = self.last_frame {
+            self.frame_times.push((now - last_frame).as_secs_f64() * 1000.0);
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// Average frames per second over the window.
+    pub fn fps(&self) -> Option<f64> {
+        self.frame_times.average().filter(|average| *average > 0.0).map(|average| 1000.0 / average)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::PresentMode;
+
+    #[test]
+    fn test_select_present_mode() {
+        let supported = [PresentMode::Fifo, PresentMode::Immediate];
+        assert_eq!(select_present_mode(PresentMode::Immediate, &supported), PresentMode::Immediate);
+        assert_eq!(select_present_mode(PresentMode::Mailbox, &supported), PresentMode::Immediate);
+        assert_eq!(select_present_mode(PresentMode::Mailbox, &[PresentMode::Fifo]), PresentMode::Fifo);
+        assert_eq!(select_present_mode(PresentMode::Fifo, &supported), PresentMode::Fifo);
+        assert_eq!(select_present_mode(PresentMode::Immediate, &[]), PresentMode::Fifo);
+    }
+
+    #[test]
+    fn test_frame_limit_interval() {
+        assert_eq!(FrameLimit::Unlimited.interval(60.0), None);
+        assert_eq!(FrameLimit::Fixed(250).interval(60.0), Some(Duration::from_millis(4)));
+        assert_eq!(FrameLimit::RefreshMultiple(2).interval(125.0), Some(Duration::from_millis(4)));
+        assert_eq!(FrameLimit::RefreshMultiple(0).interval(100.0), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_frame_limiter() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut limiter = FrameLimiter::new(FrameLimit::Fixed(100), DEFAULT_REFRESH_HZ);
+        assert_eq!(limiter.poll(ms(0)), None);
+        assert_eq!(limiter.poll(ms(4)), Some(ms(10)));
+        // A slightly late frame doesn't push the following ones back
+        assert_eq!(limiter.poll(ms(13)), None);
+        assert_eq!(limiter.poll(ms(15)), Some(ms(20)));
+        // Falling a whole frame behind starts over from now
+        assert_eq!(limiter.poll(ms(45)), None);
+        assert_eq!(limiter.poll(ms(50)), Some(ms(55)));
+
+        let mut unlimited = FrameLimiter::new(FrameLimit::Unlimited, DEFAULT_REFRESH_HZ);
+        assert_eq!(unlimited.poll(ms(0)), None);
+        assert_eq!(unlimited.poll(ms(0)), None);
+    }
+
+    #[test]
+    fn test_rolling_stats() {
+        let mut stats = RollingStats::new(4);
+        assert_eq!(stats.average(), None);
+        for sample in [9.0, 1.0, 2.0, 3.0, 4.0] {
+            stats.push(sample);
+        }
+        assert_eq!(stats.len(), 4);
+        assert_eq!((stats.min(), stats.max(), stats.average()), (Some(1.0), Some(4.0), Some(2.5)));
+        assert_eq!(stats.percentile(50.0), Some(2.0));
+        assert_eq!(stats.percentile(99.0), Some(4.0));
+        assert_eq!(stats.percentile(0.0), Some(1.0));
+
+        let start = Instant::now();
+        let mut frames = FrameStats::new();
+        for frame in 0..5 {
+            frames.record(start + Duration::from_millis(frame * 4));
+        }
+        assert_eq!(frames.frame_times.len(), 4);
+        assert!((frames.fps().unwrap() - 250.0).abs() < 1e-6);
+    }
+}
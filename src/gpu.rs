@@ -0,0 +1,181 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use wgpu::util::DeviceExt;
+
+/// Adapter, device and queue for drawing to `surface`.
+pub async fn request_device(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), GpuError> {
+    let adapter = instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: Some(surface),
+            force_fallback_adapter: false,
+        },
+    ).await.ok_or(GpuError::NoAdapter)?;
+    log::info!("Rendering with {:?}", adapter.get_info());
+
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            // Only used to time passes, so it's fine to go without
+            features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+            // WebGL doesn't support all of wgpu's features, so if
+            // we're building for the web we'll have to disable some.
+            limits: if cfg!(target_arch = "wasm32") {
+                wgpu::Limits::downlevel_webgl2_defaults()
+            } else {
+                wgpu::Limits::default()
+            },
+            label: None,
+        },
+        None, // Trace path
+    ).await.map_err(|_| GpuError::DeviceError)?;
+    Ok((adapter, device, queue))
+}
+
+type DeviceResult = Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), GpuError>;
+
+/// A device being requested without blocking the event loop, which the web doesn't allow.
+pub struct DeviceRequest(Pin<Box<dyn Future<Output = DeviceResult>>>);
+
+impl DeviceRequest {
+    pub fn new(instance: Arc<wgpu::Instance>, surface: Arc<wgpu::Surface>) -> Self {
+        DeviceRequest(Box::pin(async move { request_device(&instance, &surface).await }))
+    }
+
+    /// Native requests are ready on the first poll. On the web they wait on the browser,
+    /// so this is polled again every frame until it is.
+    pub fn poll(&mut self) -> Poll<DeviceResult> {
+        self.0.as_mut().poll(&mut Context::from_waker(Waker::noop()))
+    }
+}
+
+/// Vertex buffer holding `contents`, `None` when there is nothing to draw.
+pub fn create_vertex_buffer<T: bytemuck::Pod>(device: &wgpu::Device, label: &str, contents: &[T]) -> Option<wgpu::Buffer> {
+    if contents.is_empty() {
+        return None;
+    }
+    Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(contents),
+        usage: wgpu::BufferUsages::VERTEX,
+    }))
+}
+
+/// Surface failures in a row after which the device is assumed lost.
+const MAX_SURFACE_FAILURES: u32 = 3;
+
+/// Set once the device runs out of memory, or once the surface keeps reporting itself lost or
+/// outdated after being reconfigured. wgpu 0.18 has no device lost callback, so the surface is
+/// how a lost device shows itself. Other uncaptured errors are logged instead of panicking,
+/// which is wgpu's default.
+#[derive(Debug, Default)]
+pub struct DeviceLoss {
+    lost: Arc<AtomicBool>,
+    /// Frames in a row the surface failed to give out a texture.
+    surface_failures: u32,
+}
+
+impl DeviceLoss {
+    pub fn watch(device: &wgpu::Device) -> Self {
+        let loss = DeviceLoss::default();
+        let lost = loss.lost.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            if let wgpu::Error::OutOfMemory { .. } = error {
+                lost.store(true, Ordering::Relaxed);
+            }
+            log::error!("{}", error);
+        }));
+        loss
+    }
+
+    /// Counts a frame the surface was lost or outdated for. Returns `false` while reconfiguring
+    /// the surface is still worth a try, `true` once the device is treated as lost.
+    pub fn surface_failed(&mut self) -> bool {
+        self.surface_failures += 1;
+        if self.surface_failures > MAX_SURFACE_FAILURES {
+            self.lost.store(true, Ordering::Relaxed);
+        }
+        self.is_lost()
+    }
+
+    /// The surface gave out a texture again.
+    pub fn surface_ok(&mut self) {
+        self.surface_failures = 0;
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub enum GpuError {
+    /// The window can't be drawn to, or no adapter can present to it.
+    SurfaceError,
+    NoAdapter,
+    DeviceError,
+    /// The device ran out of memory for the surface's frames.
+    OutOfMemory,
+    /// Textures the renderer needs, such as the skin or font atlas, couldn't be created.
+    ResourceError,
+}
+
+impl GpuError {
+    /// What went wrong, for players rather than developers.
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            GpuError::SurfaceError => "The game window can't be drawn to. Try updating your graphics drivers.",
+            GpuError::NoAdapter => {
+                "No graphics adapter supporting Vulkan, Metal, DirectX 12 or OpenGL was found. Check that your graphics drivers are installed."
+            }
+            GpuError::DeviceError => "The graphics adapter couldn't be opened. It may not meet the minimum requirements, or its drivers may need updating.",
+            GpuError::OutOfMemory => "The graphics adapter ran out of memory. Try closing other programs or lowering the window size.",
+            GpuError::ResourceError => "The game's textures couldn't be created. Try a different skin, or updating your graphics drivers.",
+        }
+    }
+}
+
+impl Display for GpuError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GpuError::SurfaceError => write!(f, "SurfaceError"),
+            GpuError::NoAdapter => write!(f, "NoAdapter"),
+            GpuError::DeviceError => write!(f, "DeviceError"),
+            GpuError::OutOfMemory => write!(f, "OutOfMemory"),
+            GpuError::ResourceError => write!(f, "ResourceError"),
+        }
+    }
+}
+
+impl Error for GpuError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_surface_loss_loses_device() {
+        let mut loss = DeviceLoss::default();
+        for _ in 0..MAX_SURFACE_FAILURES {
+            assert!(!loss.surface_failed());
+        }
+        // A frame getting through starts the count over
+        loss.surface_ok();
+        for _ in 0..MAX_SURFACE_FAILURES {
+            assert!(!loss.surface_failed());
+        }
+        assert!(loss.surface_failed());
+        assert!(loss.is_lost());
+    }
+}
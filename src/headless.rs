@@ -0,0 +1,387 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use image::RgbaImage;
+use crate::renderer::{Renderer, Scene, TargetDescriptor};
+use crate::skin::Skin;
+
+/// Offscreen frames are RGBA8 in sRGB, so the bytes read back are ready to save as PNG.
+pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// A renderer drawing into an offscreen texture instead of a window, for frame capture and tests.
+pub struct HeadlessRenderer {
+    pub renderer: Renderer,
+    target: wgpu::Texture,
+    view: wgpu::TextureView,
+    readback: wgpu::Buffer,
+}
+
+impl HeadlessRenderer {
+    /// Prefers the software fallback adapter, which renders the same everywhere, and takes
+    /// whatever adapter there is otherwise.
+    pub async fn new(width: u32, height: u32, sample_count: u32, skin: Skin) -> Result<Self, HeadlessError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let mut adapter = None;
+        for force_fallback_adapter in [true, false] {
+            adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            }).await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.ok_or(HeadlessError::NoAdapter)?;
+        log::info!("Rendering offscreen with {:?}", adapter.get_info());
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                // Software adapters don't always reach the defaults
+                limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                label: None,
+            },
+            None,
+        ).await.map_err(|_| HeadlessError::DeviceError)?;
+
+        let mut renderer = Renderer::new(&adapter, device, queue, TargetDescriptor { format: HEADLESS_FORMAT, width, height, sample_count }, skin)
+            .map_err(|_| HeadlessError::DeviceError)?;
+        // Frames of a capture should all be drawn with the same shaders
+        renderer.hot_reload_shaders = false;
+        let (target, view, readback) = Self::create_target(&renderer.device, width, height);
+        Ok(HeadlessRenderer { renderer, target, view, readback })
+    }
+
+    fn create_target(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::Texture, wgpu::TextureView, wgpu::Buffer) {
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HEADLESS_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Headless Readback Buffer"),
+            size: (padded_bytes_per_row(width) * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        (target, view, readback)
+    }
+
+    /// Renders `scene` and reads the frame back, blocking until the GPU is done.
+    pub fn render(&mut self, scene: &Scene) -> RgbaImage {
+        self.renderer.render(&self.view, scene);
+
+        let (width, height) = (self.renderer.width, self.renderer.height);
+        let padded_row = padded_bytes_per_row(width);
+        let mut encoder = self.renderer.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.target,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.renderer.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = self.readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        self.renderer.device.poll(wgpu::Maintain::Wait);
+        let pixels = {
+            let data = slice.get_mapped_range();
+            unpad_rows(&data, width * 4, padded_row)
+        };
+        self.readback.unmap();
+        RgbaImage::from_raw(width, height, pixels).expect("readback holds a whole frame")
+    }
+}
+
+/// Texture copies need rows aligned to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(alignment) * alignment
+}
+
+fn unpad_rows(data: &[u8], row: u32, padded_row: u32) -> Vec<u8> {
+    data.chunks(padded_row as usize)
+        .flat_map(|chunk| &chunk[..row as usize])
+        .copied()
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    NoAdapter,
+    DeviceError,
+}
+
+impl Display for HeadlessError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadlessError::NoAdapter => write!(f, "NoAdapter"),
+            HeadlessError::DeviceError => write!(f, "DeviceError"),
+        }
+    }
+}
+
+impl Error for HeadlessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use image::Rgba;
+    use crate::cursor_trail::{CursorTrail, TrailSettings};
+    use crate::frame_pacing::RollingStats;
+    use crate::hit_circle::CircleDrawable;
+    use crate::profiler::{Profile, Timings};
+    use crate::renderer::Hud;
+    use crate::slider_renderer::SliderDrawable;
+    use crate::sprite_batch::BlendMode;
+
+    impl HeadlessRenderer {
+        fn resize(&mut self, width: u32, height: u32) {
+            self.renderer.resize(width, height);
+            (self.target, self.view, self.readback) = Self::create_target(&self.renderer.device, width, height);
+        }
+    }
+
+    /// How far apart two images are.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    struct ImageDiff {
+        /// Pixels with any channel off by more than the tolerance.
+        differing_pixels: usize,
+        /// Largest difference of any channel.
+        max_difference: u8,
+    }
+
+    /// Compares two frames channel by channel. `None` if their sizes differ.
+    fn diff_images(expected: &RgbaImage, actual: &RgbaImage, tolerance: u8) -> Option<ImageDiff> {
+        if expected.dimensions() != actual.dimensions() {
+            return None;
+        }
+        let mut diff = ImageDiff { differing_pixels: 0, max_difference: 0 };
+        for (a, b) in expected.pixels().zip(actual.pixels()) {
+            let difference = a.0.iter().zip(b.0.iter()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+            diff.max_difference = diff.max_difference.max(difference);
+            if difference > tolerance {
+                diff.differing_pixels += 1;
+            }
+        }
+        Some(diff)
+    }
+
+    /// Nothing but the cursor off screen at time 0, for tests to fill in.
+    fn scene(trail: &CursorTrail) -> Scene<'_> {
+        Scene {
+            time: 0.0,
+            circles: &[],
+            sliders: &[],
+            spinners: &[],
+            cursor: (-100.0, -100.0),
+            cursor_trail: trail,
+            cursor_pressed: false,
+            now: Instant::now(),
+            hud: Hud::default(),
+            profile: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_images() {
+        let a = RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+        let mut b = a.clone();
+        b.put_pixel(1, 1, Rgba([12, 20, 30, 255]));
+        b.put_pixel(2, 2, Rgba([10, 20, 90, 255]));
+        assert_eq!(diff_images(&a, &b, 2), Some(ImageDiff { differing_pixels: 1, max_difference: 60 }));
+        assert_eq!(diff_images(&a, &a, 0), Some(ImageDiff { differing_pixels: 0, max_difference: 0 }));
+        assert_eq!(diff_images(&a, &RgbaImage::new(4, 3), 0), None);
+        assert_eq!(unpad_rows(&[1, 2, 0, 0, 3, 4, 0, 0], 2, 4), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_headless_playfield_frame() {
+        let Ok(mut headless) = pollster::block_on(HeadlessRenderer::new(320, 240, 4, Skin::new())) else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+        let trail = CursorTrail::new(TrailSettings::default());
+        let circle = |time: f32| CircleDrawable {
+            position: (256.0, 192.0),
+            radius: 64.0,
+            index: 0,
+            combo_number: 1,
+            combo_colour: 0,
+            alpha: 1.0,
+            approach_scale: Some(1.0 + 3.0 * (1.0 - time / 1000.0)),
+        };
+        let circles = [circle(500.0), circle(900.0)];
+        let scene = |time| Scene {
+            time,
+            circles: if time < 900.0 { &circles[..1] } else { &circles[1..] },
+            ..scene(&trail)
+        };
+
+        let frame = headless.render(&scene(500.0));
+        assert_eq!(frame.dimensions(), (320, 240));
+        // Black background, with the circle's combo colour in the middle
+        assert_eq!(frame.get_pixel(60, 120), &Rgba([0, 0, 0, 255]));
+        let center = frame.get_pixel(160, 130);
+        assert!(center[0] > 100 && center[1] > 100, "{:?}", center);
+
+        // Frames only depend on the scene
+        assert_eq!(diff_images(&frame, &headless.render(&scene(500.0)), 0).unwrap().differing_pixels, 0);
+        // The approach circle shrinks over time
+        assert!(diff_images(&frame, &headless.render(&scene(900.0)), 8).unwrap().differing_pixels > 100);
+
+        headless.resize(160, 120);
+        assert_eq!(headless.render(&scene(500.0)).dimensions(), (160, 120));
+    }
+
+    #[test]
+    fn test_headless_slider_body_below_circle() {
+        let Ok(mut headless) = pollster::block_on(HeadlessRenderer::new(320, 240, 1, Skin::new())) else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+        let trail = CursorTrail::new(TrailSettings::default());
+        let circle = CircleDrawable {
+            position: (256.0, 192.0),
+            radius: 64.0,
+            index: 0,
+            combo_number: 1,
+            combo_colour: 0,
+            alpha: 1.0,
+            approach_scale: None,
+        };
+        let slider = SliderDrawable {
+            points: vec![(56.0, 192.0), (456.0, 192.0)],
+            radius: 64.0,
+            alpha: 1.0,
+            ball: None,
+            track_colour: Some([1.0, 0.0, 0.0, 1.0]),
+            tracking: false,
+        };
+        let scene = |circles, sliders| Scene { circles, sliders, ..scene(&trail) };
+
+        let circle_only = headless.render(&scene(std::slice::from_ref(&circle), &[]));
+        let slider_only = headless.render(&scene(&[], std::slice::from_ref(&slider)));
+        let both = headless.render(&scene(std::slice::from_ref(&circle), std::slice::from_ref(&slider)));
+        assert_ne!(slider_only.get_pixel(160, 120), circle_only.get_pixel(160, 120));
+        // The circle covers the body in the middle, and the body shows past the circle
+        assert_eq!(both.get_pixel(160, 120), circle_only.get_pixel(160, 120));
+        assert_eq!(both.get_pixel(100, 120), slider_only.get_pixel(100, 120));
+        assert_ne!(both.get_pixel(100, 120), circle_only.get_pixel(100, 120));
+    }
+
+    #[test]
+    fn test_headless_post_processing() {
+        let Ok(mut headless) = pollster::block_on(HeadlessRenderer::new(320, 240, 1, Skin::new())) else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+        let trail = CursorTrail::new(TrailSettings::default());
+        let scene = scene(&trail);
+        let render = |headless: &mut HeadlessRenderer, additive: bool| {
+            if additive {
+                let renderer = &mut headless.renderer;
+                let sprite = renderer.skin_sprite("hitcircle", 0, (256.0, 192.0), 32.0).unwrap();
+                renderer.sprite_batch.push(sprite.with_blend(BlendMode::Additive));
+            }
+            headless.render(&scene)
+        };
+
+        headless.renderer.clear_color = wgpu::Color::WHITE;
+        assert_eq!(render(&mut headless, false).get_pixel(20, 120), &Rgba([255, 255, 255, 255]));
+        // Half the linear intensity, in sRGB
+        headless.renderer.post_process.settings.background_dim = 0.5;
+        let dimmed = render(&mut headless, false).get_pixel(20, 120)[0];
+        assert!((186..=190).contains(&dimmed), "{}", dimmed);
+
+        // Bloom spreads additive sprites past their edges
+        headless.renderer.clear_color = wgpu::Color::BLACK;
+        headless.renderer.post_process.settings.background_dim = 0.0;
+        let sharp = render(&mut headless, true);
+        headless.renderer.post_process.settings.bloom_intensity = 1.0;
+        let bloomed = render(&mut headless, true);
+        let diff = diff_images(&sharp, &bloomed, 4).unwrap();
+        assert!(diff.differing_pixels > 1000, "{:?}", diff);
+        assert_eq!(sharp.get_pixel(160, 20), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_headless_cursor_pass() {
+        let Ok(mut headless) = pollster::block_on(HeadlessRenderer::new(320, 240, 4, Skin::new())) else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+        let trail = CursorTrail::new(TrailSettings::default());
+        let scene = |cursor| Scene { cursor, ..scene(&trail) };
+
+        // Fully dimmed, so only what's drawn after post-processing shows up
+        headless.renderer.clear_color = wgpu::Color::WHITE;
+        headless.renderer.post_process.settings.background_dim = 1.0;
+        let hidden = headless.render(&scene((-100.0, -100.0)));
+        let shown = headless.render(&scene((256.0, 192.0)));
+        assert_eq!(hidden.get_pixel(160, 120), &Rgba([0, 0, 0, 255]));
+        let center = shown.get_pixel(160, 120);
+        assert!(center[0] > 200 && center[1] > 200, "{:?}", center);
+    }
+
+    #[test]
+    fn test_headless_timing_overlay() {
+        let Ok(mut headless) = pollster::block_on(HeadlessRenderer::new(320, 240, 1, Skin::new())) else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+        let trail = CursorTrail::new(TrailSettings::default());
+        let mut frame_times = RollingStats::new(4);
+        frame_times.push(16.7);
+        let mut cpu = Timings::new();
+        cpu.record_ms("Update", 0.3);
+        let scene = |profile| Scene { profile, ..scene(&trail) };
+
+        let plain = headless.render(&scene(None));
+        let profile = Profile { frame_times: &frame_times, cpu: &cpu };
+        // egui hides new windows for their first frame while it sizes them
+        headless.render(&scene(Some(profile)));
+        let overlaid = headless.render(&scene(Some(profile)));
+        let diff = diff_images(&plain, &overlaid, 4).unwrap();
+        assert!(diff.differing_pixels > 1000, "{:?}", diff);
+        // The table sits in the top left corner
+        assert_eq!(overlaid.get_pixel(300, 220), plain.get_pixel(300, 220));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::osu::OsuCircle;
+use crate::spinner::difficulty_range;
+
+/// Approach circles start at this multiple of the circle's size.
+pub const APPROACH_CIRCLE_SCALE: f32 = 4.0;
+/// How long a circle takes to fade out after its hit time, in milliseconds.
+pub const HIT_FADE_OUT_MS: f32 = 150.0;
+
+/// Circle radius in osu!pixels for a circle size (CS) setting.
+pub fn circle_radius(circle_size: f32) -> f32 {
+    54.4 - 4.48 * circle_size
+}
+
+/// When circles appear, from the approach rate (AR) setting. In milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CircleTiming {
+    /// How long before its hit time a circle appears.
+    pub preempt: f32,
+    pub fade_in: f32,
+}
+
+impl CircleTiming {
+    pub fn from_approach_rate(approach_rate: f32) -> CircleTiming {
+        CircleTiming {
+            preempt: difficulty_range(approach_rate, 1800.0, 1200.0, 450.0),
+            fade_in: difficulty_range(approach_rate, 1200.0, 800.0, 300.0),
+        }
+    }
+}
+
+/// One hit circle as it should be drawn this frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CircleDrawable {
+    /// Center in osu!pixels.
+    pub position: (f32, f32),
+    pub radius: f32,
+    /// Index of the object in the beatmap; earlier objects are drawn on top.
+    pub index: usize,
+    pub combo_number: u32,
+    /// Index into the skin's combo colours.
+    pub combo_colour: usize,
+    pub alpha: f32,
+    /// Size of the approach circle relative to the circle, `None` once the hit time has passed.
+    pub approach_scale: Option<f32>,
+}
+
+impl CircleDrawable {
+    /// `None` while the circle isn't visible at `time`.
+    pub fn new(circle: &OsuCircle, index: usize, time: f32, timing: &CircleTiming, radius: f32) -> Option<CircleDrawable> {
+        let hit_time = circle.time as f32;
+        let appear_time = hit_time - timing.preempt;
+        if time < appear_time || time > hit_time + HIT_FADE_OUT_MS {
+            return None;
+        }
+
+        let (alpha, approach_scale) = if time <= hit_time {
+            let alpha = ((time - appear_time) / timing.fade_in.max(1.0)).min(1.0);
+            let remaining = (hit_time - time) / timing.preempt.max(1.0);
+            (alpha, Some(1.0 + (APPROACH_CIRCLE_SCALE - 1.0) * remaining))
+        } else {
+            (1.0 - (time - hit_time) / HIT_FADE_OUT_MS, None)
+        };
+
+        Some(CircleDrawable {
+            position: (circle.x, circle.y),
+            radius,
+            index,
+            combo_number: 1,
+            combo_colour: 0,
+            alpha,
+            approach_scale,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circle_drawable_over_time() {
+        let circle = OsuCircle { x: 100.0, y: 50.0, time: 2000, new_combo: true, combo_skip: 0 };
+        let timing = CircleTiming::from_approach_rate(5.0);
+        assert_eq!(timing.preempt, 1200.0);
+
+        assert!(CircleDrawable::new(&circle, 0, 700.0, &timing, 32.0).is_none());
+        let appearing = CircleDrawable::new(&circle, 0, 800.0, &timing, 32.0).unwrap();
+        assert_eq!((appearing.alpha, appearing.approach_scale), (0.0, Some(APPROACH_CIRCLE_SCALE)));
+        let halfway = CircleDrawable::new(&circle, 0, 1400.0, &timing, 32.0).unwrap();
+        assert_eq!((halfway.alpha, halfway.approach_scale), (0.75, Some(2.5)));
+        let hit = CircleDrawable::new(&circle, 0, 2000.0, &timing, 32.0).unwrap();
+        assert_eq!((hit.alpha, hit.approach_scale), (1.0, Some(1.0)));
+        let fading = CircleDrawable::new(&circle, 0, 2075.0, &timing, 32.0).unwrap();
+        assert_eq!((fading.alpha, fading.approach_scale), (0.5, None));
+        assert!(CircleDrawable::new(&circle, 0, 2200.0, &timing, 32.0).is_none());
+    }
+}
@@ -0,0 +1,60 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    K1,
+    K2,
+    M1,
+    M2,
+    Pause,
+    Skip,
+    Retry,
+    QuickRestart,
+    OffsetIncrease,
+    OffsetDecrease,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::K1,
+        Action::K2,
+        Action::M1,
+        Action::M2,
+        Action::Pause,
+        Action::Skip,
+        Action::Retry,
+        Action::QuickRestart,
+        Action::OffsetIncrease,
+        Action::OffsetDecrease,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::K1 => "K1",
+            Action::K2 => "K2",
+            Action::M1 => "M1",
+            Action::M2 => "M2",
+            Action::Pause => "Pause",
+            Action::Skip => "Skip",
+            Action::Retry => "Retry",
+            Action::QuickRestart => "QuickRestart",
+            Action::OffsetIncrease => "OffsetIncrease",
+            Action::OffsetDecrease => "OffsetDecrease",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// Whether the action counts as a hit key for judgement.
+    pub fn is_gameplay_key(&self) -> bool {
+        matches!(self, Action::K1 | Action::K2 | Action::M1 | Action::M2)
+    }
+}
+
+/// A mapped press or release, stamped with the gameplay clock in milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ActionEvent {
+    pub action: Action,
+    pub pressed: bool,
+    pub time: f64,
+}
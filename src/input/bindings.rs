@@ -0,0 +1,245 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use winit::event::{MouseButton, VirtualKeyCode};
+use crate::input::Action;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => key_name(*key).unwrap_or("Unknown").to_string(),
+            Binding::Mouse(MouseButton::Left) => "MouseLeft".to_string(),
+            Binding::Mouse(MouseButton::Right) => "MouseRight".to_string(),
+            Binding::Mouse(MouseButton::Middle) => "MouseMiddle".to_string(),
+            Binding::Mouse(MouseButton::Other(button)) => format!("Mouse{}", button),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Binding> {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+            _ => {
+                if let Some(button) = name.strip_prefix("Mouse").and_then(|n| n.parse::<u16>().ok()) {
+                    return Some(Binding::Mouse(MouseButton::Other(button)));
+                }
+                key_from_name(name).map(Binding::Key)
+            }
+        }
+    }
+}
+
+/// Action <-> physical input table. An action may have several bindings,
+/// but each binding triggers at most one action.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputBindings {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        InputBindings {
+            bindings: vec![
+                (Binding::Key(VirtualKeyCode::Z), Action::K1),
+                (Binding::Key(VirtualKeyCode::X), Action::K2),
+                (Binding::Mouse(MouseButton::Left), Action::M1),
+                (Binding::Mouse(MouseButton::Right), Action::M2),
+                (Binding::Key(VirtualKeyCode::Escape), Action::Pause),
+                (Binding::Key(VirtualKeyCode::Space), Action::Skip),
+                (Binding::Key(VirtualKeyCode::R), Action::Retry),
+                (Binding::Key(VirtualKeyCode::Grave), Action::QuickRestart),
+                (Binding::Key(VirtualKeyCode::Equals), Action::OffsetIncrease),
+                (Binding::Key(VirtualKeyCode::Minus), Action::OffsetDecrease),
+            ],
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn empty() -> InputBindings {
+        InputBindings { bindings: Vec::new() }
+    }
+
+    /// Loads bindings from `path`, falling back to the defaults if the file can't be read.
+    /// A missing file is created with the defaults so there is something to edit.
+    pub fn load_or_default(path: &str) -> InputBindings {
+        match Self::load(path) {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                log::warn!("Using default key bindings ({}): {}", path, e);
+                let bindings = InputBindings::default();
+                if !std::path::Path::new(path).exists() {
+                    if let Err(e) = bindings.save(path) {
+                        log::warn!("Couldn't write default key bindings to {}: {}", path, e);
+                    }
+                }
+                bindings
+            }
+        }
+    }
+
+    pub fn load(path: &str) -> Result<InputBindings, BindingsError> {
+        let file = std::fs::read_to_string(path).map_err(|_| BindingsError::FileError)?;
+        Self::parse(&file)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), BindingsError> {
+        std::fs::write(path, self.serialize()).map_err(|_| BindingsError::FileError)
+    }
+
+    /// Parses `Action = Binding` lines. Blank lines and `//` comments are skipped.
+    pub fn parse(text: &str) -> Result<InputBindings, BindingsError> {
+        let mut bindings = InputBindings::empty();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let (action, binding) = line
+                .split_once('=')
+                .ok_or(BindingsError::ParseError(line_number + 1))?;
+            let action = Action::from_name(action.trim()).ok_or(BindingsError::ParseError(line_number + 1))?;
+            let binding = Binding::from_name(binding.trim()).ok_or(BindingsError::ParseError(line_number + 1))?;
+            bindings.bind(binding, action);
+        }
+        Ok(bindings)
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut text = String::new();
+        for action in Action::ALL {
+            for binding in self.bindings_for(action) {
+                text.push_str(&format!("{} = {}\n", action.name(), binding.name()));
+            }
+        }
+        text
+    }
+
+    /// Binds `binding` to `action`, replacing whatever the binding was mapped to before.
+    pub fn bind(&mut self, binding: Binding, action: Action) {
+        self.bindings.retain(|(existing, _)| *existing != binding);
+        self.bindings.push((binding, action));
+    }
+
+    pub fn action_for(&self, binding: Binding) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(existing, _)| *existing == binding)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn bindings_for(&self, action: Action) -> Vec<Binding> {
+        self.bindings
+            .iter()
+            .filter(|(_, existing)| *existing == action)
+            .map(|(binding, _)| *binding)
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BindingsError {
+    FileError,
+    ParseError(usize),
+}
+
+impl Display for BindingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingsError::FileError => write!(f, "FileError"),
+            BindingsError::ParseError(line) => write!(f, "ParseError on line {}", line),
+        }
+    }
+}
+
+impl Error for BindingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+const KEY_NAMES: &[(&str, VirtualKeyCode)] = &[
+    ("A", VirtualKeyCode::A), ("B", VirtualKeyCode::B), ("C", VirtualKeyCode::C),
+    ("D", VirtualKeyCode::D), ("E", VirtualKeyCode::E), ("F", VirtualKeyCode::F),
+    ("G", VirtualKeyCode::G), ("H", VirtualKeyCode::H), ("I", VirtualKeyCode::I),
+    ("J", VirtualKeyCode::J), ("K", VirtualKeyCode::K), ("L", VirtualKeyCode::L),
+    ("M", VirtualKeyCode::M), ("N", VirtualKeyCode::N), ("O", VirtualKeyCode::O),
+    ("P", VirtualKeyCode::P), ("Q", VirtualKeyCode::Q), ("R", VirtualKeyCode::R),
+    ("S", VirtualKeyCode::S), ("T", VirtualKeyCode::T), ("U", VirtualKeyCode::U),
+    ("V", VirtualKeyCode::V), ("W", VirtualKeyCode::W), ("X", VirtualKeyCode::X),
+    ("Y", VirtualKeyCode::Y), ("Z", VirtualKeyCode::Z),
+    ("Key0", VirtualKeyCode::Key0), ("Key1", VirtualKeyCode::Key1), ("Key2", VirtualKeyCode::Key2),
+    ("Key3", VirtualKeyCode::Key3), ("Key4", VirtualKeyCode::Key4), ("Key5", VirtualKeyCode::Key5),
+    ("Key6", VirtualKeyCode::Key6), ("Key7", VirtualKeyCode::Key7), ("Key8", VirtualKeyCode::Key8),
+    ("Key9", VirtualKeyCode::Key9),
+    ("F1", VirtualKeyCode::F1), ("F2", VirtualKeyCode::F2), ("F3", VirtualKeyCode::F3),
+    ("F4", VirtualKeyCode::F4), ("F5", VirtualKeyCode::F5), ("F6", VirtualKeyCode::F6),
+    ("F7", VirtualKeyCode::F7), ("F8", VirtualKeyCode::F8), ("F9", VirtualKeyCode::F9),
+    ("F10", VirtualKeyCode::F10), ("F11", VirtualKeyCode::F11), ("F12", VirtualKeyCode::F12),
+    ("Escape", VirtualKeyCode::Escape), ("Space", VirtualKeyCode::Space), ("Tab", VirtualKeyCode::Tab),
+    ("Return", VirtualKeyCode::Return), ("Back", VirtualKeyCode::Back), ("Delete", VirtualKeyCode::Delete),
+    ("Insert", VirtualKeyCode::Insert), ("Home", VirtualKeyCode::Home), ("End", VirtualKeyCode::End),
+    ("PageUp", VirtualKeyCode::PageUp), ("PageDown", VirtualKeyCode::PageDown),
+    ("Left", VirtualKeyCode::Left), ("Right", VirtualKeyCode::Right),
+    ("Up", VirtualKeyCode::Up), ("Down", VirtualKeyCode::Down),
+    ("Grave", VirtualKeyCode::Grave), ("Minus", VirtualKeyCode::Minus), ("Equals", VirtualKeyCode::Equals),
+    ("LBracket", VirtualKeyCode::LBracket), ("RBracket", VirtualKeyCode::RBracket),
+    ("Semicolon", VirtualKeyCode::Semicolon), ("Apostrophe", VirtualKeyCode::Apostrophe),
+    ("Comma", VirtualKeyCode::Comma), ("Period", VirtualKeyCode::Period), ("Slash", VirtualKeyCode::Slash),
+    ("Backslash", VirtualKeyCode::Backslash),
+    ("LShift", VirtualKeyCode::LShift), ("RShift", VirtualKeyCode::RShift),
+    ("LControl", VirtualKeyCode::LControl), ("RControl", VirtualKeyCode::RControl),
+    ("LAlt", VirtualKeyCode::LAlt), ("RAlt", VirtualKeyCode::RAlt),
+    ("Numpad0", VirtualKeyCode::Numpad0), ("Numpad1", VirtualKeyCode::Numpad1),
+    ("Numpad2", VirtualKeyCode::Numpad2), ("Numpad3", VirtualKeyCode::Numpad3),
+    ("Numpad4", VirtualKeyCode::Numpad4), ("Numpad5", VirtualKeyCode::Numpad5),
+    ("Numpad6", VirtualKeyCode::Numpad6), ("Numpad7", VirtualKeyCode::Numpad7),
+    ("Numpad8", VirtualKeyCode::Numpad8), ("Numpad9", VirtualKeyCode::Numpad9),
+    ("NumpadAdd", VirtualKeyCode::NumpadAdd), ("NumpadSubtract", VirtualKeyCode::NumpadSubtract),
+];
+
+fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+    KEY_NAMES.iter().find(|(_, code)| *code == key).map(|(name, _)| *name)
+}
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    KEY_NAMES.iter().find(|(key, _)| *key == name).map(|(_, code)| *code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_roundtrip() {
+        let mut bindings = InputBindings::default();
+        bindings.bind(Binding::Key(VirtualKeyCode::C), Action::K1);
+        bindings.bind(Binding::Mouse(MouseButton::Other(4)), Action::Skip);
+
+        let parsed = InputBindings::parse(&bindings.serialize()).unwrap();
+        assert_eq!(parsed.action_for(Binding::Key(VirtualKeyCode::C)), Some(Action::K1));
+        assert_eq!(parsed.action_for(Binding::Key(VirtualKeyCode::Z)), Some(Action::K1));
+        assert_eq!(parsed.action_for(Binding::Mouse(MouseButton::Other(4))), Some(Action::Skip));
+        assert_eq!(parsed.bindings_for(Action::K2), vec![Binding::Key(VirtualKeyCode::X)]);
+    }
+
+    #[test]
+    fn test_bindings_parse_error() {
+        let result = InputBindings::parse("K1 = Z\nNotAnAction = X\n");
+        assert_eq!(result, Err(BindingsError::ParseError(2)));
+    }
+
+    #[test]
+    fn test_bind_replaces_previous_action() {
+        let mut bindings = InputBindings::default();
+        bindings.bind(Binding::Key(VirtualKeyCode::Z), Action::K2);
+        assert_eq!(bindings.action_for(Binding::Key(VirtualKeyCode::Z)), Some(Action::K2));
+        assert!(bindings.bindings_for(Action::K1).is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+use winit::event::{DeviceEvent, ElementState, KeyboardInput, WindowEvent};
+use crate::input::Binding;
+
+/// Owned copy of the parts of a window event gameplay cares about.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RawInput {
+    Button { binding: Binding, pressed: bool },
+    CursorMoved { x: f64, y: f64 },
+    MouseMotion { dx: f64, dy: f64 },
+    FocusLost,
+}
+
+impl RawInput {
+    pub fn from_event(event: &WindowEvent) -> Option<RawInput> {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => Some(RawInput::Button {
+                binding: Binding::Key(*key),
+                pressed: *state == ElementState::Pressed,
+            }),
+            WindowEvent::MouseInput { state, button, .. } => Some(RawInput::Button {
+                binding: Binding::Mouse(*button),
+                pressed: *state == ElementState::Pressed,
+            }),
+            WindowEvent::CursorMoved { position, .. } => Some(RawInput::CursorMoved {
+                x: position.x,
+                y: position.y,
+            }),
+            WindowEvent::Focused(false) => Some(RawInput::FocusLost),
+            _ => None,
+        }
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<RawInput> {
+        match event {
+            DeviceEvent::MouseMotion { delta: (dx, dy) } => Some(RawInput::MouseMotion { dx: *dx, dy: *dy }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimedInput {
+    pub at: Instant,
+    pub input: RawInput,
+}
+
+/// Queue of inputs stamped with the monotonic clock when the event loop hands them over.
+/// Events are only converted to gameplay time when drained, so a slow update or draw
+/// delays processing but not the recorded hit time. Events that arrive while the loop is
+/// blocked, e.g. waiting to present with Fifo, are still stamped late, which is why
+/// pacing defaults to a present mode that doesn't block.
+pub struct InputCapture {
+    queue: VecDeque<TimedInput>,
+}
+
+impl InputCapture {
+    pub fn new() -> InputCapture {
+        InputCapture {
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Stamps and queues `event`. Returns the captured input, if the event was relevant.
+    pub fn capture(&mut self, event: &WindowEvent) -> Option<RawInput> {
+        let at = Instant::now();
+        let input = RawInput::from_event(event)?;
+        self.push(at, input);
+        Some(input)
+    }
+
+    pub fn capture_device(&mut self, event: &DeviceEvent) -> Option<RawInput> {
+        let at = Instant::now();
+        let input = RawInput::from_device_event(event)?;
+        self.push(at, input);
+        Some(input)
+    }
+
+    pub fn push(&mut self, at: Instant, input: RawInput) {
+        // Keep the queue ordered even if a caller stamps events out of order
+        let index = self.queue.partition_point(|queued| queued.at <= at);
+        self.queue.insert(index, TimedInput { at, input });
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = TimedInput> + '_ {
+        self.queue.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::clock::GameplayClock;
+    use winit::event::VirtualKeyCode;
+
+    #[test]
+    fn test_drain_in_arrival_order() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let press = |pressed| RawInput::Button { binding: Binding::Key(VirtualKeyCode::Z), pressed };
+        let mut capture = InputCapture::new();
+        capture.push(ms(10), press(true));
+        capture.push(ms(30), press(false));
+        // Stamped earlier than what is already queued
+        capture.push(ms(20), RawInput::MouseMotion { dx: 1.0, dy: 0.0 });
+        // Same instant as a queued event goes after it
+        capture.push(ms(10), RawInput::FocusLost);
+
+        let drained: Vec<TimedInput> = capture.drain().collect();
+        let expected = [
+            (ms(10), press(true)),
+            (ms(10), RawInput::FocusLost),
+            (ms(20), RawInput::MouseMotion { dx: 1.0, dy: 0.0 }),
+            (ms(30), press(false)),
+        ];
+        assert_eq!(drained, expected.map(|(at, input)| TimedInput { at, input }));
+        assert_eq!(capture.drain().count(), 0);
+    }
+
+    #[test]
+    fn test_capture_stamps_on_arrival() {
+        let mut capture = InputCapture::new();
+        let before = Instant::now();
+        let input = capture.capture_device(&DeviceEvent::MouseMotion { delta: (2.0, -3.0) });
+        assert_eq!(input, Some(RawInput::MouseMotion { dx: 2.0, dy: -3.0 }));
+        assert_eq!(capture.capture_device(&DeviceEvent::Added), None);
+
+        let drained: Vec<TimedInput> = capture.drain().collect();
+        assert_eq!(drained.len(), 1);
+        assert!(drained[0].at >= before && drained[0].at <= Instant::now());
+    }
+
+    #[test]
+    fn test_slow_frame_keeps_hit_time() {
+        let mut clock = GameplayClock::new();
+        clock.start();
+        let mut capture = InputCapture::new();
+        capture.capture_device(&DeviceEvent::MouseMotion { delta: (1.0, 0.0) });
+        let arrived = clock.time_ms();
+
+        // The frame after the event takes a while before input is drained
+        std::thread::sleep(Duration::from_millis(30));
+        let timed = capture.drain().next().unwrap();
+        assert!(clock.time_at(timed.at) <= arrived);
+        assert!(clock.time_ms() - clock.time_at(timed.at) >= 30.0);
+    }
+}
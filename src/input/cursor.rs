@@ -0,0 +1,197 @@
+/// Axis-aligned rectangle in pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    pub fn clamp(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            x.clamp(self.x, self.x + self.width),
+            y.clamp(self.y, self.y + self.height),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CursorMode {
+    /// Follow the OS cursor (`WindowEvent::CursorMoved`).
+    Absolute,
+    /// Accumulate raw `DeviceEvent::MouseMotion` deltas scaled by the sensitivity.
+    Relative,
+    /// Map `tablet_area` (in window pixels, as reported by the tablet driver) onto the target area.
+    Tablet,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CursorSettings {
+    pub mode: CursorMode,
+    pub sensitivity: f64,
+    pub confine_to_window: bool,
+    /// Part of the window the tablet driver maps the tablet onto, `None` for the whole window.
+    pub tablet_area: Option<Rect>,
+}
+
+impl Default for CursorSettings {
+    fn default() -> Self {
+        CursorSettings {
+            mode: CursorMode::Absolute,
+            sensitivity: 1.0,
+            confine_to_window: true,
+            tablet_area: None,
+        }
+    }
+}
+
+/// Turns OS cursor positions and raw mouse motion into a cursor position in window pixels.
+pub struct CursorPipeline {
+    pub settings: CursorSettings,
+    window: Rect,
+    target_area: Rect,
+    position: (f64, f64),
+}
+
+impl CursorPipeline {
+    pub fn new(settings: CursorSettings, window_width: f64, window_height: f64) -> CursorPipeline {
+        let window = Rect::new(0.0, 0.0, window_width, window_height);
+        CursorPipeline {
+            settings,
+            window,
+            target_area: window,
+            position: (window_width / 2.0, window_height / 2.0),
+        }
+    }
+
+    pub fn position(&self) -> (f64, f64) {
+        self.position
+    }
+
+    pub fn set_window_size(&mut self, width: f64, height: f64) {
+        let resized_target = self.target_area == self.window;
+        self.window = Rect::new(0.0, 0.0, width, height);
+        if resized_target {
+            self.target_area = self.window;
+        }
+        self.position = self.confine(self.position.0, self.position.1);
+    }
+
+    /// Area the tablet area is mapped onto, usually the playfield.
+    pub fn set_target_area(&mut self, area: Rect) {
+        self.target_area = area;
+    }
+
+    pub fn on_cursor_moved(&mut self, x: f64, y: f64) {
+        let position = match self.settings.mode {
+            CursorMode::Absolute => (x, y),
+            CursorMode::Tablet => self.map_tablet(x, y),
+            CursorMode::Relative => return,
+        };
+        self.position = self.confine(position.0, position.1);
+    }
+
+    pub fn on_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.settings.mode != CursorMode::Relative {
+            return;
+        }
+        let x = self.position.0 + dx * self.settings.sensitivity;
+        let y = self.position.1 + dy * self.settings.sensitivity;
+        self.position = self.confine(x, y);
+    }
+
+    fn map_tablet(&self, x: f64, y: f64) -> (f64, f64) {
+        let area = self.settings.tablet_area.unwrap_or(self.window);
+        let u = (x - area.x) / area.width;
+        let v = (y - area.y) / area.height;
+        (
+            self.target_area.x + u * self.target_area.width,
+            self.target_area.y + v * self.target_area.height,
+        )
+    }
+
+    fn confine(&self, x: f64, y: f64) -> (f64, f64) {
+        if self.settings.confine_to_window {
+            self.window.clamp(x, y)
+        } else {
+            (x, y)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_motion_with_sensitivity() {
+        let settings = CursorSettings {
+            mode: CursorMode::Relative,
+            sensitivity: 1.5,
+            ..Default::default()
+        };
+        let mut cursor = CursorPipeline::new(settings, 800.0, 600.0);
+        cursor.on_mouse_motion(10.0, -20.0);
+        assert_eq!(cursor.position(), (415.0, 270.0));
+
+        // OS cursor positions are ignored in relative mode
+        cursor.on_cursor_moved(0.0, 0.0);
+        assert_eq!(cursor.position(), (415.0, 270.0));
+    }
+
+    #[test]
+    fn test_relative_motion_is_confined() {
+        let settings = CursorSettings {
+            mode: CursorMode::Relative,
+            ..Default::default()
+        };
+        let mut cursor = CursorPipeline::new(settings, 800.0, 600.0);
+        cursor.on_mouse_motion(-10000.0, 10000.0);
+        assert_eq!(cursor.position(), (0.0, 600.0));
+    }
+
+    #[test]
+    fn test_tablet_area_mapping() {
+        let settings = CursorSettings {
+            mode: CursorMode::Tablet,
+            tablet_area: Some(Rect::new(100.0, 100.0, 200.0, 150.0)),
+            ..Default::default()
+        };
+        let mut cursor = CursorPipeline::new(settings, 800.0, 600.0);
+        cursor.set_target_area(Rect::new(80.0, 60.0, 640.0, 480.0));
+
+        cursor.on_cursor_moved(100.0, 100.0);
+        assert_eq!(cursor.position(), (80.0, 60.0));
+        cursor.on_cursor_moved(200.0, 175.0);
+        assert_eq!(cursor.position(), (400.0, 300.0));
+        cursor.on_cursor_moved(300.0, 250.0);
+        assert_eq!(cursor.position(), (720.0, 540.0));
+
+        // Outside the tablet area ends up clamped to the window
+        cursor.on_cursor_moved(400.0, 400.0);
+        assert_eq!(cursor.position(), (800.0, 600.0));
+    }
+
+    #[test]
+    fn test_default_tablet_area_is_the_window() {
+        let settings = CursorSettings {
+            mode: CursorMode::Tablet,
+            ..Default::default()
+        };
+        let mut cursor = CursorPipeline::new(settings, 800.0, 600.0);
+        cursor.set_target_area(Rect::new(80.0, 60.0, 640.0, 480.0));
+        cursor.on_cursor_moved(800.0, 600.0);
+        assert_eq!(cursor.position(), (720.0, 540.0));
+
+        // Follows the window when it's resized
+        cursor.set_window_size(1600.0, 1200.0);
+        cursor.set_target_area(Rect::new(160.0, 120.0, 1280.0, 960.0));
+        cursor.on_cursor_moved(800.0, 600.0);
+        assert_eq!(cursor.position(), (800.0, 600.0));
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+use crate::input::{Action, ActionEvent, Binding, InputBindings};
+
+/// Turns presses and releases of bound keys and buttons into gameplay actions.
+pub struct InputMapper {
+    pub bindings: InputBindings,
+    /// Bindings currently down. An action stays held while any of its bindings is.
+    held: HashSet<Binding>,
+}
+
+impl InputMapper {
+    pub fn new(bindings: InputBindings) -> InputMapper {
+        InputMapper {
+            bindings,
+            held: HashSet::new(),
+        }
+    }
+
+    /// Maps a press or release of `binding` to an action stamped with `time` (gameplay clock, ms).
+    /// Returns `None` for unbound input, OS key repeats, and bindings of an action that is
+    /// already held through another binding.
+    pub fn map_binding(&mut self, binding: Binding, pressed: bool, time: f64) -> Option<ActionEvent> {
+        let action = self.bindings.action_for(binding)?;
+        let was_held = self.is_held(action);
+        let changed = if pressed {
+            self.held.insert(binding)
+        } else {
+            self.held.remove(&binding)
+        };
+
+        if changed && was_held != self.is_held(action) {
+            Some(ActionEvent { action, pressed, time })
+        } else {
+            None
+        }
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.iter().any(|binding| self.bindings.action_for(*binding) == Some(action))
+    }
+
+    /// Releases everything, e.g. when the window loses focus.
+    pub fn release_all(&mut self, time: f64) -> Vec<ActionEvent> {
+        let mut released: Vec<Action> = Vec::new();
+        for binding in self.held.drain() {
+            if let Some(action) = self.bindings.action_for(binding).filter(|action| !released.contains(action)) {
+                released.push(action);
+            }
+        }
+        released
+            .into_iter()
+            .map(|action| ActionEvent { action, pressed: false, time })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::VirtualKeyCode;
+
+    #[test]
+    fn test_action_held_until_every_binding_is_released() {
+        let mut bindings = InputBindings::empty();
+        bindings.bind(Binding::Key(VirtualKeyCode::Z), Action::K1);
+        bindings.bind(Binding::Key(VirtualKeyCode::C), Action::K1);
+        let mut mapper = InputMapper::new(bindings);
+        let (z, c) = (Binding::Key(VirtualKeyCode::Z), Binding::Key(VirtualKeyCode::C));
+
+        assert_eq!(mapper.map_binding(z, true, 0.0), Some(ActionEvent { action: Action::K1, pressed: true, time: 0.0 }));
+        assert_eq!(mapper.map_binding(c, true, 1.0), None);
+        // Key repeat
+        assert_eq!(mapper.map_binding(z, true, 2.0), None);
+        assert_eq!(mapper.map_binding(z, false, 3.0), None);
+        assert!(mapper.is_held(Action::K1));
+        assert_eq!(mapper.map_binding(c, false, 4.0), Some(ActionEvent { action: Action::K1, pressed: false, time: 4.0 }));
+        assert!(!mapper.is_held(Action::K1));
+
+        mapper.map_binding(z, true, 5.0);
+        mapper.map_binding(c, true, 5.0);
+        assert_eq!(mapper.release_all(6.0), vec![ActionEvent { action: Action::K1, pressed: false, time: 6.0 }]);
+        assert!(!mapper.is_held(Action::K1));
+    }
+}
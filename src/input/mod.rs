@@ -0,0 +1,11 @@
+mod action;
+mod bindings;
+mod capture;
+mod cursor;
+mod mapper;
+
+pub use action::{Action, ActionEvent};
+pub use bindings::{Binding, InputBindings};
+pub use capture::{InputCapture, RawInput};
+pub use cursor::{CursorMode, CursorPipeline, CursorSettings, Rect};
+pub use mapper::InputMapper;
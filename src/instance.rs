@@ -1,9 +0,0 @@
-struct Instance {
-    position: cgmath::Vector3<f32>
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct InstanceRaw {
-    model: [[f32; 4]; 4],
-}
\ No newline at end of file
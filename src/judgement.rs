@@ -0,0 +1,230 @@
+use crate::hit_circle::circle_radius;
+use crate::osu::{OsuCircle, OsuMap, OsuObject, OsuSpinner};
+use crate::replay::{Keys, ReplayFrame, ReplayScore};
+use crate::spinner::{difficulty_range, SpinnerState};
+
+/// Result of one hit object.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Judgement {
+    Great,
+    Good,
+    Meh,
+    Miss,
+}
+
+impl Judgement {
+    /// Points before the combo bonus.
+    pub fn hit_value(self) -> i32 {
+        match self {
+            Judgement::Great => 300,
+            Judgement::Good => 100,
+            Judgement::Meh => 50,
+            Judgement::Miss => 0,
+        }
+    }
+}
+
+/// How far from an object's time a press may land for each judgement, in milliseconds either way.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HitWindows {
+    pub great: f32,
+    pub good: f32,
+    pub meh: f32,
+}
+
+impl HitWindows {
+    pub fn from_overall_difficulty(overall_difficulty: f32) -> HitWindows {
+        HitWindows {
+            great: difficulty_range(overall_difficulty, 80.0, 50.0, 20.0),
+            good: difficulty_range(overall_difficulty, 140.0, 100.0, 60.0),
+            meh: difficulty_range(overall_difficulty, 200.0, 150.0, 100.0),
+        }
+    }
+
+    /// `None` if `offset` is outside every window.
+    pub fn judge(&self, offset: f32) -> Option<Judgement> {
+        let offset = offset.abs();
+        if offset <= self.great {
+            Some(Judgement::Great)
+        } else if offset <= self.good {
+            Some(Judgement::Good)
+        } else if offset <= self.meh {
+            Some(Judgement::Meh)
+        } else {
+            None
+        }
+    }
+}
+
+/// Adds judgements up into a score as osu!'s ScoreV1 does, without the difficulty and mod multipliers.
+#[derive(Clone, Debug, Default)]
+pub struct ScoreCounter {
+    score: ReplayScore,
+    combo: u16,
+}
+
+impl ScoreCounter {
+    pub fn add(&mut self, judgement: Judgement) {
+        let score = &mut self.score;
+        match judgement {
+            Judgement::Great => score.count_300 += 1,
+            Judgement::Good => score.count_100 += 1,
+            Judgement::Meh => score.count_50 += 1,
+            Judgement::Miss => {
+                score.count_miss += 1;
+                self.combo = 0;
+                return;
+            }
+        }
+        score.total_score += judgement.hit_value() * (25 + self.combo as i32) / 25;
+        self.combo += 1;
+        score.max_combo = score.max_combo.max(self.combo);
+    }
+
+    pub fn finish(mut self) -> ReplayScore {
+        self.score.perfect = self.score.count_miss == 0 && self.score.max_combo > 0;
+        self.score
+    }
+}
+
+/// Judges a play of `map` from its replay frames, the same way for live plays and loaded replays.
+/// A circle is hit by the first key press on it within the hit windows, and presses can't skip
+/// ahead of an object that can still be hit. Sliders are judged on their head alone, spinners
+/// on how much of the required spinning was done.
+pub fn judge_play(map: &OsuMap, frames: &[ReplayFrame]) -> ReplayScore {
+    let windows = HitWindows::from_overall_difficulty(map.difficulty.overall_difficulty);
+    let radius = circle_radius(map.difficulty.circle_size);
+    let mut previous = Keys::NONE;
+    let presses: Vec<&ReplayFrame> = frames
+        .iter()
+        .filter(|frame| {
+            let pressed = frame.keys.pressed_since(previous);
+            previous = frame.keys;
+            pressed
+        })
+        .collect();
+
+    let mut next_press = 0;
+    let mut counter = ScoreCounter::default();
+    for object in map.objects.values() {
+        let judgement = match object {
+            OsuObject::Circle(circle) => judge_circle(circle, &presses, &mut next_press, &windows, radius),
+            OsuObject::Slider(slider) => judge_circle(&slider.head(), &presses, &mut next_press, &windows, radius),
+            OsuObject::Spinner(spinner) => judge_spinner(spinner, frames, map.difficulty.overall_difficulty),
+        };
+        counter.add(judgement);
+    }
+    counter.finish()
+}
+
+/// Uses up presses from `next_press` on until one hits `circle` or they're past its windows.
+fn judge_circle(circle: &OsuCircle, presses: &[&ReplayFrame], next_press: &mut usize, windows: &HitWindows, radius: f32) -> Judgement {
+    while let Some(press) = presses.get(*next_press) {
+        let offset = (press.time - circle.time as i64) as f32;
+        if offset > windows.meh {
+            break;
+        }
+        *next_press += 1;
+        let (dx, dy) = (press.x - circle.x, press.y - circle.y);
+        if dx * dx + dy * dy > radius * radius {
+            continue;
+        }
+        if let Some(judgement) = windows.judge(offset) {
+            return judgement;
+        }
+    }
+    Judgement::Miss
+}
+
+fn judge_spinner(spinner: &OsuSpinner, frames: &[ReplayFrame], overall_difficulty: f32) -> Judgement {
+    let mut state = SpinnerState::new(spinner, overall_difficulty);
+    for frame in frames {
+        state.update(frame.time as f32, (frame.x, frame.y), frame.keys != Keys::NONE);
+    }
+    let spins = state.spins();
+    if state.is_cleared() {
+        Judgement::Great
+    } else if spins >= state.spins_required - 1.0 {
+        Judgement::Good
+    } else if spins >= state.spins_required / 4.0 {
+        Judgement::Meh
+    } else {
+        Judgement::Miss
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(times: &[u32]) -> OsuMap {
+        let mut map = OsuMap::new();
+        for &time in times {
+            let circle = OsuCircle { x: 100.0, y: 100.0, time, new_combo: false, combo_skip: 0 };
+            map.objects.insert(time as u64, OsuObject::Circle(circle));
+        }
+        map
+    }
+
+    fn tap(frames: &mut Vec<ReplayFrame>, time: i64, (x, y): (f32, f32)) {
+        frames.push(ReplayFrame { time, x, y, keys: Keys::K1 });
+        frames.push(ReplayFrame { time: time + 10, x, y, keys: Keys::NONE });
+    }
+
+    #[test]
+    fn test_hit_windows() {
+        let windows = HitWindows::from_overall_difficulty(5.0);
+        assert_eq!(windows, HitWindows { great: 50.0, good: 100.0, meh: 150.0 });
+        assert_eq!(windows.judge(-50.0), Some(Judgement::Great));
+        assert_eq!(windows.judge(80.0), Some(Judgement::Good));
+        assert_eq!(windows.judge(-150.0), Some(Judgement::Meh));
+        assert_eq!(windows.judge(151.0), None);
+    }
+
+    #[test]
+    fn test_judge_circles() {
+        let map = map(&[1000, 2000, 3000, 4000]);
+        let mut frames = Vec::new();
+        tap(&mut frames, 1010, (100.0, 100.0));
+        tap(&mut frames, 2080, (105.0, 100.0));
+        // Off the circle, then nothing for the last one
+        tap(&mut frames, 3000, (300.0, 300.0));
+
+        let score = judge_play(&map, &frames);
+        assert_eq!((score.count_300, score.count_100, score.count_50, score.count_miss), (1, 1, 0, 2));
+        assert_eq!(score.max_combo, 2);
+        assert_eq!(score.total_score, 300 + 100 * 26 / 25);
+        assert!(!score.perfect);
+    }
+
+    #[test]
+    fn test_press_hits_one_object() {
+        // Both circles are in reach of the press, which only hits the earlier one
+        let map = map(&[1000, 1100]);
+        let mut frames = Vec::new();
+        tap(&mut frames, 1050, (100.0, 100.0));
+        assert_eq!(judge_play(&map, &frames).count_miss, 1);
+
+        tap(&mut frames, 1100, (100.0, 100.0));
+        let score = judge_play(&map, &frames);
+        assert_eq!((score.count_300, score.count_miss), (2, 0));
+        assert!(score.perfect);
+    }
+
+    #[test]
+    fn test_judge_spinner() {
+        let mut map = OsuMap::new();
+        map.objects.insert(0, OsuObject::Spinner(OsuSpinner { time: 0, end_time: 1000 }));
+        let still = [ReplayFrame { time: 0, x: 0.0, y: 0.0, keys: Keys::K1 }];
+        assert_eq!(judge_play(&map, &still).count_miss, 1);
+
+        let spinning: Vec<ReplayFrame> = (0..=100)
+            .map(|i| {
+                let angle = i as f32 / 100.0 * 6.0 * std::f32::consts::TAU;
+                let (x, y) = (256.0 + 50.0 * angle.cos(), 192.0 + 50.0 * angle.sin());
+                ReplayFrame { time: i * 10, x, y, keys: Keys::K1 }
+            })
+            .collect();
+        assert_eq!(judge_play(&map, &spinning).count_300, 1);
+    }
+}
@@ -1,23 +1,45 @@
-mod circle;
 mod texture;
 mod state;
-mod instance;
-mod vertex;
 mod uniforms;
 mod audio;
 mod osu;
-
-use winit::{event::*, 
-            event_loop::{ControlFlow, EventLoop}, 
-            window::WindowBuilder, 
-            window::Window, 
-            dpi::{PhysicalSize, Size}, 
-            event
+mod replay;
+mod judgement;
+mod input;
+mod clock;
+mod playfield;
+mod slider;
+mod slider_renderer;
+mod spinner;
+mod spinner_renderer;
+mod cursor_trail;
+mod cursor_renderer;
+mod skin;
+mod atlas;
+mod sprite_batch;
+mod mipmap;
+mod text;
+mod draw_order;
+mod msaa;
+mod post_process;
+mod background;
+mod video;
+mod shaders;
+mod render_cache;
+mod renderer;
+mod headless;
+mod hit_circle;
+mod capture;
+mod frame_pacing;
+mod gpu;
+mod profiler;
+mod timing_overlay;
+
+use winit::{event::*,
+            event_loop::{ControlFlow, EventLoop},
+            window::WindowBuilder,
+            dpi::{PhysicalSize, Size},
 };
-use bytemuck;
-use env_logger;
-use pollster;
-use wgpu::util::DeviceExt;
 
 
 #[cfg(target_arch="wasm32")]
@@ -39,6 +61,7 @@ use crate::state::State;
 //     2, 3, 4,
 // ];
 
+#[allow(dead_code)]
 struct Camera {
     eye: cgmath::Point3<f32>,
     target: cgmath::Point3<f32>,
@@ -49,6 +72,7 @@ struct Camera {
     zfar: f32,
 }
 
+#[allow(dead_code)]
 impl Camera {
     fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         // 1.
@@ -57,7 +81,7 @@ impl Camera {
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
         // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
 }
 
@@ -111,68 +135,89 @@ pub async fn run() {
             .expect("Couldn't append canvas to document body.");
     }
 
-    let mut state = State::new(window).await;
-    //let mut audio_stream_manager = audio::AudioStreamManager::from_file("res/morse.wav").unwrap();
+    let mut state = match State::new(window).await {
+        Ok(state) => state,
+        Err(error) => {
+            log::error!("Couldn't set up graphics: {}", error);
+            eprintln!("{}", error.user_message());
+            return;
+        }
+    };
+    // A beatmap, or music to play along to, can be given on the command line
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = std::env::args().nth(1) {
+        state.open(&path);
+    }
+    state.start_play();
 
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == state.window.id() =>  if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
-                        input: KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-
-                    WindowEvent::KeyboardInput {
-                        input,
-                        device_id,
-                        is_synthetic
-                    } => {
-                        if (keyevent.input(event))
-                        //audio_stream_manager.play().unwrap();
-                    }
-                    
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
+            } if window_id == state.window().id() && !state.input(event) => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
 
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
-                    }
+                WindowEvent::Resized(physical_size) => {
+                    state.resize(*physical_size);
+                }
 
-                    _ => {}
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    state.resize(**new_inner_size);
                 }
+
+                _ => {}
+            },
+            Event::DeviceEvent { ref event, .. } => {
+                state.device_input(event);
             }
-            Event::RedrawRequested(window_id) if window_id == state.window.id() => {
+            Event::RedrawRequested(window_id) if window_id == state.window().id() => {
+                match state.recover_device() {
+                    Ok(true) => {}
+                    // Still waiting on the new device
+                    Ok(false) => return,
+                    Err(error) => {
+                        log::error!("Couldn't recreate the graphics device: {}", error);
+                        eprintln!("{}", error.user_message());
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
                 state.update();
-                match state.render() {
-                    Ok(_) => {}
-                    // Recreate the swap_chain if lost
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                    // The system is out of memory, we should probably quit
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+                if let Err(error) = state.render().or_else(|error| state.surface_error(error)) {
+                    log::error!("Couldn't draw a frame: {}", error);
+                    eprintln!("{}", error.user_message());
+                    *control_flow = ControlFlow::Exit;
                 }
             }
+            Event::LoopDestroyed => state.save_replay(),
             Event::MainEventsCleared => {
                 // RedrawRequested will only trigger once, unless we manually
-                // request it.
-                state.window.request_redraw();
+                // request it. The limiter holds it back until the next frame is due,
+                // waiting for events rather than sleeping so input is still stamped on arrival.
+                match state.frame_limiter.poll(std::time::Instant::now()) {
+                    None => {
+                        state.window.request_redraw();
+                        control_flow.set_poll();
+                    }
+                    Some(next_frame) => control_flow.set_wait_until(next_frame),
+                }
             }
             _ => {}
         }
     });
 }
 
-fn main() {
+/// Entry point of `wgpu_test_app`: the game, or `render ...` for offline captures.
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("render") {
+        env_logger::init();
+        if let Err(error) = capture::run(&args[1..]) {
+            eprintln!("{}\n{}", error, capture::USAGE);
+            std::process::exit(1);
+        }
+        return;
+    }
     pollster::block_on(run());
 }
-
@@ -0,0 +1,27 @@
+// Downsamples one mip level into the next with a linear filter
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+struct BlitOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> BlitOutput {
+    // Single triangle covering the target
+    var out: BlitOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: BlitOutput) -> @location(0) vec4<f32> {
+    // Explicit level: GL doesn't restrict sampling to the view's single mip
+    return textureSampleLevel(t_source, s_source, in.tex_coords, 0.0);
+}
@@ -0,0 +1,100 @@
+/// Sample counts that can be asked for, lowest first.
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+/// Highest of `supported` that doesn't exceed `requested`, or 1 (always supported) if there is none.
+pub fn select_sample_count(requested: u32, supported: &[u32]) -> u32 {
+    supported
+        .iter()
+        .copied()
+        .filter(|count| *count <= requested && SAMPLE_COUNTS.contains(count))
+        .max()
+        .unwrap_or(1)
+}
+
+/// Sample counts the adapter can render `format` with.
+pub fn supported_sample_counts(adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> Vec<u32> {
+    let flags = adapter.get_texture_format_features(format).flags;
+    SAMPLE_COUNTS
+        .iter()
+        .copied()
+        .filter(|count| flags.sample_count_supported(*count))
+        .collect()
+}
+
+pub fn multisample_state(sample_count: u32) -> wgpu::MultisampleState {
+    wgpu::MultisampleState {
+        count: sample_count,
+        ..Default::default()
+    }
+}
+
+/// Multisampled colour target that resolves into the frame. Without multisampling
+/// the frame is rendered to directly.
+pub struct MsaaTarget {
+    pub sample_count: u32,
+    view: Option<wgpu::TextureView>,
+}
+
+impl MsaaTarget {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32, sample_count: u32) -> Self {
+        let view = (sample_count > 1).then(|| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("MSAA Colour Target"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            });
+            texture.create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        MsaaTarget { sample_count, view }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) {
+        *self = MsaaTarget::new(device, format, width, height, self.sample_count);
+    }
+
+    /// Colour attachment drawing into `frame`, through the multisampled texture if there is one.
+    /// The samples themselves are discarded once resolved.
+    pub fn color_attachment<'a>(&'a self, frame: &'a wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>) -> wgpu::RenderPassColorAttachment<'a> {
+        match &self.view {
+            Some(view) => wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: Some(frame),
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Discard,
+                },
+            },
+            None => wgpu::RenderPassColorAttachment {
+                view: frame,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_sample_count() {
+        assert_eq!(select_sample_count(4, &[1, 2, 4, 8]), 4);
+        assert_eq!(select_sample_count(8, &[1, 4]), 4);
+        assert_eq!(select_sample_count(2, &[1, 4]), 1);
+        assert_eq!(select_sample_count(16, &[1, 2, 4, 8, 16]), 8);
+        assert_eq!(select_sample_count(0, &[]), 1);
+    }
+}
@@ -1,6 +1,3 @@
-use egui::Shape::LineSegment;
-use egui::TextBuffer;
-use std::collections::HashMap;
 use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
 
@@ -24,6 +21,10 @@ pub struct OsuCircle {
     pub x: f32,
     pub y: f32,
     pub time: u32,
+    /// Starts a new combo.
+    pub new_combo: bool,
+    /// Combo colours to skip when starting a new combo.
+    pub combo_skip: u32,
 }
 
 pub struct OsuSpinner {
@@ -40,6 +41,28 @@ pub struct OsuSlider {
     pub curve_points: Vec<(f32, f32)>,
     pub repeat: u32,
     pub pixel_length: f32,
+    pub new_combo: bool,
+    pub combo_skip: u32,
+}
+
+impl OsuSlider {
+    /// The circle that starts the slider.
+    pub fn head(&self) -> OsuCircle {
+        OsuCircle {
+            x: self.x,
+            y: self.y,
+            time: self.time,
+            new_combo: self.new_combo,
+            combo_skip: self.combo_skip,
+        }
+    }
+}
+
+/// Video shown behind the playfield instead of the background image.
+pub struct OsuVideo {
+    pub filename: String,
+    /// Gameplay time the video starts at, in milliseconds.
+    pub start_time: i32,
 }
 
 pub enum TimingPoint {
@@ -47,6 +70,8 @@ pub enum TimingPoint {
     Uninherited(UninheritedTimingPoint),
 }
 
+// Every field of the line is kept, whether or not anything plays it back yet
+#[allow(dead_code)]
 pub struct UninheritedTimingPoint {
     pub time: u32,
     pub bpm: f32,
@@ -57,6 +82,17 @@ pub struct UninheritedTimingPoint {
     pub effects: u32
 }
 
+impl TimingPoint {
+    pub fn time(&self) -> u32 {
+        match self {
+            TimingPoint::Inherited(inherited) => inherited.time,
+            TimingPoint::Uninherited(uninherited) => uninherited.time,
+        }
+    }
+}
+
+// Every field of the line is kept, whether or not anything plays it back yet
+#[allow(dead_code)]
 pub struct InheritedTimingPoint {
     pub time: u32,
     pub slider_multiplier: f32,
@@ -66,22 +102,63 @@ pub struct InheritedTimingPoint {
     pub effects: u32
 }
 
+/// Settings from the [Difficulty] section.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OsuDifficulty {
+    pub circle_size: f32,
+    pub approach_rate: f32,
+    pub overall_difficulty: f32,
+    /// Slider velocity in hundreds of osu!pixels per beat.
+    pub slider_multiplier: f32,
+}
+
+impl Default for OsuDifficulty {
+    fn default() -> Self {
+        OsuDifficulty {
+            circle_size: 5.0,
+            approach_rate: 5.0,
+            overall_difficulty: 5.0,
+            slider_multiplier: 1.4,
+        }
+    }
+}
+
+/// Where a hit object sits in the beatmap's combos.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Combo {
+    /// Number drawn on the object, counting from 1.
+    pub number: u32,
+    /// Index into the skin's combo colours.
+    pub colour: usize,
+}
+
 pub struct OsuMap {
     pub objects: BTreeMap<u64, OsuObject>,
-    pub timing_points: BTreeMap<u64, OsuObject>,
+    /// Ordered by time.
+    pub timing_points: Vec<TimingPoint>,
+    pub difficulty: OsuDifficulty,
     pub name: String,
     pub artist: String,
     pub creator: String,
+    /// Music file, relative to the beatmap's directory.
+    pub audio_filename: Option<String>,
+    /// Background image file, relative to the beatmap's directory.
+    pub background: Option<String>,
+    pub video: Option<OsuVideo>,
 }
 
 impl OsuMap {
     pub fn new() -> OsuMap {
         OsuMap {
             objects: BTreeMap::new(),
-            timing_points: BTreeMap::new(),
+            timing_points: Vec::new(),
+            difficulty: OsuDifficulty::default(),
             name: String::new(),
             artist: String::new(),
             creator: String::new(),
+            audio_filename: None,
+            background: None,
+            video: None,
         }
     }
 
@@ -99,9 +176,13 @@ impl OsuMap {
         let file = std::fs::read_to_string(file)?;
         let mut map = OsuMap::new();
         let mut mode = "";
+        let mut approach_rate = None;
         for line in file.lines() {
-            if sections.contains(line) {
-                mode = line;
+            let line = line.trim();
+            if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                if sections.contains(section) {
+                    mode = section;
+                }
                 continue;
             }
 
@@ -111,30 +192,67 @@ impl OsuMap {
                         map.objects.insert(object.time() as u64, object);
                     }
                 }
-                "TimingPoints" => {}
+                "General" | "Metadata" => {
+                    let Some((key, value)) = line.split_once(':') else {
+                        continue;
+                    };
+                    let value = value.trim().to_string();
+                    match key.trim() {
+                        "AudioFilename" => map.audio_filename = Some(value),
+                        "Title" => map.name = value,
+                        "Artist" => map.artist = value,
+                        "Creator" => map.creator = value,
+                        _ => {}
+                    }
+                }
+                "Events" => map.parse_event(line),
+                "TimingPoints" => map.timing_points.extend(Self::parse_timing_point(line)),
+                "Difficulty" => {
+                    let Some((key, value)) = line.split_once(':') else {
+                        continue;
+                    };
+                    let Ok(value) = value.trim().parse::<f32>() else {
+                        continue;
+                    };
+                    match key.trim() {
+                        "CircleSize" => map.difficulty.circle_size = value,
+                        "ApproachRate" => approach_rate = Some(value),
+                        "OverallDifficulty" => map.difficulty.overall_difficulty = value,
+                        "SliderMultiplier" => map.difficulty.slider_multiplier = value,
+                        _ => {}
+                    }
+                }
                 _ => {}
             }
         }
+        // Old beatmaps have no approach rate and use the overall difficulty
+        map.difficulty.approach_rate = approach_rate.unwrap_or(map.difficulty.overall_difficulty);
+        map.timing_points.sort_by_key(|point| point.time());
 
-        Err("Invalid file".into())
+        if map.objects.is_empty() {
+            return Err("Invalid file".into());
+        }
+        Ok(map)
     }
 
     fn parse_hit_object(line: &str) -> Option<OsuObject> {
-        let mut properties = line.split(",").into_iter();
+        let mut properties = line.split(",");
         let x = properties.next()?.parse::<f32>().ok()?;
         let y = properties.next()?.parse::<f32>().ok()?;
         let time = properties.next()?.parse::<u32>().ok()?;
-        let object_type = properties.next()?.parse::<u32>().ok()? & 0b1011;
+        let flags = properties.next()?.parse::<u32>().ok()?;
+        let new_combo = flags & 0b100 != 0;
+        let combo_skip = (flags >> 4) & 0b111;
 
-        match object_type {
-            1 => Some(OsuObject::Circle(OsuCircle { x, y, time })),
+        match flags & 0b1011 {
+            1 => Some(OsuObject::Circle(OsuCircle { x, y, time, new_combo, combo_skip })),
             2 => {
                 properties.next().unwrap();
                 let curve_data = properties.next().unwrap().split("|").collect::<Vec<&str>>();
                 let curve_type = curve_data[0];
 
                 let curve_points = curve_data[1..]
-                    .into_iter()
+                    .iter()
                     .map(|point| {
                         let mut point = point.split(":");
                         let x = point.next().unwrap().parse::<f32>().unwrap();
@@ -153,6 +271,8 @@ impl OsuMap {
                     curve_points,
                     repeat,
                     pixel_length,
+                    new_combo,
+                    combo_skip,
                 }))
             }
             8 => {
@@ -164,9 +284,76 @@ impl OsuMap {
         }
     }
 
-    //TODO
+    /// Picks up the background image and video; storyboard events are ignored.
+    fn parse_event(&mut self, line: &str) {
+        let mut properties = line.splitn(4, ',');
+        let (Some(event_type), Some(start_time), Some(filename)) = (properties.next(), properties.next(), properties.next()) else {
+            return;
+        };
+        let filename = filename.trim().trim_matches('"').to_string();
+        match event_type.trim() {
+            "0" | "Background" if self.background.is_none() => self.background = Some(filename),
+            "1" | "Video" if self.video.is_none() => {
+                if let Ok(start_time) = start_time.trim().parse::<i32>() {
+                    self.video = Some(OsuVideo { filename, start_time });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Combo number and colour of every object, in time order. Spinners start a new combo
+    /// and the object after one does too.
+    pub fn combos(&self) -> Vec<Combo> {
+        let mut combo = Combo { number: 0, colour: 0 };
+        let mut after_spinner = false;
+        self.objects
+            .values()
+            .enumerate()
+            .map(|(index, object)| {
+                let (new_combo, combo_skip) = match object {
+                    OsuObject::Circle(circle) => (circle.new_combo, circle.combo_skip),
+                    OsuObject::Slider(slider) => (slider.new_combo, slider.combo_skip),
+                    OsuObject::Spinner(_) => (true, 0),
+                };
+                if index == 0 {
+                    combo = Combo { number: 1, colour: combo_skip as usize };
+                } else if new_combo || after_spinner {
+                    combo = Combo { number: 1, colour: combo.colour + 1 + combo_skip as usize };
+                } else {
+                    combo.number += 1;
+                }
+                after_spinner = matches!(object, OsuObject::Spinner(_));
+                combo
+            })
+            .collect()
+    }
+
+    /// Milliseconds the ball takes to travel a slider's path once.
+    pub fn slider_span_duration(&self, slider: &OsuSlider) -> f32 {
+        let uninherited = self.timing_points.iter().filter_map(|point| match point {
+            TimingPoint::Uninherited(uninherited) => Some(uninherited),
+            _ => None,
+        });
+        // The first uninherited point also covers objects before it
+        let beat = uninherited.clone().take_while(|point| point.time <= slider.time).last().or(uninherited.clone().next());
+        let beat_length = beat.map_or(500.0, |beat| 60000.0 / beat.bpm);
+        let section_start = beat.map_or(0, |beat| beat.time);
+        let velocity = self
+            .timing_points
+            .iter()
+            .filter_map(|point| match point {
+                TimingPoint::Inherited(inherited) if (section_start..=slider.time).contains(&inherited.time) => Some(inherited),
+                _ => None,
+            })
+            .next_back()
+            .map_or(1.0, |inherited| inherited.slider_multiplier.clamp(0.1, 10.0));
+        let pixels_per_beat = self.difficulty.slider_multiplier * 100.0 * velocity;
+        slider.pixel_length / pixels_per_beat * beat_length
+    }
+
     fn parse_timing_point(line: &str) -> Option<TimingPoint> {
-        let mut properties = line.split(",").into_iter();
+        let mut properties = line.split(",");
         let time = properties.next()?.parse::<u32>().ok()?;
         let beat_length = properties.next()?.parse::<f32>().ok()?;
         let meter = properties.next()?.parse::<u32>().ok()?;
@@ -218,7 +405,7 @@ mod tests {
                 assert_eq!(slider.pixel_length, 105.0);
             }
             None => assert!(object.is_some()),
-            _ => assert!(false, "Expected slider, got something else")
+            _ => panic!("Expected slider, got something else")
         }
     }
     
@@ -233,7 +420,7 @@ mod tests {
                 assert_eq!(circle.time, 757);
             }
             None => assert!(object.is_some()),
-            _ => assert!(false, "Expected circle, got something else")
+            _ => panic!("Expected circle, got something else")
         }
     }
     
@@ -247,10 +434,80 @@ mod tests {
                 assert_eq!(spinner.end_time, 1000);
             }
             None => assert!(object.is_some()),
-            _ => assert!(false, "Expected spinner, got something else")
+            _ => panic!("Expected spinner, got something else")
         }
     }
     
+    #[test]
+    fn test_parse_events() {
+        let mut map = OsuMap::new();
+        map.parse_event("//Background and Video events");
+        map.parse_event("0,0,\"bg 1.jpg\",0,0");
+        map.parse_event("Video,-150,\"clip.mp4\"");
+        map.parse_event("Sprite,Foreground,Centre,\"sb/star.png\",320,240");
+        map.parse_event("0,0,\"other.jpg\",0,0");
+        assert_eq!(map.background.as_deref(), Some("bg 1.jpg"));
+        let video = map.video.unwrap();
+        assert_eq!((video.filename.as_str(), video.start_time), ("clip.mp4", -150));
+    }
+
+    #[test]
+    fn test_parse_general_and_metadata() {
+        let path = std::env::temp_dir().join("wgpu_test_metadata.osu");
+        let file = "osu file format v14\n\n[General]\nAudioFilename: audio.mp3\n\n[Metadata]\nTitle:Song\nArtist:Band\nCreator:Mapper\n\n[HitObjects]\n256,192,1000,1,0,0:0:0:0:\n";
+        std::fs::write(&path, file).unwrap();
+        let map = OsuMap::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(map.audio_filename.as_deref(), Some("audio.mp3"));
+        assert_eq!((map.name.as_str(), map.artist.as_str(), map.creator.as_str()), ("Song", "Band", "Mapper"));
+    }
+
+    fn circle(time: u32, new_combo: bool, combo_skip: u32) -> OsuObject {
+        OsuObject::Circle(OsuCircle { x: 0.0, y: 0.0, time, new_combo, combo_skip })
+    }
+
+    #[test]
+    fn test_parse_combo_flags() {
+        match OsuMap::parse_hit_object("339,109,757,37,0,0:0:0:0:") {
+            Some(OsuObject::Circle(circle)) => assert_eq!((circle.new_combo, circle.combo_skip), (true, 2)),
+            _ => panic!("Expected circle"),
+        }
+    }
+
+    #[test]
+    fn test_combos() {
+        let mut map = OsuMap::new();
+        map.objects.insert(0, circle(0, true, 0));
+        map.objects.insert(100, circle(100, false, 0));
+        map.objects.insert(200, circle(200, true, 2));
+        map.objects.insert(300, OsuObject::Spinner(OsuSpinner { time: 300, end_time: 400 }));
+        map.objects.insert(500, circle(500, false, 0));
+        let combos: Vec<(u32, usize)> = map.combos().iter().map(|combo| (combo.number, combo.colour)).collect();
+        assert_eq!(combos, vec![(1, 0), (2, 0), (1, 3), (1, 4), (1, 5)]);
+    }
+
+    #[test]
+    fn test_slider_span_duration() {
+        let mut map = OsuMap::new();
+        map.difficulty.slider_multiplier = 2.0;
+        map.timing_points.extend(OsuMap::parse_timing_point("1000,500,4,2,0,100,1,0"));
+        map.timing_points.extend(OsuMap::parse_timing_point("3000,-50,4,2,0,100,0,0"));
+        let slider = |time| OsuSlider {
+            x: 0.0,
+            y: 0.0,
+            time,
+            curve_type: "L".to_string(),
+            curve_points: vec![(400.0, 0.0)],
+            repeat: 1,
+            pixel_length: 400.0,
+            new_combo: false,
+            combo_skip: 0,
+        };
+        // 200 osu!pixels per beat, and twice that once the velocity doubles
+        assert_eq!(map.slider_span_duration(&slider(0)), 1000.0);
+        assert_eq!(map.slider_span_duration(&slider(2000)), 1000.0);
+        assert_eq!(map.slider_span_duration(&slider(3000)), 500.0);
+    }
+
     #[test]
     fn test_parse_timing_point_uninherited() {
         //TODO
@@ -267,7 +524,7 @@ mod tests {
                 assert_eq!(uninherited.effects, 0);
             }
             None => assert!(object.is_some()),
-            _ => assert!(false, "Expected uninherited, got something else")
+            _ => panic!("Expected uninherited, got something else")
         }
     }
     
@@ -286,7 +543,7 @@ mod tests {
                 assert_eq!(inherited.effects, 0);
             }
             None => assert!(object.is_some()),
-            _ => assert!(false, "Expected inherited, got something else")
+            _ => panic!("Expected inherited, got something else")
         }
     }
 }
@@ -0,0 +1,163 @@
+use crate::input::Rect;
+use crate::uniforms::PlayfieldUniform;
+
+/// Size of the beatmap coordinate space in osu!pixels.
+pub const PLAYFIELD_WIDTH: f32 = 512.0;
+pub const PLAYFIELD_HEIGHT: f32 = 384.0;
+
+/// Fraction of the window kept free around the playfield on each side.
+pub const DEFAULT_MARGIN: f32 = 0.1;
+
+/// Maps osu!pixels to window pixels and clip space, keeping the playfield at 4:3
+/// and letterboxing whatever is left of the window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Playfield {
+    pub window_width: f32,
+    pub window_height: f32,
+    pub margin: f32,
+    /// Window pixels per osu!pixel.
+    pub scale: f32,
+    /// Window position of the playfield's top-left corner.
+    pub offset: [f32; 2],
+}
+
+impl Playfield {
+    pub fn new(window_width: f32, window_height: f32) -> Playfield {
+        Self::with_margin(window_width, window_height, DEFAULT_MARGIN)
+    }
+
+    pub fn with_margin(window_width: f32, window_height: f32, margin: f32) -> Playfield {
+        let mut playfield = Playfield {
+            window_width,
+            window_height,
+            margin,
+            scale: 1.0,
+            offset: [0.0, 0.0],
+        };
+        playfield.resize(window_width, window_height);
+        playfield
+    }
+
+    pub fn resize(&mut self, window_width: f32, window_height: f32) {
+        self.window_width = window_width.max(1.0);
+        self.window_height = window_height.max(1.0);
+
+        let usable = 1.0 - 2.0 * self.margin;
+        self.scale = (self.window_width * usable / PLAYFIELD_WIDTH)
+            .min(self.window_height * usable / PLAYFIELD_HEIGHT);
+        self.offset = [
+            (self.window_width - PLAYFIELD_WIDTH * self.scale) / 2.0,
+            (self.window_height - PLAYFIELD_HEIGHT * self.scale) / 2.0,
+        ];
+    }
+
+    pub fn osu_to_window(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.offset[0] + x * self.scale, self.offset[1] + y * self.scale)
+    }
+
+    pub fn window_to_osu(&self, x: f32, y: f32) -> (f32, f32) {
+        ((x - self.offset[0]) / self.scale, (y - self.offset[1]) / self.scale)
+    }
+
+    pub fn osu_to_clip(&self, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = self.osu_to_window(x, y);
+        (2.0 * x / self.window_width - 1.0, 1.0 - 2.0 * y / self.window_height)
+    }
+
+    pub fn clip_to_osu(&self, x: f32, y: f32) -> (f32, f32) {
+        self.window_to_osu((x + 1.0) * self.window_width / 2.0, (1.0 - y) * self.window_height / 2.0)
+    }
+
+    /// Length of `osu_pixels` in window pixels.
+    pub fn scale_length(&self, osu_pixels: f32) -> f32 {
+        osu_pixels * self.scale
+    }
+
+    /// Whether a circle of `radius` osu!pixels around `(x, y)` reaches into the window.
+    pub fn is_circle_visible(&self, x: f32, y: f32, radius: f32) -> bool {
+        let (left, top) = self.clip_to_osu(-1.0, 1.0);
+        let (right, bottom) = self.clip_to_osu(1.0, -1.0);
+        x + radius >= left && x - radius <= right && y + radius >= top && y - radius <= bottom
+    }
+
+    /// The playfield's area in window pixels.
+    pub fn window_rect(&self) -> Rect {
+        Rect::new(
+            self.offset[0] as f64,
+            self.offset[1] as f64,
+            self.scale_length(PLAYFIELD_WIDTH) as f64,
+            self.scale_length(PLAYFIELD_HEIGHT) as f64,
+        )
+    }
+
+    /// Affine osu!pixel -> clip transform for the shader (`clip = pos * scale + offset`).
+    /// Clip space has y pointing up while osu!pixels have it pointing down.
+    pub fn uniform(&self) -> PlayfieldUniform {
+        let origin = self.osu_to_clip(0.0, 0.0);
+        let unit = self.osu_to_clip(1.0, 1.0);
+        PlayfieldUniform {
+            scale: [unit.0 - origin.0, unit.1 - origin.1],
+            offset: [origin.0, origin.1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: (f32, f32), b: (f32, f32)) {
+        assert!((a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_letterbox_wide_window() {
+        let playfield = Playfield::with_margin(1920.0, 1080.0, 0.0);
+        assert_eq!(playfield.scale, 1080.0 / 384.0);
+        assert_close(playfield.osu_to_window(0.0, 0.0), (240.0, 0.0));
+        assert_close(playfield.osu_to_window(512.0, 384.0), (1680.0, 1080.0));
+    }
+
+    #[test]
+    fn test_letterbox_tall_window() {
+        let playfield = Playfield::with_margin(512.0, 1000.0, 0.0);
+        assert_eq!(playfield.scale, 1.0);
+        assert_close(playfield.osu_to_window(0.0, 0.0), (0.0, 308.0));
+    }
+
+    #[test]
+    fn test_margin() {
+        let playfield = Playfield::with_margin(800.0, 600.0, 0.1);
+        assert_close(playfield.osu_to_window(0.0, 0.0), (80.0, 60.0));
+        assert_close(playfield.osu_to_window(512.0, 384.0), (720.0, 540.0));
+    }
+
+    #[test]
+    fn test_osu_to_clip() {
+        let playfield = Playfield::with_margin(1024.0, 768.0, 0.0);
+        assert_close(playfield.osu_to_clip(0.0, 0.0), (-1.0, 1.0));
+        assert_close(playfield.osu_to_clip(512.0, 384.0), (1.0, -1.0));
+        assert_close(playfield.osu_to_clip(256.0, 192.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_inverse_transforms() {
+        let playfield = Playfield::new(1366.0, 705.0);
+        for point in [(0.0, 0.0), (512.0, 384.0), (100.5, 300.25), (-20.0, 400.0)] {
+            let window = playfield.osu_to_window(point.0, point.1);
+            assert_close(playfield.window_to_osu(window.0, window.1), point);
+            let clip = playfield.osu_to_clip(point.0, point.1);
+            assert_close(playfield.clip_to_osu(clip.0, clip.1), point);
+        }
+    }
+
+    #[test]
+    fn test_circle_visibility() {
+        let playfield = Playfield::with_margin(1024.0, 768.0, 0.1);
+        // The margin shows a bit more than the playfield around it
+        assert!(playfield.is_circle_visible(-50.0, 192.0, 0.0));
+        assert!(!playfield.is_circle_visible(-100.0, 192.0, 10.0));
+        assert!(playfield.is_circle_visible(-100.0, 192.0, 50.0));
+        assert!(!playfield.is_circle_visible(256.0, 500.0, 10.0));
+    }
+}
@@ -0,0 +1,426 @@
+use crate::msaa::MsaaTarget;
+use crate::profiler::GpuProfiler;
+use crate::render_cache::{BindGroupLayoutId, PipelineDescriptor, PipelineId, RenderCache};
+use crate::renderer::TargetDescriptor;
+
+/// Strongest background blur, as a Gaussian sigma in half-resolution pixels.
+const MAX_BLUR_SIGMA: f32 = 10.0;
+/// Spread of the bloom glow, in half-resolution pixels.
+const BLOOM_SIGMA: f32 = 6.0;
+
+/// Player settings for the post-processing stage.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PostProcessSettings {
+    /// 0 leaves the background as is, 1 turns it black.
+    pub background_dim: f32,
+    /// 0 is sharp, 1 the strongest blur.
+    pub background_blur: f32,
+    /// Strength of the glow around additive sprites, 0 turns bloom off.
+    pub bloom_intensity: f32,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        PostProcessSettings {
+            background_dim: 0.0,
+            background_blur: 0.0,
+            bloom_intensity: 0.0,
+        }
+    }
+}
+
+impl PostProcessSettings {
+    /// Whether the frame has to go through the post-processing passes at all.
+    pub fn is_active(&self) -> bool {
+        self.background_dim > 0.0 || self.background_blur > 0.0 || self.bloom_enabled()
+    }
+
+    pub fn bloom_enabled(&self) -> bool {
+        self.bloom_intensity > 0.0
+    }
+
+    pub fn blur_sigma(&self) -> f32 {
+        self.background_blur.clamp(0.0, 1.0) * MAX_BLUR_SIGMA
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurUniform {
+    direction: [f32; 2],
+    sigma: f32,
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CompositeUniform {
+    background_dim: f32,
+    bloom_intensity: f32,
+    _padding: [f32; 2],
+}
+
+/// One direction of a separable blur, rendered into its own target.
+struct BlurPass {
+    target: wgpu::TextureView,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// One texel of the target along the blur axis, in UV units.
+    direction: [f32; 2],
+}
+
+/// Intermediate render targets, all recreated on resize.
+struct Targets {
+    background: wgpu::TextureView,
+    scene: wgpu::TextureView,
+    bloom: wgpu::TextureView,
+    /// Additive sprites are drawn again into `bloom` with the main pass's pipelines, so it needs the same sample count.
+    bloom_msaa: MsaaTarget,
+    /// Horizontal then vertical, at half resolution.
+    background_blur: [BlurPass; 2],
+    bloom_blur: [BlurPass; 2],
+    /// Composites over the sharp background, then over the blurred one.
+    composite_bind_groups: [wgpu::BindGroup; 2],
+}
+
+/// Chain of fullscreen passes between the main pass and the frame: the background is drawn
+/// on its own so it can be blurred and dimmed, the scene is drawn over transparency, and
+/// additive sprites are drawn a second time to blur into bloom. A composite pass puts them together.
+pub struct PostProcess {
+    pub settings: PostProcessSettings,
+    format: wgpu::TextureFormat,
+    blur_pipeline: PipelineId,
+    blur_bind_group_layout: BindGroupLayoutId,
+    composite_pipeline: PipelineId,
+    composite_bind_group_layout: BindGroupLayoutId,
+    composite_uniform: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    targets: Targets,
+}
+
+impl PostProcess {
+    pub fn new(device: &wgpu::Device, cache: &mut RenderCache, format: wgpu::TextureFormat, width: u32, height: u32, sample_count: u32) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let blur_bind_group_layout = cache.bind_group_layout_id(device, &[texture_entry(0), sampler_entry(1), uniform_entry(2)]);
+        let composite_bind_group_layout = cache.bind_group_layout_id(
+            device,
+            &[texture_entry(0), texture_entry(1), texture_entry(2), sampler_entry(3), uniform_entry(4)],
+        );
+
+        let mut pipeline = |bind_group_layout, entry_point| {
+            let descriptor = PipelineDescriptor::new("post_process.wgsl", &[bind_group_layout], format)
+                .with_entry_points("vs_main", entry_point)
+                .with_blend(None);
+            cache.pipeline_id(device, &descriptor)
+        };
+        let blur_pipeline = pipeline(blur_bind_group_layout, "fs_blur");
+        let composite_pipeline = pipeline(composite_bind_group_layout, "fs_composite");
+
+        let composite_uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Composite Uniform Buffer"),
+            size: std::mem::size_of::<CompositeUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Process Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let targets = Targets::new(
+            device,
+            TargetDescriptor { format, width, height, sample_count },
+            cache.bind_group_layout(blur_bind_group_layout),
+            cache.bind_group_layout(composite_bind_group_layout),
+            &sampler,
+            &composite_uniform,
+        );
+
+        Self {
+            settings: PostProcessSettings::default(),
+            format,
+            blur_pipeline,
+            blur_bind_group_layout,
+            composite_pipeline,
+            composite_bind_group_layout,
+            composite_uniform,
+            sampler,
+            targets,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, cache: &RenderCache, width: u32, height: u32) {
+        let target = TargetDescriptor { format: self.format, width, height, sample_count: self.targets.bloom_msaa.sample_count };
+        self.targets = Targets::new(
+            device,
+            target,
+            cache.bind_group_layout(self.blur_bind_group_layout),
+            cache.bind_group_layout(self.composite_bind_group_layout),
+            &self.sampler,
+            &self.composite_uniform,
+        );
+    }
+
+    /// Uploads this frame's settings.
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.composite_uniform,
+            0,
+            bytemuck::cast_slice(&[CompositeUniform {
+                background_dim: self.settings.background_dim.clamp(0.0, 1.0),
+                bloom_intensity: self.settings.bloom_intensity.max(0.0),
+                _padding: [0.0; 2],
+            }]),
+        );
+        let chains = [
+            (&self.targets.background_blur, self.settings.blur_sigma()),
+            (&self.targets.bloom_blur, BLOOM_SIGMA),
+        ];
+        for (chain, sigma) in chains {
+            for pass in chain {
+                queue.write_buffer(
+                    &pass.uniform,
+                    0,
+                    bytemuck::cast_slice(&[BlurUniform {
+                        direction: pass.direction,
+                        sigma,
+                        _padding: 0.0,
+                    }]),
+                );
+            }
+        }
+    }
+
+    /// Where the background goes, cleared to `clear_color`.
+    pub fn background_attachment(&self, clear_color: wgpu::Color) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: &self.targets.background,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear_color),
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+
+    /// Where the main pass resolves to instead of the frame. It has to be cleared to transparent.
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.targets.scene
+    }
+
+    /// Where additive sprites are drawn again for bloom, with the main pass's sample count.
+    pub fn bloom_attachment(&self) -> wgpu::RenderPassColorAttachment<'_> {
+        self.targets.bloom_msaa.color_attachment(&self.targets.bloom, wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT))
+    }
+
+    /// Blurs what needs blurring and composites everything into `frame`.
+    pub fn apply(&self, encoder: &mut wgpu::CommandEncoder, cache: &RenderCache, profiler: &mut GpuProfiler, frame: &wgpu::TextureView) {
+        let blurred = self.settings.blur_sigma() > 0.0;
+        if blurred {
+            self.blur(encoder, cache, profiler, &self.targets.background_blur);
+        }
+        if self.settings.bloom_enabled() {
+            self.blur(encoder, cache, profiler, &self.targets.bloom_blur);
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Composite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: frame,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: profiler.timestamp_writes("Composite Pass"),
+        });
+        render_pass.set_pipeline(cache.pipeline(self.composite_pipeline));
+        render_pass.set_bind_group(0, &self.targets.composite_bind_groups[blurred as usize], &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn blur(&self, encoder: &mut wgpu::CommandEncoder, cache: &RenderCache, profiler: &mut GpuProfiler, chain: &[BlurPass; 2]) {
+        for pass in chain {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Blur Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &pass.target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: profiler.timestamp_writes("Blur Pass"),
+            });
+            render_pass.set_pipeline(cache.pipeline(self.blur_pipeline));
+            render_pass.set_bind_group(0, &pass.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+impl Targets {
+    fn new(
+        device: &wgpu::Device,
+        target: TargetDescriptor,
+        blur_bind_group_layout: &wgpu::BindGroupLayout,
+        composite_bind_group_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        composite_uniform: &wgpu::Buffer,
+    ) -> Self {
+        let TargetDescriptor { format, width, height, sample_count } = target;
+        let background = create_target(device, "Background Target", format, width, height);
+        let scene = create_target(device, "Scene Target", format, width, height);
+        let bloom = create_target(device, "Bloom Target", format, width, height);
+        let bloom_msaa = MsaaTarget::new(device, format, width, height, sample_count);
+
+        // Blurring at half resolution is cheaper and, being blurry, looks the same
+        let (blur_width, blur_height) = ((width / 2).max(1), (height / 2).max(1));
+        let blur_pass = |source: &wgpu::TextureView, direction: [f32; 2]| {
+            let target = create_target(device, "Blur Target", format, blur_width, blur_height);
+            let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Blur Uniform Buffer"),
+                size: std::mem::size_of::<BlurUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Blur Bind Group"),
+                layout: blur_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform.as_entire_binding(),
+                    },
+                ],
+            });
+            BlurPass { target, uniform, bind_group, direction }
+        };
+        let blur_chain = |source: &wgpu::TextureView| {
+            let horizontal = blur_pass(source, [1.0 / blur_width as f32, 0.0]);
+            let vertical = blur_pass(&horizontal.target, [0.0, 1.0 / blur_height as f32]);
+            [horizontal, vertical]
+        };
+        let background_blur = blur_chain(&background);
+        let bloom_blur = blur_chain(&bloom);
+
+        let composite_bind_group = |background: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Composite Bind Group"),
+                layout: composite_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(background),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&scene),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&bloom_blur[1].target),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: composite_uniform.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let composite_bind_groups = [composite_bind_group(&background), composite_bind_group(&background_blur[1].target)];
+
+        Targets {
+            background,
+            scene,
+            bloom,
+            bloom_msaa,
+            background_blur,
+            bloom_blur,
+            composite_bind_groups,
+        }
+    }
+}
+
+/// Single-sampled colour target that fullscreen passes can read from.
+fn create_target(device: &wgpu::Device, label: &str, format: wgpu::TextureFormat, width: u32, height: u32) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        let mut settings = PostProcessSettings::default();
+        assert!(!settings.is_active());
+        settings.background_blur = 2.0;
+        assert!(settings.is_active() && !settings.bloom_enabled());
+        assert_eq!(settings.blur_sigma(), MAX_BLUR_SIGMA);
+        settings = PostProcessSettings { bloom_intensity: 0.5, ..Default::default() };
+        assert!(settings.is_active() && settings.bloom_enabled());
+        assert_eq!(settings.blur_sigma(), 0.0);
+    }
+}
@@ -0,0 +1,74 @@
+// Fullscreen passes run after the main pass: separable Gaussian blur and the final composite
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    // Single triangle covering the target
+    var out: FullscreenOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+struct BlurUniform {
+    // One texel of the target along the blur axis, in UV units
+    direction: vec2<f32>,
+    sigma: f32,
+};
+
+@group(0) @binding(0)
+var t_blur: texture_2d<f32>;
+@group(0) @binding(1)
+var s_blur: sampler;
+@group(0) @binding(2)
+var<uniform> blur: BlurUniform;
+
+// Sigmas past 10 would need more taps than this
+const MAX_BLUR_RADIUS: i32 = 32;
+
+@fragment
+fn fs_blur(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    if blur.sigma <= 0.0 {
+        return textureSampleLevel(t_blur, s_blur, in.tex_coords, 0.0);
+    }
+    let radius = min(i32(ceil(blur.sigma * 3.0)), MAX_BLUR_RADIUS);
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let offset = f32(i);
+        let weight = exp(-offset * offset / (2.0 * blur.sigma * blur.sigma));
+        sum += textureSampleLevel(t_blur, s_blur, in.tex_coords + blur.direction * offset, 0.0) * weight;
+        total += weight;
+    }
+    return sum / total;
+}
+
+struct CompositeUniform {
+    background_dim: f32,
+    bloom_intensity: f32,
+};
+
+@group(0) @binding(0)
+var t_background: texture_2d<f32>;
+@group(0) @binding(1)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(2)
+var t_bloom: texture_2d<f32>;
+@group(0) @binding(3)
+var s_composite: sampler;
+@group(0) @binding(4)
+var<uniform> composite: CompositeUniform;
+
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let background = textureSampleLevel(t_background, s_composite, in.tex_coords, 0.0).rgb * (1.0 - composite.background_dim);
+    // The scene is premultiplied over a transparent clear
+    let scene = textureSampleLevel(t_scene, s_composite, in.tex_coords, 0.0);
+    let bloom = textureSampleLevel(t_bloom, s_composite, in.tex_coords, 0.0).rgb * composite.bloom_intensity;
+    return vec4<f32>(scene.rgb + background * (1.0 - scene.a) + bloom, 1.0);
+}
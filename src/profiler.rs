@@ -0,0 +1,244 @@
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+use crate::frame_pacing::RollingStats;
+
+/// Samples kept per timed section.
+const TIMINGS_WINDOW: usize = 240;
+/// Passes that can be timed in one frame; later ones go untimed.
+const MAX_TIMED_PASSES: usize = 16;
+/// Frames whose timestamps can be waiting to be read back at once.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+/// Rolling statistics of named sections in milliseconds, in the order they were first recorded.
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    sections: Vec<(&'static str, RollingStats)>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Timings::default()
+    }
+
+    pub fn record(&mut self, section: &'static str, elapsed: Duration) {
+        self.record_ms(section, elapsed.as_secs_f64() * 1000.0);
+    }
+
+    pub fn record_ms(&mut self, section: &'static str, ms: f64) {
+        match self.sections.iter_mut().find(|(name, _)| *name == section) {
+            Some((_, stats)) => stats.push(ms),
+            None => {
+                let mut stats = RollingStats::new(TIMINGS_WINDOW);
+                stats.push(ms);
+                self.sections.push((section, stats));
+            }
+        }
+    }
+
+    pub fn sections(&self) -> &[(&'static str, RollingStats)] {
+        &self.sections
+    }
+}
+
+/// Timings shown in the profiling overlay besides the renderer's own pass timings.
+#[derive(Copy, Clone, Debug)]
+pub struct Profile<'a> {
+    pub frame_times: &'a RollingStats,
+    pub cpu: &'a Timings,
+}
+
+/// Readback of one frame's timestamps.
+struct FrameQueries {
+    readback: wgpu::Buffer,
+    /// Pass of each begin/end timestamp pair.
+    labels: Vec<&'static str>,
+    /// Set while the readback is being mapped.
+    mapping: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+/// Times render passes on the GPU with timestamp queries, when the device supports them.
+/// Results are read back a few frames late so the CPU never waits on the GPU.
+pub struct GpuProfiler {
+    queries: Option<(wgpu::QuerySet, wgpu::Buffer)>,
+    frames: Vec<FrameQueries>,
+    current: usize,
+    /// Whether the current frame's readback is free to record into.
+    recording: bool,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Milliseconds each pass took, passes with the same label summed per frame.
+    pub passes: Timings,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let enabled = device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        let size = (MAX_TIMED_PASSES * 2 * std::mem::size_of::<u64>()) as u64;
+        let queries = enabled.then(|| {
+            let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: (MAX_TIMED_PASSES * 2) as u32,
+            });
+            let resolve = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            (query_set, resolve)
+        });
+        let frames = if enabled {
+            (0..FRAMES_IN_FLIGHT)
+                .map(|_| FrameQueries {
+                    readback: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Timestamp Readback Buffer"),
+                        size,
+                        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                        mapped_at_creation: false,
+                    }),
+                    labels: Vec::new(),
+                    mapping: None,
+                })
+                .collect()
+        } else {
+            log::info!("Timestamp queries are not supported, GPU passes won't be timed");
+            Vec::new()
+        };
+        GpuProfiler {
+            queries,
+            frames,
+            current: 0,
+            recording: false,
+            period: queue.get_timestamp_period(),
+            passes: Timings::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.queries.is_some()
+    }
+
+    /// Collects finished readbacks and starts recording a frame if a readback is free.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        if !self.is_enabled() {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        for frame in &mut self.frames {
+            let Some(mapping) = &frame.mapping else {
+                continue;
+            };
+            match mapping.try_recv() {
+                Err(TryRecvError::Empty) => continue,
+                Ok(Ok(())) => {
+                    let durations = {
+                        let data = frame.readback.slice(..).get_mapped_range();
+                        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                        pass_durations(&timestamps[..frame.labels.len() * 2], self.period)
+                    };
+                    frame.readback.unmap();
+                    let mut totals: Vec<(&'static str, f64)> = Vec::new();
+                    for (label, ms) in frame.labels.iter().zip(durations) {
+                        match totals.iter_mut().find(|(name, _)| name == label) {
+                            Some((_, total)) => *total += ms,
+                            None => totals.push((label, ms)),
+                        }
+                    }
+                    for (label, ms) in totals {
+                        self.passes.record_ms(label, ms);
+                    }
+                }
+                Ok(Err(error)) => log::warn!("Couldn't read back pass timestamps: {}", error),
+                Err(TryRecvError::Disconnected) => {}
+            }
+            frame.mapping = None;
+        }
+        let frame = &mut self.frames[self.current];
+        frame.labels.clear();
+        self.recording = frame.mapping.is_none();
+    }
+
+    /// Timestamp writes for a pass called `label`, `None` if it can't be timed.
+    pub fn timestamp_writes(&mut self, label: &'static str) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let (query_set, _) = self.queries.as_ref()?;
+        let labels = &mut self.frames[self.current].labels;
+        if !self.recording || labels.len() == MAX_TIMED_PASSES {
+            return None;
+        }
+        let index = labels.len() as u32 * 2;
+        labels.push(label);
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// Copies this frame's timestamps to its readback. Must be the last thing recorded into `encoder`.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some((query_set, resolve)) = &self.queries else {
+            return;
+        };
+        let frame = &self.frames[self.current];
+        if !self.recording || frame.labels.is_empty() {
+            return;
+        }
+        let count = frame.labels.len() as u32 * 2;
+        encoder.resolve_query_set(query_set, 0..count, resolve, 0);
+        encoder.copy_buffer_to_buffer(resolve, 0, &frame.readback, 0, count as u64 * std::mem::size_of::<u64>() as u64);
+    }
+
+    /// Starts reading back this frame's timestamps. Call once `resolve`'s encoder is submitted.
+    pub fn end_frame(&mut self) {
+        if !self.recording {
+            return;
+        }
+        let frame = &mut self.frames[self.current];
+        if !frame.labels.is_empty() {
+            let (sender, receiver) = mpsc::channel();
+            frame.readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                sender.send(result).ok();
+            });
+            frame.mapping = Some(receiver);
+        }
+        self.recording = false;
+        self.current = (self.current + 1) % self.frames.len();
+    }
+}
+
+/// Milliseconds between each begin and end timestamp pair, given `period` nanoseconds per tick.
+fn pass_durations(timestamps: &[u64], period: f32) -> Vec<f64> {
+    timestamps
+        .chunks_exact(2)
+        .map(|pair| pair[1].saturating_sub(pair[0]) as f64 * period as f64 / 1_000_000.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_durations() {
+        let durations = pass_durations(&[1_000, 3_000_000, 4_000_000, 4_500_000, 9, 5], 1.0);
+        assert_eq!(durations.len(), 3);
+        assert!((durations[0] - 2.999).abs() < 1e-9);
+        assert!((durations[1] - 0.5).abs() < 1e-9);
+        // Timestamps that went backwards count as zero rather than wrapping
+        assert_eq!(durations[2], 0.0);
+        assert!((pass_durations(&[0, 1_000], 83.333)[0] - 0.083333).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_timings() {
+        let mut timings = Timings::new();
+        timings.record("Update", Duration::from_millis(2));
+        timings.record_ms("Audio", 0.5);
+        timings.record("Update", Duration::from_millis(4));
+        let names: Vec<&str> = timings.sections().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["Update", "Audio"]);
+        assert_eq!(timings.sections()[0].1.average(), Some(3.0));
+        assert_eq!(timings.sections()[1].1.len(), 1);
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::mipmap::MipmapGenerator;
+use crate::msaa;
+use crate::shaders::{self, ShaderRegistry};
+use crate::texture::Texture;
+
+/// Handle to a bind group layout created by a `RenderCache`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BindGroupLayoutId(usize);
+
+/// Handle to a render pipeline created by a `RenderCache`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineId(usize);
+
+/// Owned `wgpu::VertexBufferLayout`, so it can be part of a key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    pub array_stride: wgpu::BufferAddress,
+    pub step_mode: wgpu::VertexStepMode,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl From<wgpu::VertexBufferLayout<'_>> for VertexLayout {
+    fn from(layout: wgpu::VertexBufferLayout<'_>) -> Self {
+        VertexLayout {
+            array_stride: layout.array_stride,
+            step_mode: layout.step_mode,
+            attributes: layout.attributes.to_vec(),
+        }
+    }
+}
+
+impl VertexLayout {
+    fn as_wgpu(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: self.step_mode,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+/// Everything that tells two render pipelines apart. Pipelines are drawn as triangle lists
+/// into a single colour target.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineDescriptor {
+    /// Shader file name, as registered with the `ShaderRegistry`.
+    pub shader: &'static str,
+    pub vertex_entry: &'static str,
+    pub fragment_entry: &'static str,
+    pub vertex_layouts: Vec<VertexLayout>,
+    pub bind_group_layouts: Vec<BindGroupLayoutId>,
+    pub format: wgpu::TextureFormat,
+    pub blend: Option<wgpu::BlendState>,
+    pub sample_count: u32,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+}
+
+impl PipelineDescriptor {
+    /// A `vs_main`/`fs_main` pipeline without vertex buffers, premultiplied alpha blending or depth, single-sampled.
+    pub fn new(shader: &'static str, bind_group_layouts: &[BindGroupLayoutId], format: wgpu::TextureFormat) -> Self {
+        PipelineDescriptor {
+            shader,
+            vertex_entry: "vs_main",
+            fragment_entry: "fs_main",
+            vertex_layouts: Vec::new(),
+            bind_group_layouts: bind_group_layouts.to_vec(),
+            format,
+            blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            sample_count: 1,
+            depth_stencil: None,
+        }
+    }
+
+    pub fn with_entry_points(mut self, vertex: &'static str, fragment: &'static str) -> Self {
+        self.vertex_entry = vertex;
+        self.fragment_entry = fragment;
+        self
+    }
+
+    pub fn with_vertex_layouts(mut self, layouts: &[wgpu::VertexBufferLayout<'_>]) -> Self {
+        self.vertex_layouts = layouts.iter().cloned().map(VertexLayout::from).collect();
+        self
+    }
+
+    pub fn with_blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn with_depth_stencil(mut self, depth_stencil: wgpu::DepthStencilState) -> Self {
+        self.depth_stencil = Some(depth_stencil);
+        self
+    }
+}
+
+/// Identifies a texture bind group: the same texture bound through the same layout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct TextureBindGroupKey {
+    layout: BindGroupLayoutId,
+    texture: u64,
+}
+
+/// Creates GPU objects on first use and hands out the existing one for an equal descriptor
+/// afterwards, so renderers sharing a shader, layout and target share pipelines.
+/// Also owns the shader registry and rebuilds the pipelines of shaders edited on disk.
+pub struct RenderCache {
+    pub shaders: ShaderRegistry,
+    modules: HashMap<&'static str, wgpu::ShaderModule>,
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    bind_group_layout_ids: HashMap<Vec<wgpu::BindGroupLayoutEntry>, BindGroupLayoutId>,
+    pipeline_layouts: HashMap<Vec<BindGroupLayoutId>, wgpu::PipelineLayout>,
+    pipelines: Vec<(PipelineDescriptor, wgpu::RenderPipeline)>,
+    pipeline_ids: HashMap<PipelineDescriptor, PipelineId>,
+    texture_bind_groups: HashMap<TextureBindGroupKey, Rc<wgpu::BindGroup>>,
+    mipmap_generators: HashMap<wgpu::TextureFormat, MipmapGenerator>,
+}
+
+impl RenderCache {
+    pub fn new(shaders: ShaderRegistry) -> Self {
+        RenderCache {
+            shaders,
+            modules: HashMap::new(),
+            bind_group_layouts: Vec::new(),
+            bind_group_layout_ids: HashMap::new(),
+            pipeline_layouts: HashMap::new(),
+            pipelines: Vec::new(),
+            pipeline_ids: HashMap::new(),
+            texture_bind_groups: HashMap::new(),
+            mipmap_generators: HashMap::new(),
+        }
+    }
+
+    pub fn bind_group_layout_id(&mut self, device: &wgpu::Device, entries: &[wgpu::BindGroupLayoutEntry]) -> BindGroupLayoutId {
+        if let Some(id) = self.bind_group_layout_ids.get(entries) {
+            return *id;
+        }
+        let id = BindGroupLayoutId(self.bind_group_layouts.len());
+        self.bind_group_layouts.push(device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("Cached Bind Group Layout {}", id.0)),
+            entries,
+        }));
+        self.bind_group_layout_ids.insert(entries.to_vec(), id);
+        id
+    }
+
+    /// Layout of a fragment-stage filterable 2D texture at binding 0 and its sampler at binding 1.
+    pub fn texture_layout_id(&mut self, device: &wgpu::Device) -> BindGroupLayoutId {
+        self.bind_group_layout_id(device, &texture_layout_entries())
+    }
+
+    pub fn bind_group_layout(&self, id: BindGroupLayoutId) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layouts[id.0]
+    }
+
+    pub fn pipeline_id(&mut self, device: &wgpu::Device, descriptor: &PipelineDescriptor) -> PipelineId {
+        if let Some(id) = self.pipeline_ids.get(descriptor) {
+            return *id;
+        }
+        if !self.modules.contains_key(descriptor.shader) {
+            let module = self.shaders.module(device, descriptor.shader);
+            self.modules.insert(descriptor.shader, module);
+        }
+        if !self.pipeline_layouts.contains_key(&descriptor.bind_group_layouts) {
+            let bind_group_layouts: Vec<&wgpu::BindGroupLayout> =
+                descriptor.bind_group_layouts.iter().map(|id| &self.bind_group_layouts[id.0]).collect();
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Cached Pipeline Layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+            self.pipeline_layouts.insert(descriptor.bind_group_layouts.clone(), layout);
+        }
+
+        let pipeline = create_pipeline(
+            device,
+            &self.modules[descriptor.shader],
+            &self.pipeline_layouts[&descriptor.bind_group_layouts],
+            descriptor,
+        );
+        let id = PipelineId(self.pipelines.len());
+        self.pipelines.push((descriptor.clone(), pipeline));
+        self.pipeline_ids.insert(descriptor.clone(), id);
+        id
+    }
+
+    pub fn pipeline(&self, id: PipelineId) -> &wgpu::RenderPipeline {
+        &self.pipelines[id.0].1
+    }
+
+    /// Bind group for `texture` through a `texture_layout_id` layout, shared by everyone binding the same texture.
+    pub fn texture_bind_group(&mut self, device: &wgpu::Device, texture: &Texture) -> Rc<wgpu::BindGroup> {
+        let layout = self.texture_layout_id(device);
+        let key = TextureBindGroupKey { layout, texture: texture.id };
+        self.texture_bind_groups
+            .entry(key)
+            .or_insert_with(|| {
+                Rc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Cached Texture Bind Group"),
+                    layout: &self.bind_group_layouts[layout.0],
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&texture.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                        },
+                    ],
+                }))
+            })
+            .clone()
+    }
+
+    /// Mipmap generator rendering to `format`, shared by every texture of that format.
+    pub fn mipmap_generator(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) -> &MipmapGenerator {
+        self.mipmap_generators.entry(format).or_insert_with(|| MipmapGenerator::new(device, format))
+    }
+
+    /// Drops texture bind groups nobody but the cache holds anymore, e.g. a previous map's background.
+    pub fn prune_bind_groups(&mut self) {
+        self.texture_bind_groups.retain(|_, bind_group| Rc::strong_count(bind_group) > 1);
+    }
+
+    /// Rebuilds the pipelines of shaders edited on disk. A shader whose pipelines fail keeps the old ones.
+    pub fn reload_shaders(&mut self, device: &wgpu::Device) {
+        for (name, module) in self.shaders.poll_changes(device) {
+            let affected: Vec<usize> = (0..self.pipelines.len()).filter(|index| self.pipelines[*index].0.shader == name).collect();
+            let rebuilt = shaders::checked(device, || {
+                affected
+                    .iter()
+                    .map(|index| {
+                        let descriptor = &self.pipelines[*index].0;
+                        create_pipeline(device, &module, &self.pipeline_layouts[&descriptor.bind_group_layouts], descriptor)
+                    })
+                    .collect::<Vec<_>>()
+            });
+            match rebuilt {
+                Ok(pipelines) => {
+                    for (index, pipeline) in affected.into_iter().zip(pipelines) {
+                        self.pipelines[index].1 = pipeline;
+                    }
+                    self.modules.insert(name, module);
+                    log::info!("Reloaded {}", name);
+                }
+                Err(error) => log::error!("{} failed to reload, keeping the old pipelines: {}", name, error),
+            }
+        }
+    }
+}
+
+fn texture_layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        },
+    ]
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    module: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    descriptor: &PipelineDescriptor,
+) -> wgpu::RenderPipeline {
+    let vertex_layouts: Vec<wgpu::VertexBufferLayout> = descriptor.vertex_layouts.iter().map(VertexLayout::as_wgpu).collect();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{} {} Pipeline", descriptor.shader, descriptor.fragment_entry)),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: descriptor.vertex_entry,
+            buffers: &vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: descriptor.fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format: descriptor.format,
+                blend: descriptor.blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: descriptor.depth_stencil.clone(),
+        multisample: msaa::multisample_state(descriptor.sample_count),
+        multiview: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessRenderer;
+    use crate::skin::Skin;
+
+    #[test]
+    fn test_equal_descriptors_share_objects() {
+        let Ok(headless) = pollster::block_on(HeadlessRenderer::new(16, 16, 1, Skin::new())) else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+        let device = &headless.renderer.device;
+        let mut cache = RenderCache::new(ShaderRegistry::with_directory(None));
+
+        let texture_layout = cache.texture_layout_id(device);
+        assert_eq!(cache.bind_group_layout_id(device, &texture_layout_entries()), texture_layout);
+        let uniform_layout = cache.bind_group_layout_id(
+            device,
+            &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        );
+        assert_ne!(uniform_layout, texture_layout);
+
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let descriptor = PipelineDescriptor::new("slider.wgsl", &[texture_layout], format).with_entry_points("vs_composite", "fs_composite");
+        let first = cache.pipeline_id(device, &descriptor);
+        assert_eq!(cache.pipeline_id(device, &descriptor.clone()), first);
+        assert_ne!(cache.pipeline_id(device, &descriptor.clone().with_blend(None)), first);
+        assert_eq!(cache.pipeline_layouts.len(), 1);
+
+        let texture = Texture::streaming(device, 4, 4, None);
+        let bind_group = cache.texture_bind_group(device, &texture);
+        assert!(Rc::ptr_eq(&bind_group, &cache.texture_bind_group(device, &texture)));
+        let other = cache.texture_bind_group(device, &Texture::streaming(device, 4, 4, None));
+        assert!(!Rc::ptr_eq(&bind_group, &other));
+        drop(other);
+        cache.prune_bind_groups();
+        assert_eq!(cache.texture_bind_groups.len(), 1);
+
+        // Every mipmapped texture of a format is rendered by the same generator
+        let image = image::DynamicImage::new_rgba8(8, 8);
+        let options = crate::texture::TextureOptions::default();
+        for _ in 0..2 {
+            Texture::from_image_with_options(device, &headless.renderer.queue, &mut cache, &image, None, &options).unwrap();
+        }
+        assert_eq!(cache.mipmap_generators.len(), 1);
+    }
+}
@@ -0,0 +1,553 @@
+use std::f32::consts::TAU;
+use std::time::Instant;
+use wgpu::util::DeviceExt;
+use crate::atlas::Atlas;
+use crate::background::Background;
+use crate::cursor_renderer::CursorRenderer;
+use crate::cursor_trail::{CursorTrail, TrailMode};
+use crate::draw_order::{DrawOrder, Layer};
+use crate::gpu::GpuError;
+use crate::hit_circle::CircleDrawable;
+use crate::msaa::{self, MsaaTarget};
+use crate::playfield::Playfield;
+use crate::post_process::PostProcess;
+use crate::profiler::{GpuProfiler, Profile};
+use crate::render_cache::RenderCache;
+use crate::shaders::ShaderRegistry;
+use crate::skin::{normalize, Skin};
+use crate::slider_renderer::{SliderDrawable, SliderRenderer, SliderStyle};
+use crate::spinner::SpinnerState;
+use crate::spinner_renderer::{SpinnerDrawable, SpinnerRenderer};
+use crate::sprite_batch::{BlendMode, Sprite, SpriteBatch, TextureId};
+use crate::text::{self, Font, FontAtlas, FontError};
+use crate::timing_overlay::TimingOverlay;
+use crate::uniforms::PlayfieldUniform;
+
+/// HUD elements are sized for a 768px tall window, like osu!'s.
+const HUD_REFERENCE_HEIGHT: f32 = 768.0;
+/// Pixel size UI font glyphs are rasterized at.
+const UI_FONT_SIZE: f32 = 32.0;
+/// TTF drawn for UI labels instead of the bundled font, if there is one.
+const UI_FONT_PATH: &str = "font.ttf";
+/// Cursor size while a key is held, for skins with CursorExpand.
+const CURSOR_EXPAND_SCALE: f32 = 1.3;
+/// How fast the cursor spins for skins with CursorRotate.
+const CURSOR_TURNS_PER_SECOND: f32 = 0.1;
+
+/// Values shown on the HUD counters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hud {
+    pub score: u64,
+    pub combo: u32,
+    pub accuracy: f32,
+    pub offset_ms: f64,
+    /// Average time between frames, shown with the frame rate when set.
+    pub frame_time_ms: Option<f64>,
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Hud {
+            score: 0,
+            combo: 0,
+            accuracy: 100.0,
+            offset_ms: 0.0,
+            frame_time_ms: None,
+        }
+    }
+}
+
+/// Everything drawn in one frame.
+pub struct Scene<'a> {
+    /// Gameplay time in milliseconds.
+    pub time: f32,
+    pub circles: &'a [CircleDrawable],
+    /// Topmost first.
+    pub sliders: &'a [SliderDrawable],
+    pub spinners: &'a [SpinnerState],
+    /// Cursor position in osu!pixels.
+    pub cursor: (f32, f32),
+    pub cursor_trail: &'a CursorTrail,
+    /// Whether a gameplay key is held, which expands the cursor.
+    pub cursor_pressed: bool,
+    /// Wall-clock time the trail fades relative to.
+    pub now: Instant,
+    pub hud: Hud,
+    /// Timings to show in the profiling overlay, which is hidden while this is `None`.
+    pub profile: Option<Profile<'a>>,
+}
+
+/// Format and size of the views a renderer draws into.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TargetDescriptor {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// MSAA samples, lowered to the closest count the adapter supports for `format`.
+    pub sample_count: u32,
+}
+
+/// Draws scenes into any texture view of its format, whether a window's surface or an offscreen texture.
+pub struct Renderer {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub clear_color: wgpu::Color,
+    /// Pipelines, layouts and texture bind groups shared by every renderer below.
+    pub cache: RenderCache,
+    /// Multisampled target the frame is drawn through; every pipeline drawing to it shares its sample count.
+    pub msaa: MsaaTarget,
+    /// Background dim and blur plus bloom. Skipped entirely while its settings are all off and there is no background.
+    pub post_process: PostProcess,
+    /// Beatmap background, drawn in window pixels.
+    pub background: Background,
+    pub sprite_batch: SpriteBatch,
+    pub skin: Skin,
+    pub skin_atlas: Atlas<(String, usize)>,
+    /// Sprite batch textures of the skin atlas pages.
+    pub skin_pages: Vec<TextureId>,
+    /// Sprites in window pixels, drawn over the playfield.
+    pub ui_batch: SpriteBatch,
+    pub ui_skin_pages: Vec<TextureId>,
+    pub ui_font: FontAtlas,
+    /// Sprite batch textures of the UI font pages, for text placed in the playfield.
+    pub font_pages: Vec<TextureId>,
+    pub ui_uniform: wgpu::Buffer,
+    pub ui_bind_group: wgpu::BindGroup,
+    pub playfield: Playfield,
+    pub playfield_uniform: wgpu::Buffer,
+    pub playfield_bind_group: wgpu::BindGroup,
+    /// Cursor and trail sprites, drawn in a final pass over everything else. It is single-sampled
+    /// so it can draw straight onto the finished frame, after post-processing.
+    pub cursor_batch: SpriteBatch,
+    pub cursor_skin_pages: Vec<TextureId>,
+    pub cursor_renderer: CursorRenderer,
+    pub slider_renderer: SliderRenderer,
+    pub spinner_renderer: SpinnerRenderer,
+    /// GPU time of each pass, when the device supports timestamp queries.
+    pub profiler: GpuProfiler,
+    pub timing_overlay: TimingOverlay,
+    /// Whether shader files edited on disk are picked up between frames. On in debug builds.
+    pub hot_reload_shaders: bool,
+}
+
+impl Renderer {
+    /// `sample_count` is lowered to the closest count `adapter` supports for `format`.
+    pub fn new(adapter: &wgpu::Adapter, device: wgpu::Device, queue: wgpu::Queue, target: TargetDescriptor, skin: Skin) -> Result<Self, GpuError> {
+        let TargetDescriptor { format, width, height, sample_count: requested_samples } = target;
+        let sample_count = msaa::select_sample_count(requested_samples, &msaa::supported_sample_counts(adapter, format));
+        if sample_count != requested_samples {
+            log::warn!("{}x MSAA is not supported, using {}x", requested_samples, sample_count);
+        }
+        let mut cache = RenderCache::new(ShaderRegistry::new());
+        let msaa = MsaaTarget::new(&device, format, width, height, sample_count);
+        let post_process = PostProcess::new(&device, &mut cache, format, width, height, sample_count);
+
+        let skin_atlas = skin.build_atlas(&device, &queue, &mut cache).map_err(|error| {
+            log::error!("Couldn't build the skin atlas: {}", error);
+            GpuError::ResourceError
+        })?;
+
+        let playfield = Playfield::new(width as f32, height as f32);
+
+        let playfield_uniform = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Playfield Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[playfield.uniform()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let playfield_bind_group_layout = cache.bind_group_layout_id(
+            &device,
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+        );
+
+        let playfield_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Playfield Bind Group"),
+                layout: cache.bind_group_layout(playfield_bind_group_layout),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: playfield_uniform.as_entire_binding(),
+                    }
+                ],
+            }
+        );
+
+        let mut sprite_batch = SpriteBatch::new(&device, &mut cache, format, sample_count, playfield_bind_group_layout);
+        let skin_pages = sprite_batch.register_atlas(&device, &mut cache, &skin_atlas);
+
+        let ui_uniform = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("UI Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[PlayfieldUniform::screen(width as f32, height as f32)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let ui_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("UI Bind Group"),
+                layout: cache.bind_group_layout(playfield_bind_group_layout),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: ui_uniform.as_entire_binding(),
+                    }
+                ],
+            }
+        );
+
+        let mut ui_batch = SpriteBatch::new(&device, &mut cache, format, sample_count, playfield_bind_group_layout);
+        let ui_skin_pages = ui_batch.register_atlas(&device, &mut cache, &skin_atlas);
+        let font = Font::from_file(UI_FONT_PATH, UI_FONT_SIZE, text::ASCII).unwrap_or_else(|error| {
+            if !matches!(error, FontError::FileError) {
+                log::warn!("Couldn't load {}, using the bundled font: {}", UI_FONT_PATH, error);
+            }
+            Font::default_ui(UI_FONT_SIZE)
+        });
+        let ui_font = FontAtlas::new(&device, &queue, &mut cache, &mut ui_batch, font).map_err(|error| {
+            log::error!("Couldn't build the UI font atlas: {}", error);
+            GpuError::ResourceError
+        })?;
+        let font_pages = sprite_batch.register_atlas(&device, &mut cache, &ui_font.atlas);
+
+        let mut slider_renderer = SliderRenderer::new(&device, &mut cache, format, sample_count, width, height, playfield_bind_group_layout);
+        slider_renderer.style = SliderStyle::from_ini(&skin.ini);
+
+        let mut spinner_renderer = SpinnerRenderer::new(&device, &mut cache, format, sample_count, playfield_bind_group_layout);
+        spinner_renderer.disc_colour = normalize(skin.ini.spinner_background);
+
+        let mut cursor_batch = SpriteBatch::new(&device, &mut cache, format, 1, playfield_bind_group_layout);
+        let cursor_skin_pages = cursor_batch.register_atlas(&device, &mut cache, &skin_atlas);
+        let cursor_renderer = CursorRenderer::new(&device, &mut cache, format, 1, playfield_bind_group_layout);
+
+        let background = Background::new(&device, &mut cache, format, playfield_bind_group_layout);
+
+        let profiler = GpuProfiler::new(&device, &queue);
+        let timing_overlay = TimingOverlay::new(&device, format);
+
+        Ok(Self {
+            device,
+            queue,
+            format,
+            width,
+            height,
+            clear_color: wgpu::Color::BLACK,
+            cache,
+            msaa,
+            post_process,
+            background,
+            sprite_batch,
+            skin,
+            skin_atlas,
+            skin_pages,
+            ui_batch,
+            ui_skin_pages,
+            ui_font,
+            font_pages,
+            ui_uniform,
+            ui_bind_group,
+            playfield,
+            playfield_uniform,
+            playfield_bind_group,
+            cursor_batch,
+            cursor_skin_pages,
+            cursor_renderer,
+            slider_renderer,
+            spinner_renderer,
+            profiler,
+            timing_overlay,
+            hot_reload_shaders: cfg!(debug_assertions),
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.msaa.resize(&self.device, self.format, width, height);
+        self.post_process.resize(&self.device, &self.cache, width, height);
+        self.slider_renderer.resize(&self.device, &self.cache, width, height);
+
+        self.playfield.resize(width as f32, height as f32);
+        self.queue.write_buffer(
+            &self.playfield_uniform,
+            0,
+            bytemuck::cast_slice(&[self.playfield.uniform()])
+        );
+        self.queue.write_buffer(
+            &self.ui_uniform,
+            0,
+            bytemuck::cast_slice(&[PlayfieldUniform::screen(width as f32, height as f32)])
+        );
+    }
+
+    /// Sprite of a skin element's frame, sized in osu!pixels relative to a hit circle of `radius`.
+    pub fn skin_sprite(&self, name: &str, frame: usize, position: (f32, f32), radius: f32) -> Option<Sprite> {
+        // Skin elements are sized for a 128px hit circle
+        self.skin_sprite_on(&self.skin_pages, name, frame, position, radius * 2.0 / 128.0)
+    }
+
+    /// Sprite of a skin element's frame at `scale` times its size in osu!pixels, for the batch `pages` belong to.
+    fn skin_sprite_on(&self, pages: &[TextureId], name: &str, frame: usize, position: (f32, f32), scale: f32) -> Option<Sprite> {
+        let image = self.skin.frames(name).get(frame)?;
+        let region = self.skin_atlas.region(&(name.to_string(), frame))?;
+        let (width, height) = image.size();
+        Some(Sprite::new(pages[region.page], region, position, (width * scale, height * scale)))
+    }
+
+    fn queue_circle(&mut self, circle: &CircleDrawable) {
+        let (x, y) = circle.position;
+        let reach = circle.radius * circle.approach_scale.unwrap_or(1.0).max(1.0);
+        if !self.playfield.is_circle_visible(x, y, reach) {
+            return;
+        }
+        let combo_colour = self.skin.ini.combo_colour(circle.combo_colour);
+        let order = |layer, part| DrawOrder::hit_object(layer, circle.index, part);
+
+        let sprites = [
+            self.skin_sprite("hitcircle", 0, circle.position, circle.radius)
+                .map(|sprite| sprite.with_colour(combo_colour).with_order(order(Layer::HitObject, 0))),
+            self.skin_sprite("hitcircleoverlay", 0, circle.position, circle.radius)
+                .map(|sprite| sprite.with_order(order(Layer::HitObject, 1))),
+            circle.approach_scale.and_then(|scale| {
+                self.skin_sprite("approachcircle", 0, circle.position, circle.radius * scale)
+                    .map(|sprite| sprite.with_colour(combo_colour).with_order(order(Layer::ApproachCircle, 0)))
+            }),
+        ];
+        for sprite in sprites.into_iter().flatten() {
+            self.sprite_batch.push(sprite.with_alpha(sprite.tint[3] * circle.alpha));
+        }
+
+        let ini = &self.skin.ini;
+        let number = text::layout_skin_text(
+            &self.skin,
+            &ini.hit_circle_prefix,
+            ini.hit_circle_overlap,
+            &circle.combo_number.to_string(),
+            circle.radius * 2.0 / 128.0,
+        );
+        for sprite in number.sprites(&self.skin_atlas, &self.skin_pages, circle.position, (0.5, 0.5)) {
+            self.sprite_batch.push(sprite.with_alpha(circle.alpha).with_order(order(Layer::HitObject, 2)));
+        }
+    }
+
+    /// Additive trail sprites fading behind the cursor, then the cursor with its middle on top,
+    /// following the skin's CursorCentre, CursorExpand and CursorRotate.
+    fn queue_cursor(&mut self, scene: &Scene) {
+        let ini = &self.skin.ini;
+        let pages = &self.cursor_skin_pages;
+        let anchor = if ini.cursor_centre { 0.5 } else { 0.0 };
+        let scale = if ini.cursor_expand && scene.cursor_pressed { CURSOR_EXPAND_SCALE } else { 1.0 };
+        let rotation = if ini.cursor_rotate { scene.time / 1000.0 * CURSOR_TURNS_PER_SECOND * TAU } else { 0.0 };
+
+        let mut sprites = Vec::new();
+        if scene.cursor_trail.settings.mode == TrailMode::Sprites {
+            for point in scene.cursor_trail.points() {
+                if let Some(sprite) = self.skin_sprite_on(pages, "cursortrail", 0, point.position, 1.0) {
+                    let sprite = sprite.with_alpha(scene.cursor_trail.fade(point, scene.now)).with_layer(Layer::Cursor);
+                    sprites.push(sprite.with_blend(BlendMode::Additive));
+                }
+            }
+        }
+        // The cursor goes over its trail, which would otherwise be sorted last as it's additive
+        let cursor_order = DrawOrder { part: 1, ..DrawOrder::new(Layer::Cursor) };
+        sprites.extend(self.skin_sprite_on(pages, "cursor", 0, scene.cursor, scale).map(|sprite| sprite.with_rotation(rotation).with_order(cursor_order)));
+        sprites.extend(self.skin_sprite_on(pages, "cursormiddle", 0, scene.cursor, scale).map(|sprite| sprite.with_order(cursor_order)));
+        for sprite in sprites {
+            self.cursor_batch.push(sprite.with_anchor(anchor, anchor));
+        }
+    }
+
+    fn queue_spinner_labels(&mut self, spinner: &SpinnerDrawable) {
+        for label in spinner.labels() {
+            let text = self.ui_font.font.layout(&label.text, label.size);
+            for sprite in text.sprites(&self.ui_font.atlas, &self.font_pages, label.position, (0.5, 0.5)) {
+                self.sprite_batch.push(sprite.with_colour(label.colour).with_alpha(label.alpha).with_layer(Layer::Spinner));
+            }
+        }
+    }
+
+    /// Score, accuracy and combo counters in the window corners, plus the offset and frame rate as text labels.
+    fn queue_hud(&mut self, hud: &Hud) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let scale = height / HUD_REFERENCE_HEIGHT;
+        let ini = &self.skin.ini;
+
+        let score = text::layout_skin_text(&self.skin, &ini.score_prefix, ini.score_overlap, &format!("{:08}", hud.score), scale);
+        let accuracy = text::layout_skin_text(&self.skin, &ini.score_prefix, ini.score_overlap, &format!("{:.2}%", hud.accuracy), scale * 0.6);
+        let combo = text::layout_skin_text(&self.skin, &ini.combo_prefix, ini.combo_overlap, &format!("{}x", hud.combo), scale);
+
+        let margin = 8.0 * scale;
+        let sprites = [
+            score.sprites(&self.skin_atlas, &self.ui_skin_pages, (width - margin, 0.0), (1.0, 0.0)),
+            accuracy.sprites(&self.skin_atlas, &self.ui_skin_pages, (width - margin, score.height), (1.0, 0.0)),
+            combo.sprites(&self.skin_atlas, &self.ui_skin_pages, (margin, height), (0.0, 1.0)),
+            self.ui_font.sprites(&format!("Offset: {:+}ms", hud.offset_ms), 20.0 * scale, (margin, margin), (0.0, 0.0)),
+        ];
+        for sprite in sprites.into_iter().flatten() {
+            self.ui_batch.push(sprite.with_layer(Layer::Hud));
+        }
+        if let Some(frame_time) = hud.frame_time_ms.filter(|frame_time| *frame_time > 0.0) {
+            let label = format!("{:.0} fps ({:.2}ms)", 1000.0 / frame_time, frame_time);
+            for sprite in self.ui_font.sprites(&label, 20.0 * scale, (width - margin, height - margin), (1.0, 1.0)) {
+                self.ui_batch.push(sprite.with_layer(Layer::Hud));
+            }
+        }
+    }
+
+    /// Draws `scene` into `view`, which must have this renderer's format and size.
+    pub fn render(&mut self, view: &wgpu::TextureView, scene: &Scene) {
+        if self.hot_reload_shaders {
+            self.cache.reload_shaders(&self.device);
+        }
+        self.cache.prune_bind_groups();
+        self.profiler.begin_frame(&self.device);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+
+        self.slider_renderer.prepare(&self.device, scene.sliders);
+        self.slider_renderer.render_bodies(&mut encoder, &self.cache, &mut self.profiler, &self.playfield_bind_group);
+
+        let spinners: Vec<SpinnerDrawable> = scene
+            .spinners
+            .iter()
+            .filter_map(|spinner| SpinnerDrawable::new(spinner, scene.time))
+            .collect();
+        self.spinner_renderer.prepare(&self.device, &spinners);
+        for spinner in &spinners {
+            self.queue_spinner_labels(spinner);
+        }
+
+        for circle in scene.circles {
+            self.queue_circle(circle);
+        }
+        self.sprite_batch.prepare(&self.device, &self.queue);
+        self.queue_hud(&scene.hud);
+        self.ui_batch.prepare(&self.device, &self.queue);
+
+        self.cursor_renderer.prepare(&self.device, scene.cursor_trail, scene.cursor, scene.now);
+        self.queue_cursor(scene);
+        self.cursor_batch.prepare(&self.device, &self.queue);
+
+        // The background only exists as a post-processing target
+        let post_processing = self.post_process.settings.is_active() || self.background.is_visible();
+        let main_attachment = if post_processing {
+            self.post_process.prepare(&self.queue);
+            self.background.prepare(&self.device, &self.queue, scene.time, self.width, self.height);
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Background Pass"),
+                    color_attachments: &[Some(self.post_process.background_attachment(self.clear_color))],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: self.profiler.timestamp_writes("Background Pass"),
+                });
+                self.background.draw(&mut render_pass, &self.cache, &self.ui_bind_group);
+            }
+            self.msaa.color_attachment(self.post_process.scene_view(), wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT))
+        } else {
+            self.msaa.color_attachment(view, wgpu::LoadOp::Clear(self.clear_color))
+        };
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[
+                    // This is what @location(0) in the fragment shader targets
+                    Some(main_attachment)
+                ],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: self.profiler.timestamp_writes("Render Pass"),
+            });
+
+            // Bottom to top, each layer's renderer first and then its sprites
+            for layer in Layer::ALL {
+                match layer {
+                    Layer::SliderBody => self.slider_renderer.draw_bodies(&mut render_pass, &self.cache),
+                    Layer::Spinner => self.spinner_renderer.draw(&mut render_pass, &self.cache, &self.playfield_bind_group),
+                    Layer::SliderBall => self.slider_renderer.draw_balls(&mut render_pass, &self.cache, &self.playfield_bind_group),
+                    _ => {}
+                }
+                self.sprite_batch.draw_layers(&mut render_pass, &self.cache, &self.playfield_bind_group, layer..=layer);
+            }
+            self.ui_batch.draw(&mut render_pass, &self.cache, &self.ui_bind_group);
+        }
+
+        if post_processing {
+            if self.post_process.settings.bloom_enabled() {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Bloom Pass"),
+                    color_attachments: &[Some(self.post_process.bloom_attachment())],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: self.profiler.timestamp_writes("Bloom Pass"),
+                });
+                self.sprite_batch.draw_blend(&mut render_pass, &self.cache, &self.playfield_bind_group, BlendMode::Additive);
+            }
+            self.post_process.apply(&mut encoder, &self.cache, &mut self.profiler, view);
+        }
+
+        if let Some(profile) = &scene.profile {
+            let gpu = self.profiler.is_enabled().then_some(&self.profiler.passes);
+            let primitives = self.timing_overlay.prepare(&self.device, &self.queue, self.width, self.height, profile, gpu);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Timing Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: self.profiler.timestamp_writes("Timing Overlay Pass"),
+            });
+            self.timing_overlay.draw(&mut render_pass, &primitives, self.width, self.height);
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Cursor Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: self.profiler.timestamp_writes("Cursor Pass"),
+            });
+            self.cursor_renderer.draw(&mut render_pass, &self.cache, &self.playfield_bind_group);
+            self.cursor_batch.draw(&mut render_pass, &self.cache, &self.playfield_bind_group);
+        }
+        self.profiler.resolve(&mut encoder);
+
+        // submit will accept anything that implements IntoIter
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.profiler.end_frame();
+    }
+}
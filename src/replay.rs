@@ -0,0 +1,534 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Cursor, Read, Write};
+
+/// Game mode byte for osu!standard replays.
+pub const MODE_STANDARD: u8 = 0;
+/// Game version written into replays we produce.
+pub const REPLAY_VERSION: i32 = 20240101;
+/// Seed frames in osu! replays use this magic delta.
+const SEED_FRAME_DELTA: i64 = -12345;
+
+/// Message for data that ends before a field does.
+const TRUNCATED: &str = "unexpected end of data";
+
+/// Ticks between 0001-01-01 (.NET epoch) and 1970-01-01 (unix epoch).
+const DOTNET_UNIX_EPOCH_TICKS: i64 = 621_355_968_000_000_000;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keys(pub u32);
+
+impl Keys {
+    pub const NONE: Keys = Keys(0);
+    pub const M1: Keys = Keys(1);
+    pub const M2: Keys = Keys(2);
+    // K1/K2 always carry the matching mouse bit, as in osu!
+    pub const K1: Keys = Keys(4 | 1);
+    pub const K2: Keys = Keys(8 | 2);
+
+    pub fn insert(&mut self, other: Keys) {
+        self.0 |= other.0;
+    }
+
+    /// Whether any key is down in `self` that isn't in `previous`.
+    pub fn pressed_since(&self, previous: Keys) -> bool {
+        self.0 & !previous.0 != 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mods(pub u32);
+
+impl Mods {
+    pub const NONE: Mods = Mods(0);
+    pub const AUTOPLAY: Mods = Mods(2048);
+}
+
+/// A single cursor/key sample. `time` is absolute in milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReplayFrame {
+    pub time: i64,
+    pub x: f32,
+    pub y: f32,
+    pub keys: Keys,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayScore {
+    pub count_300: u16,
+    pub count_100: u16,
+    pub count_50: u16,
+    pub count_geki: u16,
+    pub count_katu: u16,
+    pub count_miss: u16,
+    pub total_score: i32,
+    pub max_combo: u16,
+    pub perfect: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub mode: u8,
+    pub version: i32,
+    pub beatmap_hash: String,
+    pub player_name: String,
+    pub replay_hash: String,
+    pub score: ReplayScore,
+    pub mods: Mods,
+    pub life_bar: String,
+    /// Windows ticks (100ns since 0001-01-01), as stored in `.osr` files.
+    pub timestamp: i64,
+    pub frames: Vec<ReplayFrame>,
+    pub seed: i32,
+    pub online_score_id: i64,
+}
+
+impl Replay {
+    pub fn new(beatmap_hash: &str, player_name: &str, mods: Mods) -> Replay {
+        Replay {
+            mode: MODE_STANDARD,
+            version: REPLAY_VERSION,
+            beatmap_hash: beatmap_hash.to_string(),
+            player_name: player_name.to_string(),
+            replay_hash: String::new(),
+            score: ReplayScore::default(),
+            mods,
+            life_bar: String::new(),
+            timestamp: now_ticks(),
+            frames: Vec::new(),
+            seed: 0,
+            online_score_id: 0,
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Replay, ReplayError> {
+        let bytes = std::fs::read(path).map_err(ReplayError::FileError)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ReplayError> {
+        let bytes = self.to_bytes()?;
+        std::fs::write(path, bytes).map_err(ReplayError::FileError)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        let mut reader = Cursor::new(bytes);
+
+        let mode = read_u8(&mut reader)?;
+        let version = read_i32(&mut reader)?;
+        let beatmap_hash = read_string(&mut reader)?;
+        let player_name = read_string(&mut reader)?;
+        let replay_hash = read_string(&mut reader)?;
+        let score = ReplayScore {
+            count_300: read_u16(&mut reader)?,
+            count_100: read_u16(&mut reader)?,
+            count_50: read_u16(&mut reader)?,
+            count_geki: read_u16(&mut reader)?,
+            count_katu: read_u16(&mut reader)?,
+            count_miss: read_u16(&mut reader)?,
+            total_score: read_i32(&mut reader)?,
+            max_combo: read_u16(&mut reader)?,
+            perfect: read_u8(&mut reader)? != 0,
+        };
+        let mods = Mods(read_i32(&mut reader)? as u32);
+        let life_bar = read_string(&mut reader)?;
+        let timestamp = read_i64(&mut reader)?;
+
+        let compressed_length = read_i32(&mut reader)?;
+        let compressed = read_bytes(&mut reader, compressed_length.try_into().unwrap_or(u64::MAX))?;
+        let (frames, seed) = decode_frames(compressed)?;
+
+        // Older replays end right after the frame data
+        let online_score_id = read_i64(&mut reader).unwrap_or(0);
+
+        Ok(Replay {
+            mode,
+            version,
+            beatmap_hash,
+            player_name,
+            replay_hash,
+            score,
+            mods,
+            life_bar,
+            timestamp,
+            frames,
+            seed,
+            online_score_id,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ReplayError> {
+        let mut out = Vec::new();
+        let compressed = encode_frames(&self.frames, self.seed)?;
+
+        out.push(self.mode);
+        out.extend_from_slice(&self.version.to_le_bytes());
+        write_string(&mut out, &self.beatmap_hash);
+        write_string(&mut out, &self.player_name);
+        write_string(&mut out, &self.replay_hash);
+        for count in [
+            self.score.count_300,
+            self.score.count_100,
+            self.score.count_50,
+            self.score.count_geki,
+            self.score.count_katu,
+            self.score.count_miss,
+        ] {
+            out.extend_from_slice(&count.to_le_bytes());
+        }
+        out.extend_from_slice(&self.score.total_score.to_le_bytes());
+        out.extend_from_slice(&self.score.max_combo.to_le_bytes());
+        out.push(self.score.perfect as u8);
+        out.extend_from_slice(&(self.mods.0 as i32).to_le_bytes());
+        write_string(&mut out, &self.life_bar);
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&(compressed.len() as i32).to_le_bytes());
+        out.extend_from_slice(&compressed);
+        out.extend_from_slice(&self.online_score_id.to_le_bytes());
+
+        Ok(out)
+    }
+
+    /// Time of the last recorded frame in milliseconds.
+    pub fn duration(&self) -> i64 {
+        self.frames.last().map(|frame| frame.time).unwrap_or(0)
+    }
+}
+
+/// MD5 hex digest of a beatmap file, as referenced by `.osr` headers.
+pub fn beatmap_hash(osu_file: &str) -> Result<String, ReplayError> {
+    let bytes = std::fs::read(osu_file).map_err(ReplayError::FileError)?;
+    Ok(format!("{:x}", md5::compute(bytes)))
+}
+
+/// Collects frames during a play and turns them into a `Replay` once it ends.
+pub struct ReplayRecorder {
+    replay: Replay,
+    last_keys: Keys,
+    last_position: (f32, f32),
+}
+
+impl ReplayRecorder {
+    pub fn new(beatmap_hash: &str, player_name: &str, mods: Mods) -> ReplayRecorder {
+        ReplayRecorder {
+            replay: Replay::new(beatmap_hash, player_name, mods),
+            last_keys: Keys::NONE,
+            last_position: (f32::NAN, f32::NAN),
+        }
+    }
+
+    /// Records a frame in osu!pixels. Frames that change nothing are dropped.
+    pub fn record(&mut self, time: i64, x: f32, y: f32, keys: Keys) {
+        if keys == self.last_keys && (x, y) == self.last_position {
+            return;
+        }
+        // Frames must be monotonic, otherwise the delta encoding breaks
+        let time = match self.replay.frames.last() {
+            Some(last) if time < last.time => last.time,
+            _ => time,
+        };
+
+        self.replay.frames.push(ReplayFrame { time, x, y, keys });
+        self.last_keys = keys;
+        self.last_position = (x, y);
+    }
+
+    pub fn frames(&self) -> &[ReplayFrame] {
+        &self.replay.frames
+    }
+
+    pub fn finish(mut self, score: ReplayScore) -> Replay {
+        self.replay.score = score;
+        self.replay.timestamp = now_ticks();
+        self.replay.replay_hash = format!(
+            "{:x}",
+            md5::compute(format!(
+                "{}{}{}{}",
+                self.replay.beatmap_hash,
+                self.replay.player_name,
+                self.replay.score.total_score,
+                self.replay.timestamp
+            ))
+        );
+        self.replay
+    }
+}
+
+/// Looks up where a replay's cursor was and which keys were held at any time.
+pub struct ReplayPlayer {
+    replay: Replay,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer { replay }
+    }
+
+    /// Cursor position at `time`, linearly interpolated between frames.
+    pub fn cursor_at(&self, time: i64) -> Option<(f32, f32)> {
+        let frames = &self.replay.frames;
+        let next = frames.partition_point(|frame| frame.time <= time);
+        match (next.checked_sub(1).map(|i| &frames[i]), frames.get(next)) {
+            (Some(a), Some(b)) if b.time > a.time => {
+                let t = (time - a.time) as f32 / (b.time - a.time) as f32;
+                Some((a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t))
+            }
+            (Some(a), _) => Some((a.x, a.y)),
+            (None, Some(b)) => Some((b.x, b.y)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn keys_at(&self, time: i64) -> Keys {
+        let frames = &self.replay.frames;
+        let next = frames.partition_point(|frame| frame.time <= time);
+        next.checked_sub(1).map(|i| frames[i].keys).unwrap_or(Keys::NONE)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    FileError(std::io::Error),
+    /// The data isn't a valid replay; says what's wrong with it.
+    FormatError(&'static str),
+    CompressionError(lzma_rs::error::Error),
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::FileError(error) => write!(f, "FileError: {}", error),
+            ReplayError::FormatError(message) => write!(f, "FormatError: {}", message),
+            ReplayError::CompressionError(error) => write!(f, "CompressionError: {}", error),
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReplayError::FileError(error) => Some(error),
+            ReplayError::FormatError(_) => None,
+            ReplayError::CompressionError(error) => Some(error),
+        }
+    }
+}
+
+fn now_ticks() -> i64 {
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    DOTNET_UNIX_EPOCH_TICKS + (since_epoch.as_nanos() / 100) as i64
+}
+
+fn encode_frames(frames: &[ReplayFrame], seed: i32) -> Result<Vec<u8>, ReplayError> {
+    let mut text = String::new();
+    let mut last_time = 0;
+    for frame in frames {
+        text.push_str(&format!("{}|{}|{}|{},", frame.time - last_time, frame.x, frame.y, frame.keys.0));
+        last_time = frame.time;
+    }
+    text.push_str(&format!("{}|0|0|{},", SEED_FRAME_DELTA, seed));
+
+    let mut compressed = Vec::new();
+    let options = lzma_rs::compress::Options {
+        unpacked_size: lzma_rs::compress::UnpackedSize::WriteToHeader(Some(text.len() as u64)),
+    };
+    lzma_rs::lzma_compress_with_options(&mut text.as_bytes(), &mut compressed, &options)
+        .map_err(|error| ReplayError::CompressionError(lzma_rs::error::Error::IoError(error)))?;
+    Ok(compressed)
+}
+
+fn decode_frames(compressed: &[u8]) -> Result<(Vec<ReplayFrame>, i32), ReplayError> {
+    let mut decompressed = Vec::new();
+    lzma_rs::lzma_decompress(&mut Cursor::new(compressed), &mut decompressed).map_err(ReplayError::CompressionError)?;
+    let text = String::from_utf8(decompressed).map_err(|_| ReplayError::FormatError("frame data isn't UTF-8"))?;
+
+    let mut frames = Vec::new();
+    let mut seed = 0;
+    let mut time = 0;
+    for entry in text.split(',').filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.split('|');
+        let mut next = || parts.next().ok_or(ReplayError::FormatError("frame is missing a field"));
+        let delta = next()?.parse::<i64>().map_err(invalid_field)?;
+        let x = next()?.parse::<f32>().map_err(invalid_field)?;
+        let y = next()?.parse::<f32>().map_err(invalid_field)?;
+        let keys = next()?.parse::<f64>().map_err(invalid_field)? as u32;
+
+        if delta == SEED_FRAME_DELTA {
+            seed = keys as i32;
+            continue;
+        }
+        time += delta;
+        frames.push(ReplayFrame { time, x, y, keys: Keys(keys) });
+    }
+
+    Ok((frames, seed))
+}
+
+fn invalid_field<E>(_: E) -> ReplayError {
+    ReplayError::FormatError("frame has an invalid field")
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8, ReplayError> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf).map_err(|_| ReplayError::FormatError(TRUNCATED))?;
+    Ok(buf[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> Result<u16, ReplayError> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf).map_err(|_| ReplayError::FormatError(TRUNCATED))?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_i32(reader: &mut impl Read) -> Result<i32, ReplayError> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf).map_err(|_| ReplayError::FormatError(TRUNCATED))?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_i64(reader: &mut impl Read) -> Result<i64, ReplayError> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf).map_err(|_| ReplayError::FormatError(TRUNCATED))?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+fn read_uleb128(reader: &mut impl Read) -> Result<u64, ReplayError> {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = read_u8(reader)?;
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(ReplayError::FormatError("length is too long"));
+        }
+    }
+}
+
+fn write_uleb128(out: &mut impl Write, mut value: u64) {
+    loop {
+        let mut byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        out.write_all(&[byte]).unwrap();
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+/// The next `length` bytes. Lengths come from the file, so they're checked against what's
+/// left of it before anything is allocated.
+fn read_bytes<'a>(reader: &mut Cursor<&'a [u8]>, length: u64) -> Result<&'a [u8], ReplayError> {
+    let data = *reader.get_ref();
+    let start = reader.position().min(data.len() as u64);
+    if length > data.len() as u64 - start {
+        return Err(ReplayError::FormatError(TRUNCATED));
+    }
+    reader.set_position(start + length);
+    Ok(&data[start as usize..(start + length) as usize])
+}
+
+fn read_string(reader: &mut Cursor<&[u8]>) -> Result<String, ReplayError> {
+    match read_u8(reader)? {
+        0x00 => Ok(String::new()),
+        0x0b => {
+            let length = read_uleb128(reader)?;
+            let bytes = read_bytes(reader, length)?;
+            String::from_utf8(bytes.to_vec()).map_err(|_| ReplayError::FormatError("string isn't UTF-8"))
+        }
+        _ => Err(ReplayError::FormatError("invalid string marker")),
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    if value.is_empty() {
+        out.push(0x00);
+        return;
+    }
+    out.push(0x0b);
+    write_uleb128(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uleb128_roundtrip() {
+        for value in [0u64, 1, 127, 128, 300, 16384, u32::MAX as u64] {
+            let mut buf = Vec::new();
+            write_uleb128(&mut buf, value);
+            assert_eq!(read_uleb128(&mut Cursor::new(&buf)).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_replay_roundtrip() {
+        let mut recorder = ReplayRecorder::new("d41d8cd98f00b204e9800998ecf8427e", "player", Mods::AUTOPLAY);
+        recorder.record(0, 256.0, 192.0, Keys::NONE);
+        recorder.record(16, 260.5, 190.0, Keys::K1);
+        recorder.record(16, 260.5, 190.0, Keys::K1);
+        recorder.record(33, 270.0, 185.25, Keys::NONE);
+        let replay = recorder.finish(ReplayScore {
+            count_300: 10,
+            count_miss: 1,
+            total_score: 12345,
+            max_combo: 9,
+            ..Default::default()
+        });
+        assert_eq!(replay.frames.len(), 3);
+
+        let bytes = replay.to_bytes().unwrap();
+        let loaded = Replay::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, replay);
+    }
+
+    #[test]
+    fn test_replay_player_interpolation() {
+        let mut replay = Replay::new("", "", Mods::NONE);
+        replay.frames = vec![
+            ReplayFrame { time: 0, x: 0.0, y: 0.0, keys: Keys::NONE },
+            ReplayFrame { time: 100, x: 100.0, y: 50.0, keys: Keys::K2 },
+        ];
+        let player = ReplayPlayer::new(replay);
+        assert_eq!(player.cursor_at(50), Some((50.0, 25.0)));
+        assert_eq!(player.keys_at(50), Keys::NONE);
+        assert_eq!(player.keys_at(100), Keys::K2);
+    }
+
+    #[test]
+    fn test_lengths_past_the_end_are_rejected() {
+        let replay = Replay::new("d41d8cd98f00b204e9800998ecf8427e", "player", Mods::NONE);
+        let bytes = replay.to_bytes().unwrap();
+
+        // A string claiming to be far longer than the file
+        let mut huge_string = bytes[..5].to_vec();
+        huge_string.push(0x0b);
+        write_uleb128(&mut huge_string, u64::MAX >> 1);
+        assert!(matches!(Replay::from_bytes(&huge_string), Err(ReplayError::FormatError(_))));
+
+        // Frame data claiming ~2GB
+        let length_offset = bytes.len() - 8 - 4 - encode_frames(&[], 0).unwrap().len();
+        let mut huge_frames = bytes.clone();
+        huge_frames[length_offset..length_offset + 4].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(matches!(Replay::from_bytes(&huge_frames), Err(ReplayError::FormatError(_))));
+        huge_frames[length_offset..length_offset + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        assert!(matches!(Replay::from_bytes(&huge_frames), Err(ReplayError::FormatError(_))));
+    }
+
+    #[test]
+    fn test_error_keeps_its_cause() {
+        let error = Replay::from_file("/nonexistent/replay.osr").unwrap_err();
+        assert!(error.to_string().starts_with("FileError: "));
+        assert!(error.source().is_some());
+    }
+}
@@ -1,37 +0,0 @@
-struct ScaleFactorUniform {
-    scale_factor: vec2<f32>,
-};
-@group(1) @binding(0)
-var<uniform> scale_factor: ScaleFactorUniform;
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-};
-
-@vertex
-fn vs_main(
-    model: VertexInput
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
-   
-    var scaled_2dpos = model.position.xy * scale_factor.scale_factor;
-    out.clip_position = vec4<f32>(scaled_2dpos, 1.0, 1.0);
-    return out;
-}
-
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0)@binding(1)
-var s_diffuse: sampler;
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
@@ -0,0 +1,215 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// WGSL sources baked into the binary, by file name under `src/`. The mipmap shader is left
+/// out, its pipeline only lives as long as a texture upload.
+const EMBEDDED: [(&str, &str); 5] = [
+    ("sprite.wgsl", include_str!("sprite.wgsl")),
+    ("cursor.wgsl", include_str!("cursor.wgsl")),
+    ("spinner.wgsl", include_str!("spinner.wgsl")),
+    ("slider.wgsl", include_str!("slider.wgsl")),
+    ("post_process.wgsl", include_str!("post_process.wgsl")),
+];
+/// How often shader files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Parses and validates WGSL the way wgpu would, without a device.
+pub fn validate(source: &str) -> Result<(), ShaderError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|error| ShaderError::ParseError(error.emit_to_string(source)))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|error| ShaderError::ValidationError(error.emit_to_string(source)))?;
+    Ok(())
+}
+
+/// Runs `build` in a validation error scope, so a pipeline that doesn't match its shader is
+/// reported instead of panicking.
+pub fn checked<T>(device: &wgpu::Device, build: impl FnOnce() -> T) -> Result<T, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let built = build();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(ShaderError::PipelineError(error.to_string())),
+        None => Ok(built),
+    }
+}
+
+struct ShaderFile {
+    name: &'static str,
+    embedded: &'static str,
+    /// Modification time of the file on disk when it was last loaded or rejected.
+    modified: Option<SystemTime>,
+}
+
+/// Hands out shader modules by file name. In debug builds shaders are read from the source
+/// tree and watched, so edits can be picked up without rebuilding; a file that doesn't
+/// validate is logged and the previous version stays in use.
+pub struct ShaderRegistry {
+    /// Where shader files are read from, `None` to only use the embedded sources.
+    directory: Option<PathBuf>,
+    files: Vec<ShaderFile>,
+    last_poll: Instant,
+}
+
+impl ShaderRegistry {
+    pub fn new() -> Self {
+        let directory = cfg!(debug_assertions)
+            .then(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src")))
+            .filter(|directory| directory.is_dir());
+        Self::with_directory(directory)
+    }
+
+    pub fn with_directory(directory: Option<PathBuf>) -> Self {
+        let files = EMBEDDED
+            .iter()
+            .map(|(name, embedded)| ShaderFile { name, embedded, modified: None })
+            .collect();
+        ShaderRegistry {
+            directory,
+            files,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Module for the shader file `name`, from disk if it's there and valid.
+    pub fn module(&mut self, device: &wgpu::Device, name: &str) -> wgpu::ShaderModule {
+        let index = self
+            .files
+            .iter()
+            .position(|file| file.name == name)
+            .unwrap_or_else(|| panic!("{} is not a registered shader", name));
+        let source = match self.read(index) {
+            Some(Ok(source)) => source,
+            Some(Err(error)) => {
+                log::error!("{} failed to load, using the built-in version: {}", name, error);
+                self.files[index].embedded.to_string()
+            }
+            None => self.files[index].embedded.to_string(),
+        };
+        create_module(device, self.files[index].name, &source)
+    }
+
+    /// Modules for the shader files that changed on disk since they were last loaded. Files
+    /// that don't validate are logged and left out.
+    pub fn poll_changes(&mut self, device: &wgpu::Device) -> Vec<(&'static str, wgpu::ShaderModule)> {
+        if self.directory.is_none() || self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for index in 0..self.files.len() {
+            let modified = self.modified(index);
+            if modified.is_none() || modified == self.files[index].modified {
+                continue;
+            }
+            let name = self.files[index].name;
+            match self.read(index) {
+                Some(Ok(source)) => changed.push((name, create_module(device, name, &source))),
+                Some(Err(error)) => log::error!("{} failed to reload, keeping the old pipelines: {}", name, error),
+                None => {}
+            }
+        }
+        changed
+    }
+
+    fn modified(&self, index: usize) -> Option<SystemTime> {
+        let path = self.directory.as_ref()?.join(self.files[index].name);
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Reads and validates the file on disk, `None` if there is no directory or file.
+    fn read(&mut self, index: usize) -> Option<Result<String, ShaderError>> {
+        let path = self.directory.as_ref()?.join(self.files[index].name);
+        self.files[index].modified = self.modified(index);
+        let source = std::fs::read_to_string(path).ok()?;
+        Some(validate(&source).map(|_| source))
+    }
+}
+
+fn create_module(device: &wgpu::Device, name: &str, source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    ParseError(String),
+    ValidationError(String),
+    /// wgpu rejected a pipeline built from a valid shader, e.g. over a renamed entry point.
+    PipelineError(String),
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderError::ParseError(message) => write!(f, "ParseError\n{}", message),
+            ShaderError::ValidationError(message) => write!(f, "ValidationError\n{}", message),
+            ShaderError::PipelineError(message) => write!(f, "PipelineError\n{}", message),
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessRenderer;
+    use crate::skin::Skin;
+
+    #[test]
+    fn test_embedded_shaders_validate() {
+        for (name, source) in EMBEDDED {
+            assert!(validate(source).is_ok(), "{}: {}", name, validate(source).unwrap_err());
+        }
+        assert!(matches!(validate("fn main( {}"), Err(ShaderError::ParseError(_))));
+        let mismatched = "@fragment fn fs_main() -> @location(0) vec4<f32> { return vec3<f32>(1.0); }";
+        assert!(matches!(validate(mismatched), Err(ShaderError::ValidationError(_))));
+    }
+
+    #[test]
+    fn test_poll_changes() {
+        let Ok(headless) = pollster::block_on(HeadlessRenderer::new(16, 16, 1, Skin::new())) else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+        let device = &headless.renderer.device;
+        let directory = std::env::temp_dir().join(format!("shader_registry_test_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("spinner.wgsl");
+        let write = |source: &str, seconds: u64| {
+            std::fs::write(&path, source).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+        };
+        let poll = |registry: &mut ShaderRegistry| {
+            registry.last_poll -= POLL_INTERVAL;
+            registry.poll_changes(device).into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+
+        write(EMBEDDED[2].1, 1);
+        let mut registry = ShaderRegistry::with_directory(Some(directory.clone()));
+        registry.module(device, "spinner.wgsl");
+        assert!(registry.poll_changes(device).is_empty());
+        assert!(poll(&mut registry).is_empty());
+
+        // A broken edit is skipped, and only reported once
+        write("fn main( {}", 2);
+        assert!(poll(&mut registry).is_empty());
+        assert_eq!(registry.files[2].modified, registry.modified(2));
+
+        write(&EMBEDDED[2].1.replace("fs_main", "fs_main "), 3);
+        assert_eq!(poll(&mut registry), vec!["spinner.wgsl"]);
+        assert!(poll(&mut registry).is_empty());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
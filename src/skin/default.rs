@@ -0,0 +1,128 @@
+use image::{Rgba, RgbaImage};
+
+/// Built-in stand-ins for skin elements, drawn at load time so no assets need shipping.
+/// Coloured elements are white where the game tints them, like osu!'s own default skin.
+pub fn element(name: &str) -> Option<RgbaImage> {
+    const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+    const YELLOW: [f32; 3] = [1.0, 0.85, 0.4];
+
+    let image = match name {
+        "hitcircle" => disc(128, 0.95, [0.9, 0.9, 0.9], 1.0),
+        "hitcircleoverlay" => ring(128, 0.95, 0.12, WHITE),
+        "approachcircle" => ring(128, 0.95, 0.06, WHITE),
+        "cursor" => cursor(32),
+        "cursortrail" => glow(32, YELLOW),
+        "cursormiddle" => disc(16, 0.9, WHITE, 1.0),
+        _ => {
+            // Glyphs for any font prefix, e.g. "default-4" or "score-percent"
+            let (_, suffix) = name.rsplit_once('-')?;
+            match suffix {
+                "dot" => font_symbol(24, 64, |u, v| (u.abs() - 0.25).max((v - 0.7).abs() - 0.1)),
+                "comma" => font_symbol(24, 64, |u, v| (u.abs() - 0.25).max((v - 0.8).abs() - 0.2)),
+                "x" => font_symbol(48, 64, |u, v| {
+                    let v = (v - 0.3) / 0.5;
+                    ((u - v).abs().min((u + v).abs()) / 2.0 - 0.1).max(u.abs().max(v.abs()) - 0.6)
+                }),
+                "percent" => font_symbol(48, 64, |u, v| {
+                    let slash = (u + v * 0.6).abs() / 1.2 - 0.08;
+                    let upper = ((u + 0.45).powi(2) + (v + 0.5).powi(2)).sqrt() - 0.2;
+                    let lower = ((u - 0.45).powi(2) + (v - 0.5).powi(2)).sqrt() - 0.2;
+                    slash.max(v.abs() - 0.8).min(upper).min(lower)
+                }),
+                _ => {
+                    let digit = suffix.parse::<u8>().ok().filter(|digit| *digit < 10)?;
+                    seven_segment(digit, 48, 64)
+                }
+            }
+        }
+    };
+    Some(image)
+}
+
+/// Rasterizes `coverage`, which maps pixel centres in -1..1 to 0..1, in a single colour.
+fn rasterize(width: u32, height: u32, colour: [f32; 3], coverage: impl Fn(f32, f32) -> f32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let u = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+        let v = (y as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+        let alpha = coverage(u, v).clamp(0.0, 1.0);
+        Rgba([
+            (colour[0] * 255.0) as u8,
+            (colour[1] * 255.0) as u8,
+            (colour[2] * 255.0) as u8,
+            (alpha * 255.0).round() as u8,
+        ])
+    })
+}
+
+/// Antialiased edge: 1 inside `distance < 0`, 0 outside, over about a pixel of a `size` image.
+fn edge(distance: f32, size: u32) -> f32 {
+    0.5 - distance * size as f32 / 2.0
+}
+
+fn disc(size: u32, radius: f32, colour: [f32; 3], alpha: f32) -> RgbaImage {
+    rasterize(size, size, colour, |u, v| edge((u * u + v * v).sqrt() - radius, size).min(1.0) * alpha)
+}
+
+fn ring(size: u32, radius: f32, thickness: f32, colour: [f32; 3]) -> RgbaImage {
+    rasterize(size, size, colour, |u, v| {
+        let r = (u * u + v * v).sqrt();
+        edge(r - radius, size).min(edge(radius - thickness - r, size))
+    })
+}
+
+fn glow(size: u32, colour: [f32; 3]) -> RgbaImage {
+    rasterize(size, size, colour, |u, v| 1.0 - (u * u + v * v).sqrt())
+}
+
+fn cursor(size: u32) -> RgbaImage {
+    let mut image = disc(size, 0.9, [1.0, 0.85, 0.4], 1.0);
+    let centre = disc(size, 0.5, [1.0, 1.0, 1.0], 1.0);
+    for (pixel, centre) in image.pixels_mut().zip(centre.pixels()) {
+        if centre[3] > 0 {
+            let t = centre[3] as f32 / 255.0;
+            for channel in 0..3 {
+                pixel[channel] = (pixel[channel] as f32 * (1.0 - t) + centre[channel] as f32 * t) as u8;
+            }
+        }
+    }
+    image
+}
+
+/// White glyph from a signed distance in -1..1 coordinates.
+fn font_symbol(width: u32, height: u32, distance: impl Fn(f32, f32) -> f32) -> RgbaImage {
+    rasterize(width, height, [1.0, 1.0, 1.0], |u, v| edge(distance(u, v), height))
+}
+
+fn seven_segment(digit: u8, width: u32, height: u32) -> RgbaImage {
+    // Segments a..g, clockwise from the top, then the middle bar
+    const DIGITS: [u8; 10] = [
+        0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110,
+        0b1101101, 0b1111101, 0b0000111, 0b1111111, 0b1101111,
+    ];
+    // Segment boxes as (centre, half size) in -1..1 coordinates
+    let (w, h, t) = (0.6, 0.8, 0.1);
+    let horizontal = (w, t);
+    // Same thickness in pixels as the horizontal segments
+    let vertical = (t * height as f32 / width as f32, h / 2.0);
+    let segments = [
+        ((0.0, -h), horizontal),
+        ((w, -h / 2.0), vertical),
+        ((w, h / 2.0), vertical),
+        ((0.0, h), horizontal),
+        ((-w, h / 2.0), vertical),
+        ((-w, -h / 2.0), vertical),
+        ((0.0, 0.0), horizontal),
+    ];
+
+    rasterize(width, height, [1.0, 1.0, 1.0], |u, v| {
+        segments
+            .iter()
+            .enumerate()
+            .filter(|(segment, _)| DIGITS[digit as usize] & (1 << segment) != 0)
+            .map(|(_, ((x, y), (half_width, half_height)))| {
+                let distance = ((u - x).abs() - half_width).max((v - y).abs() - half_height);
+                edge(distance, height)
+            })
+            .fold(0.0, f32::max)
+    })
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+pub type Colour = [u8; 4];
+
+/// `colour` with its components scaled to 0..1.
+pub fn normalize(colour: Colour) -> [f32; 4] {
+    colour.map(|component| component as f32 / 255.0)
+}
+
+/// The parts of a skin.ini we use. Unknown sections and keys are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkinIni {
+    pub name: String,
+    pub author: String,
+    pub cursor_centre: bool,
+    pub cursor_expand: bool,
+    pub cursor_rotate: bool,
+    pub combo_colours: Vec<Colour>,
+    pub slider_track_override: Option<Colour>,
+    pub slider_border: Colour,
+    pub slider_ball: Colour,
+    pub spinner_background: Colour,
+    pub hit_circle_prefix: String,
+    pub hit_circle_overlap: i32,
+    pub score_prefix: String,
+    pub score_overlap: i32,
+    pub combo_prefix: String,
+    pub combo_overlap: i32,
+}
+
+impl Default for SkinIni {
+    fn default() -> Self {
+        SkinIni {
+            name: "Default".to_string(),
+            author: String::new(),
+            cursor_centre: true,
+            cursor_expand: true,
+            cursor_rotate: true,
+            combo_colours: vec![
+                [255, 192, 0, 255],
+                [0, 202, 0, 255],
+                [18, 124, 255, 255],
+                [242, 24, 57, 255],
+            ],
+            slider_track_override: None,
+            slider_border: [255, 255, 255, 255],
+            slider_ball: [2, 170, 255, 255],
+            spinner_background: [100, 100, 100, 255],
+            hit_circle_prefix: "default".to_string(),
+            hit_circle_overlap: -2,
+            score_prefix: "score".to_string(),
+            score_overlap: 0,
+            combo_prefix: "score".to_string(),
+            combo_overlap: 0,
+        }
+    }
+}
+
+impl SkinIni {
+    /// Parses skin.ini text. Values that don't parse keep their defaults, as in osu!.
+    pub fn parse(text: &str) -> SkinIni {
+        let mut ini = SkinIni::default();
+        let mut combo_colours = HashMap::new();
+        let mut section = String::new();
+
+        for line in text.lines() {
+            let line = line.trim().trim_start_matches('\u{feff}');
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_string();
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.split("//").next().unwrap_or("").trim());
+
+            match (section.as_str(), key) {
+                ("General", "Name") => ini.name = value.to_string(),
+                ("General", "Author") => ini.author = value.to_string(),
+                ("General", "CursorCentre") => set_bool(&mut ini.cursor_centre, value),
+                ("General", "CursorExpand") => set_bool(&mut ini.cursor_expand, value),
+                ("General", "CursorRotate") => set_bool(&mut ini.cursor_rotate, value),
+                ("Colours", "SliderTrackOverride") => ini.slider_track_override = parse_colour(value),
+                ("Colours", "SliderBorder") => set_colour(&mut ini.slider_border, value),
+                ("Colours", "SliderBall") => set_colour(&mut ini.slider_ball, value),
+                ("Colours", "SpinnerBackground") => set_colour(&mut ini.spinner_background, value),
+                ("Colours", key) if key.starts_with("Combo") => {
+                    if let (Ok(index), Some(colour)) = (key[5..].parse::<u32>(), parse_colour(value)) {
+                        combo_colours.insert(index, colour);
+                    }
+                }
+                ("Fonts", "HitCirclePrefix") => ini.hit_circle_prefix = normalize_path(value),
+                ("Fonts", "HitCircleOverlap") => set_int(&mut ini.hit_circle_overlap, value),
+                ("Fonts", "ScorePrefix") => ini.score_prefix = normalize_path(value),
+                ("Fonts", "ScoreOverlap") => set_int(&mut ini.score_overlap, value),
+                ("Fonts", "ComboPrefix") => ini.combo_prefix = normalize_path(value),
+                ("Fonts", "ComboOverlap") => set_int(&mut ini.combo_overlap, value),
+                _ => {}
+            }
+        }
+
+        // Combo1..Combo8, in order; gaps are skipped
+        if !combo_colours.is_empty() {
+            let mut indices: Vec<&u32> = combo_colours.keys().collect();
+            indices.sort();
+            ini.combo_colours = indices.into_iter().map(|index| combo_colours[index]).collect();
+        }
+        ini
+    }
+
+    /// Combo colour for the `combo_index`th combo, cycling through the list.
+    pub fn combo_colour(&self, combo_index: usize) -> Colour {
+        self.combo_colours[combo_index % self.combo_colours.len()]
+    }
+}
+
+fn parse_colour(value: &str) -> Option<Colour> {
+    let components: Vec<u8> = value
+        .split(',')
+        .map(|component| component.trim().parse::<u8>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match components[..] {
+        [r, g, b] => Some([r, g, b, 255]),
+        [r, g, b, a] => Some([r, g, b, a]),
+        _ => None,
+    }
+}
+
+fn set_colour(target: &mut Colour, value: &str) {
+    if let Some(colour) = parse_colour(value) {
+        *target = colour;
+    }
+}
+
+fn set_bool(target: &mut bool, value: &str) {
+    match value {
+        "1" => *target = true,
+        "0" => *target = false,
+        _ => {}
+    }
+}
+
+fn set_int(target: &mut i32, value: &str) {
+    if let Ok(number) = value.parse() {
+        *target = number;
+    }
+}
+
+/// Skins made on Windows use backslashes in font prefixes.
+fn normalize_path(value: &str) -> String {
+    value.replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_skin_ini() {
+        let text = "\u{feff}[General]\n\
+            Name: Test Skin // comment\n\
+            CursorRotate: 0\n\
+            \n\
+            [Colours]\n\
+            Combo2: 10,20,30\n\
+            Combo1: 255,0,0,128\n\
+            SliderTrackOverride: 1,2,3\n\
+            SliderBorder: bad\n\
+            \n\
+            [Fonts]\n\
+            HitCirclePrefix: fonts\\hitcircle\n\
+            HitCircleOverlap: 3\n";
+        let ini = SkinIni::parse(text);
+        assert_eq!(ini.name, "Test Skin");
+        assert!(!ini.cursor_rotate);
+        assert_eq!(ini.combo_colours, vec![[255, 0, 0, 128], [10, 20, 30, 255]]);
+        assert_eq!(ini.combo_colour(3), [10, 20, 30, 255]);
+        assert_eq!(ini.slider_track_override, Some([1, 2, 3, 255]));
+        assert_eq!(ini.slider_border, SkinIni::default().slider_border);
+        assert_eq!(ini.hit_circle_prefix, "fonts/hitcircle");
+        assert_eq!(ini.hit_circle_overlap, 3);
+        assert_eq!(ini.score_prefix, "score");
+    }
+}
@@ -0,0 +1,261 @@
+mod default;
+mod ini;
+
+pub use ini::{normalize, SkinIni};
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use image::RgbaImage;
+use crate::atlas::{Atlas, AtlasBuilder, DEFAULT_PADDING, DEFAULT_PAGE_SIZE};
+use crate::render_cache::RenderCache;
+
+/// Elements looked up in a skin folder, besides the font digits.
+/// Sliders and spinners are drawn from shapes tinted with the skin.ini colours instead.
+pub const ELEMENTS: &[&str] = &["hitcircle", "hitcircleoverlay", "approachcircle", "cursor", "cursortrail", "cursormiddle"];
+
+/// Glyphs of a skin font besides 0-9, as in `score-comma.png`.
+pub const FONT_SYMBOLS: &[&str] = &["comma", "dot", "percent", "x"];
+
+/// One frame of a skin element.
+#[derive(Clone, Debug)]
+pub struct SkinImage {
+    pub image: RgbaImage,
+    /// Image pixels per osu!pixel: 2 for @2x images, 1 otherwise.
+    pub scale: f32,
+}
+
+impl SkinImage {
+    /// Size in osu!pixels, the same for @2x and regular images.
+    pub fn size(&self) -> (f32, f32) {
+        (self.image.width() as f32 / self.scale, self.image.height() as f32 / self.scale)
+    }
+}
+
+pub struct Skin {
+    pub ini: SkinIni,
+    /// Frames of every element by name, e.g. "hitcircle" or "score-3".
+    elements: HashMap<String, Vec<SkinImage>>,
+    /// Elements found in the skin folder rather than taken from the built-in skin.
+    own_elements: HashSet<String>,
+}
+
+impl Skin {
+    /// The built-in skin.
+    pub fn new() -> Skin {
+        let mut skin = Skin {
+            ini: SkinIni::default(),
+            elements: HashMap::new(),
+            own_elements: HashSet::new(),
+        };
+        skin.fill_defaults();
+        skin
+    }
+
+    pub fn load_or_default(path: &str) -> Skin {
+        match Skin::load(path) {
+            Ok(skin) => {
+                log::info!("Loaded skin {} by {}", skin.ini.name, skin.ini.author);
+                skin
+            }
+            Err(err) => {
+                log::warn!("Couldn't load skin from {}: {}, using the default skin", path, err);
+                Skin::new()
+            }
+        }
+    }
+
+    /// Loads a skin folder. Elements it doesn't have come from the built-in skin.
+    pub fn load(path: &str) -> Result<Skin, SkinError> {
+        let dir = Path::new(path);
+        if !dir.is_dir() {
+            return Err(SkinError::FileError);
+        }
+
+        let ini = match std::fs::read(dir.join("skin.ini")) {
+            Ok(bytes) => SkinIni::parse(&String::from_utf8_lossy(&bytes)),
+            Err(_) => SkinIni::default(),
+        };
+
+        let mut elements = HashMap::new();
+        let mut names: Vec<String> = ELEMENTS.iter().map(|name| name.to_string()).collect();
+        names.extend(font_glyph_names(&ini));
+        for name in names {
+            if let Some(image) = load_image(dir, &name)? {
+                elements.insert(name, vec![image]);
+            }
+        }
+
+        let own_elements = elements.keys().cloned().collect();
+        let mut skin = Skin { ini, elements, own_elements };
+        skin.fill_defaults();
+        Ok(skin)
+    }
+
+    fn fill_defaults(&mut self) {
+        let mut names: Vec<String> = ELEMENTS.iter().map(|name| name.to_string()).collect();
+        names.extend(font_glyph_names(&self.ini));
+        for name in names {
+            if self.elements.contains_key(&name) {
+                continue;
+            }
+            if let Some(image) = default::element(&name) {
+                self.elements.insert(name, vec![SkinImage { image, scale: 1.0 }]);
+            }
+        }
+    }
+
+    /// Whether the skin folder has its own `name` element, rather than the built-in one.
+    pub fn has_own(&self, name: &str) -> bool {
+        self.own_elements.contains(name)
+    }
+
+    /// First frame of an element.
+    pub fn image(&self, name: &str) -> Option<&SkinImage> {
+        self.frames(name).first()
+    }
+
+    pub fn frames(&self, name: &str) -> &[SkinImage] {
+        self.elements.get(name).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Glyph of a skin font, e.g. `glyph(&skin.ini.score_prefix, '7')`.
+    pub fn glyph(&self, prefix: &str, character: char) -> Option<&SkinImage> {
+        self.image(&glyph_name(prefix, character)?)
+    }
+
+    /// Packs every frame of every element into an atlas keyed by (name, frame).
+    pub fn build_atlas(&self, device: &wgpu::Device, queue: &wgpu::Queue, cache: &mut RenderCache) -> anyhow::Result<Atlas<(String, usize)>> {
+        let mut builder = AtlasBuilder::new(DEFAULT_PAGE_SIZE, DEFAULT_PADDING);
+        for (name, frames) in &self.elements {
+            for (index, frame) in frames.iter().enumerate() {
+                builder.add((name.clone(), index), frame.image.clone());
+            }
+        }
+        builder.build(device, queue, cache)
+    }
+}
+
+/// Element name of a font glyph, e.g. "score-comma" for ','.
+pub fn glyph_name(prefix: &str, character: char) -> Option<String> {
+    let suffix = match character {
+        '0'..='9' => character.to_string(),
+        ',' => "comma".to_string(),
+        '.' => "dot".to_string(),
+        '%' => "percent".to_string(),
+        'x' => "x".to_string(),
+        _ => return None,
+    };
+    Some(format!("{}-{}", prefix, suffix))
+}
+
+fn font_glyph_names(ini: &SkinIni) -> Vec<String> {
+    let mut prefixes = vec![&ini.hit_circle_prefix, &ini.score_prefix, &ini.combo_prefix];
+    prefixes.dedup();
+    prefixes
+        .into_iter()
+        .flat_map(|prefix| {
+            (0..10)
+                .map(|digit| digit.to_string())
+                .chain(FONT_SYMBOLS.iter().map(|symbol| symbol.to_string()))
+                .map(move |suffix| format!("{}-{}", prefix, suffix))
+        })
+        .collect()
+}
+
+/// Loads `name-0`, `name-1`, ... if the first exists, otherwise just `name`.
+/// Loads `name@2x.png`, falling back to `name.png`.
+fn load_image(dir: &Path, name: &str) -> Result<Option<SkinImage>, SkinError> {
+    for (suffix, scale) in [("@2x", 2.0), ("", 1.0)] {
+        let path = find_file(dir, &format!("{}{}.png", name, suffix));
+        if let Some(path) = path {
+            let image = image::open(&path).map_err(|_| SkinError::ImageError(path))?;
+            return Ok(Some(SkinImage { image: image.to_rgba8(), scale }));
+        }
+    }
+    Ok(None)
+}
+
+/// Skins are made on Windows, so file names are matched case-insensitively.
+fn find_file(dir: &Path, file_name: &str) -> Option<PathBuf> {
+    let path = dir.join(file_name);
+    if path.is_file() {
+        return Some(path);
+    }
+    let parent = path.parent()?;
+    let file_name = path.file_name()?.to_str()?.to_lowercase();
+    std::fs::read_dir(parent)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.is_file() && path.file_name().and_then(|name| name.to_str()).map(str::to_lowercase) == Some(file_name.clone())
+        })
+}
+
+#[derive(Debug)]
+pub enum SkinError {
+    FileError,
+    ImageError(PathBuf),
+}
+
+impl Display for SkinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkinError::FileError => write!(f, "FileError"),
+            SkinError::ImageError(path) => write!(f, "ImageError in {}", path.display()),
+        }
+    }
+}
+
+impl Error for SkinError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_skin_is_complete() {
+        let skin = Skin::new();
+        for name in ELEMENTS {
+            assert!(skin.image(name).is_some(), "{} is missing", name);
+        }
+        for digit in '0'..='9' {
+            assert!(skin.glyph(&skin.ini.score_prefix, digit).is_some());
+            assert!(skin.glyph(&skin.ini.hit_circle_prefix, digit).is_some());
+        }
+        for symbol in [',', '.', '%', 'x'] {
+            assert!(skin.glyph(&skin.ini.score_prefix, symbol).is_some());
+        }
+    }
+
+    #[test]
+    fn test_load_skin_folder() {
+        let dir = std::env::temp_dir().join(format!("skin_test_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("fonts")).unwrap();
+        std::fs::write(dir.join("skin.ini"), "[Fonts]\nScorePrefix: fonts\\num\n").unwrap();
+        RgbaImage::new(20, 10).save(dir.join("HitCircle@2x.png")).unwrap();
+        RgbaImage::new(20, 10).save(dir.join("hitcircle.png")).unwrap();
+        RgbaImage::new(4, 4).save(dir.join("Cursor.png")).unwrap();
+        RgbaImage::new(6, 6).save(dir.join("fonts").join("num-5.png")).unwrap();
+
+        let skin = Skin::load(dir.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let hitcircle = skin.image("hitcircle").unwrap();
+        assert_eq!(hitcircle.scale, 2.0);
+        assert_eq!(hitcircle.size(), (10.0, 5.0));
+        assert_eq!(skin.frames("cursor").len(), 1);
+        assert_eq!(skin.image("cursor").unwrap().image.width(), 4);
+        assert_eq!(skin.glyph("fonts/num", '5').unwrap().image.width(), 6);
+        // Missing elements fall back to the built-in skin
+        assert_eq!(skin.image("hitcircleoverlay").unwrap().image.width(), 128);
+        assert!(skin.has_own("hitcircle") && !skin.has_own("hitcircleoverlay"));
+        assert_eq!(skin.glyph("fonts/num", '6').unwrap().image.width(), 48);
+    }
+}
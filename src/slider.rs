@@ -0,0 +1,459 @@
+use crate::hit_circle::CircleTiming;
+use crate::osu::{OsuMap, OsuSlider};
+
+/// Segments used for round caps/joins of the body mesh.
+const CAP_SEGMENTS: u32 = 24;
+/// Steps per Catmull-Rom segment, matching osu!.
+const CATMULL_STEPS: u32 = 50;
+/// Target distance between sampled points on curved segments, in osu!pixels.
+const CURVE_TOLERANCE: f32 = 2.0;
+/// Slider bodies fade out over this long after the slider ends.
+pub const SLIDER_FADE_OUT_MS: f32 = 240.0;
+
+/// The slider's track as a polyline in osu!pixels, cut to the slider's pixel length.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SliderPath {
+    pub points: Vec<(f32, f32)>,
+    /// Cumulative length up to each point, `lengths[0] == 0`.
+    pub lengths: Vec<f32>,
+}
+
+impl SliderPath {
+    pub fn from_slider(slider: &OsuSlider) -> SliderPath {
+        let mut control_points = vec![(slider.x, slider.y)];
+        control_points.extend_from_slice(&slider.curve_points);
+        Self::new(&slider.curve_type, &control_points, slider.pixel_length)
+    }
+
+    pub fn new(curve_type: &str, control_points: &[(f32, f32)], pixel_length: f32) -> SliderPath {
+        let points = match curve_type {
+            "L" => control_points.to_vec(),
+            "P" if control_points.len() == 3 => perfect_circle(control_points)
+                .unwrap_or_else(|| bezier(control_points)),
+            "C" => catmull(control_points),
+            _ => bezier(control_points),
+        };
+        Self::from_points(points, pixel_length)
+    }
+
+    /// Builds a path from an already sampled polyline, truncating or extending it to `pixel_length`.
+    /// A non-positive `pixel_length` keeps the polyline as is.
+    pub fn from_points(mut points: Vec<(f32, f32)>, pixel_length: f32) -> SliderPath {
+        points.dedup();
+        if points.is_empty() {
+            points.push((0.0, 0.0));
+        }
+
+        let mut path = SliderPath {
+            points: vec![points[0]],
+            lengths: vec![0.0],
+        };
+        for point in points.iter().skip(1) {
+            let length = path.length() + distance(*path.points.last().unwrap(), *point);
+            if pixel_length > 0.0 && length >= pixel_length {
+                let last = *path.points.last().unwrap();
+                let t = (pixel_length - path.length()) / (length - path.length());
+                path.points.push(lerp(last, *point, t));
+                path.lengths.push(pixel_length);
+                return path;
+            }
+            path.points.push(*point);
+            path.lengths.push(length);
+        }
+
+        // The control points describe a shorter path than the map asks for, extend the last segment
+        if pixel_length > path.length() && path.points.len() >= 2 {
+            let n = path.points.len();
+            let (a, b) = (path.points[n - 2], path.points[n - 1]);
+            let segment = distance(a, b);
+            let extra = pixel_length - path.length();
+            path.points.push((
+                b.0 + (b.0 - a.0) / segment * extra,
+                b.1 + (b.1 - a.1) / segment * extra,
+            ));
+            path.lengths.push(pixel_length);
+        }
+        path
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.lengths.last().unwrap_or(&0.0)
+    }
+
+    /// Position at `progress` (0 = head, 1 = tail) along the path.
+    pub fn position_at(&self, progress: f32) -> (f32, f32) {
+        let target = progress.clamp(0.0, 1.0) * self.length();
+        let index = self.lengths.partition_point(|length| *length < target);
+        if index == 0 {
+            return self.points[0];
+        }
+        if index >= self.points.len() {
+            return *self.points.last().unwrap();
+        }
+        let segment = self.lengths[index] - self.lengths[index - 1];
+        let t = if segment > 0.0 { (target - self.lengths[index - 1]) / segment } else { 0.0 };
+        lerp(self.points[index - 1], self.points[index], t)
+    }
+
+    /// The part of the path between `start` and `end` (fractions of the length), used for snaking.
+    pub fn sub_path(&self, start: f32, end: f32) -> Vec<(f32, f32)> {
+        let start = start.clamp(0.0, 1.0);
+        let end = end.clamp(start, 1.0);
+        let (start_length, end_length) = (start * self.length(), end * self.length());
+
+        let mut points = vec![self.position_at(start)];
+        for (point, length) in self.points.iter().zip(&self.lengths) {
+            if *length > start_length && *length < end_length {
+                points.push(*point);
+            }
+        }
+        points.push(self.position_at(end));
+        points.dedup();
+        points
+    }
+}
+
+/// Timing of a slider, in gameplay milliseconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SliderTiming {
+    pub start_time: f32,
+    /// Duration of a single pass over the path.
+    pub span_duration: f32,
+    pub repeats: u32,
+    /// How long before `start_time` the slider appears.
+    pub preempt: f32,
+    pub fade_in: f32,
+}
+
+/// What a slider looks like at a given time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SliderFrame {
+    pub snake_start: f32,
+    pub snake_end: f32,
+    pub alpha: f32,
+    /// Position of the ball along the path, while the slider is being played.
+    pub ball_progress: Option<f32>,
+}
+
+impl SliderTiming {
+    /// Timing of `slider` from the beatmap's timing points, appearing like a circle would.
+    pub fn from_map(slider: &OsuSlider, map: &OsuMap, circle_timing: &CircleTiming) -> SliderTiming {
+        SliderTiming {
+            start_time: slider.time as f32,
+            span_duration: map.slider_span_duration(slider),
+            repeats: slider.repeat,
+            preempt: circle_timing.preempt,
+            fade_in: circle_timing.fade_in,
+        }
+    }
+
+    pub fn end_time(&self) -> f32 {
+        self.start_time + self.span_duration * self.repeats.max(1) as f32
+    }
+
+    pub fn frame_at(&self, time: f32) -> Option<SliderFrame> {
+        let appear_time = self.start_time - self.preempt;
+        if time < appear_time || time > self.end_time() + SLIDER_FADE_OUT_MS {
+            return None;
+        }
+
+        let mut alpha = ((time - appear_time) / self.fade_in.max(1.0)).min(1.0);
+        if time > self.end_time() {
+            alpha *= 1.0 - (time - self.end_time()) / SLIDER_FADE_OUT_MS;
+        }
+
+        // Snake in over the first third of the approach
+        let mut snake_start = 0.0;
+        let mut snake_end = ((time - appear_time) / (self.preempt / 3.0).max(1.0)).min(1.0);
+        let mut ball_progress = None;
+
+        if time >= self.start_time && time <= self.end_time() && self.span_duration > 0.0 {
+            let repeats = self.repeats.max(1);
+            let spans = ((time - self.start_time) / self.span_duration).min(repeats as f32);
+            let span = (spans.floor() as u32).min(repeats - 1);
+            let fraction = spans - span as f32;
+            let forward = span.is_multiple_of(2);
+            let progress = if forward { fraction } else { 1.0 - fraction };
+            ball_progress = Some(progress);
+
+            // Snake out behind the ball on the last pass
+            if span == repeats - 1 {
+                if forward {
+                    snake_start = progress;
+                } else {
+                    snake_end = progress;
+                }
+            }
+        } else if time > self.end_time() {
+            // The body is gone once the ball has travelled it for the last time
+            snake_start = if self.repeats.max(1) % 2 == 1 { 1.0 } else { 0.0 };
+            snake_end = snake_start;
+        }
+
+        Some(SliderFrame {
+            snake_start,
+            snake_end,
+            alpha: alpha.max(0.0),
+            ball_progress,
+        })
+    }
+}
+
+/// Vertex of the body mesh. `position.z` is the distance from the path in units of the
+/// radius, so the depth test keeps the closest segment where the mesh overlaps itself.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SliderVertex {
+    pub position: [f32; 3],
+}
+
+impl SliderVertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SliderVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+            ]
+        }
+    }
+}
+
+/// Tessellates a thick polyline with round caps and joins as a triangle list.
+/// Every segment is a ridge and every point a cone, both peaking (z = 0) on the path.
+pub fn body_mesh(points: &[(f32, f32)], radius: f32) -> Vec<SliderVertex> {
+    let mut vertices = Vec::new();
+    let vertex = |x: f32, y: f32, z: f32| SliderVertex { position: [x, y, z] };
+
+    for pair in points.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let length = distance(a, b);
+        if length <= f32::EPSILON {
+            continue;
+        }
+        let normal = (-(b.1 - a.1) / length * radius, (b.0 - a.0) / length * radius);
+
+        for side in [1.0, -1.0] {
+            let (nx, ny) = (normal.0 * side, normal.1 * side);
+            vertices.push(vertex(a.0, a.1, 0.0));
+            vertices.push(vertex(b.0, b.1, 0.0));
+            vertices.push(vertex(b.0 + nx, b.1 + ny, 1.0));
+            vertices.push(vertex(a.0, a.1, 0.0));
+            vertices.push(vertex(b.0 + nx, b.1 + ny, 1.0));
+            vertices.push(vertex(a.0 + nx, a.1 + ny, 1.0));
+        }
+    }
+
+    for point in points {
+        for i in 0..CAP_SEGMENTS {
+            let theta_a = 2.0 * std::f32::consts::PI * i as f32 / CAP_SEGMENTS as f32;
+            let theta_b = 2.0 * std::f32::consts::PI * (i + 1) as f32 / CAP_SEGMENTS as f32;
+            vertices.push(vertex(point.0, point.1, 0.0));
+            vertices.push(vertex(point.0 + radius * theta_a.cos(), point.1 + radius * theta_a.sin(), 1.0));
+            vertices.push(vertex(point.0 + radius * theta_b.cos(), point.1 + radius * theta_b.sin(), 1.0));
+        }
+    }
+
+    vertices
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+fn lerp(a: (f32, f32), b: (f32, f32), t: f32) -> (f32, f32) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+fn perfect_circle(points: &[(f32, f32)]) -> Option<Vec<(f32, f32)>> {
+    let (a, b, c) = (points[0], points[1], points[2]);
+    let d = 2.0 * (a.0 * (b.1 - c.1) + b.0 * (c.1 - a.1) + c.0 * (a.1 - b.1));
+    if d.abs() < 1e-3 {
+        return None;
+    }
+
+    let a_sq = a.0 * a.0 + a.1 * a.1;
+    let b_sq = b.0 * b.0 + b.1 * b.1;
+    let c_sq = c.0 * c.0 + c.1 * c.1;
+    let center = (
+        (a_sq * (b.1 - c.1) + b_sq * (c.1 - a.1) + c_sq * (a.1 - b.1)) / d,
+        (a_sq * (c.0 - b.0) + b_sq * (a.0 - c.0) + c_sq * (b.0 - a.0)) / d,
+    );
+    let radius = distance(a, center);
+
+    let start_angle = (a.1 - center.1).atan2(a.0 - center.0);
+    let mut end_angle = (c.1 - center.1).atan2(c.0 - center.0);
+    // Go around the circle in the direction that passes through the middle point
+    let clockwise = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0) < 0.0;
+    let tau = 2.0 * std::f32::consts::PI;
+    if clockwise {
+        while end_angle > start_angle {
+            end_angle -= tau;
+        }
+    } else {
+        while end_angle < start_angle {
+            end_angle += tau;
+        }
+    }
+
+    let arc_length = (end_angle - start_angle).abs() * radius;
+    let steps = ((arc_length / CURVE_TOLERANCE).ceil() as u32).clamp(2, 1000);
+    Some(
+        (0..=steps)
+            .map(|i| {
+                let angle = start_angle + (end_angle - start_angle) * i as f32 / steps as f32;
+                (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+            })
+            .collect(),
+    )
+}
+
+/// Bezier curve; a repeated control point ("red anchor") starts a new segment.
+fn bezier(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let mut result = Vec::new();
+    let mut segment_start = 0;
+    for i in 1..=points.len() {
+        if i == points.len() || points[i] == points[i - 1] {
+            let segment = &points[segment_start..i];
+            result.extend(bezier_segment(segment));
+            segment_start = i;
+        }
+    }
+    result
+}
+
+fn bezier_segment(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let control_length: f32 = points.windows(2).map(|pair| distance(pair[0], pair[1])).sum();
+    let steps = ((control_length / CURVE_TOLERANCE).ceil() as u32).clamp(2, 1000);
+
+    let mut scratch = points.to_vec();
+    (0..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            scratch.copy_from_slice(points);
+            for level in (1..points.len()).rev() {
+                for j in 0..level {
+                    scratch[j] = lerp(scratch[j], scratch[j + 1], t);
+                }
+            }
+            scratch[0]
+        })
+        .collect()
+}
+
+fn catmull(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    if points.len() < 2 {
+        return points.to_vec();
+    }
+    let mut result = Vec::new();
+    for i in 0..points.len() - 1 {
+        let v1 = if i > 0 { points[i - 1] } else { points[i] };
+        let v2 = points[i];
+        let v3 = points[i + 1];
+        let v4 = if i + 2 < points.len() {
+            points[i + 2]
+        } else {
+            (2.0 * v3.0 - v2.0, 2.0 * v3.1 - v2.1)
+        };
+
+        for step in 0..CATMULL_STEPS {
+            let t = step as f32 / CATMULL_STEPS as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            let catmull_rom = |p1: f32, p2: f32, p3: f32, p4: f32| {
+                0.5 * (2.0 * p2
+                    + (-p1 + p3) * t
+                    + (2.0 * p1 - 5.0 * p2 + 4.0 * p3 - p4) * t2
+                    + (-p1 + 3.0 * p2 - 3.0 * p3 + p4) * t3)
+            };
+            result.push((
+                catmull_rom(v1.0, v2.0, v3.0, v4.0),
+                catmull_rom(v1.1, v2.1, v3.1, v4.1),
+            ));
+        }
+    }
+    result.push(*points.last().unwrap());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: (f32, f32), b: (f32, f32)) {
+        assert!((a.0 - b.0).abs() < 0.5 && (a.1 - b.1).abs() < 0.5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_linear_path_is_cut_to_pixel_length() {
+        let path = SliderPath::new("L", &[(0.0, 0.0), (100.0, 0.0), (100.0, 100.0)], 150.0);
+        assert_eq!(path.length(), 150.0);
+        assert_close(*path.points.last().unwrap(), (100.0, 50.0));
+        assert_close(path.position_at(0.5), (75.0, 0.0));
+    }
+
+    #[test]
+    fn test_linear_path_is_extended_to_pixel_length() {
+        let path = SliderPath::new("L", &[(0.0, 0.0), (50.0, 0.0)], 80.0);
+        assert_close(path.position_at(1.0), (80.0, 0.0));
+    }
+
+    #[test]
+    fn test_perfect_circle_passes_through_points() {
+        // Half circle of radius 50 around (50, 0)
+        let path = SliderPath::new("P", &[(0.0, 0.0), (50.0, 50.0), (100.0, 0.0)], 0.0);
+        assert!((path.length() - 50.0 * std::f32::consts::PI).abs() < 1.0);
+        assert_close(path.position_at(0.5), (50.0, 50.0));
+        assert_close(path.position_at(1.0), (100.0, 0.0));
+    }
+
+    #[test]
+    fn test_bezier_red_anchor_splits_segments() {
+        let path = SliderPath::new("B", &[(0.0, 0.0), (100.0, 0.0), (100.0, 0.0), (100.0, 100.0)], 0.0);
+        assert!((path.length() - 200.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_sub_path() {
+        let path = SliderPath::new("L", &[(0.0, 0.0), (100.0, 0.0)], 100.0);
+        assert_eq!(path.sub_path(0.25, 0.5), vec![(25.0, 0.0), (50.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_slider_frame_snaking_and_ball() {
+        let timing = SliderTiming {
+            start_time: 1000.0,
+            span_duration: 500.0,
+            repeats: 2,
+            preempt: 600.0,
+            fade_in: 400.0,
+        };
+        assert_eq!(timing.frame_at(300.0), None);
+
+        let approaching = timing.frame_at(500.0).unwrap();
+        assert_eq!(approaching.snake_end, 0.5);
+        assert_eq!(approaching.ball_progress, None);
+
+        let first_span = timing.frame_at(1250.0).unwrap();
+        assert_eq!(first_span.ball_progress, Some(0.5));
+        assert_eq!((first_span.snake_start, first_span.snake_end), (0.0, 1.0));
+
+        // Second pass runs back towards the head and the body retracts behind it
+        let second_span = timing.frame_at(1750.0).unwrap();
+        assert_eq!(second_span.ball_progress, Some(0.5));
+        assert_eq!((second_span.snake_start, second_span.snake_end), (0.0, 0.5));
+    }
+
+    #[test]
+    fn test_body_mesh_size() {
+        let mesh = body_mesh(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)], 5.0);
+        assert_eq!(mesh.len(), (2 * 12 + 3 * CAP_SEGMENTS * 3) as usize);
+        assert!(mesh.iter().all(|v| v.position[2] == 0.0 || v.position[2] == 1.0));
+    }
+}
@@ -0,0 +1,128 @@
+// Maps osu!pixels to clip space, see Playfield::uniform
+struct PlayfieldUniform {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+};
+@group(0) @binding(0)
+var<uniform> playfield: PlayfieldUniform;
+
+// Slider bodies
+
+struct BodyInput {
+    // z is the distance from the path in units of the radius
+    @location(0) position: vec3<f32>,
+    @location(1) track_colour: vec4<f32>,
+    @location(2) border_colour: vec4<f32>,
+    // x: alpha, y: border width as a fraction of the radius, z: layer, w: layer count
+    @location(3) params: vec4<f32>,
+}
+
+struct BodyOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) distance: f32,
+    @location(1) @interpolate(flat) track_colour: vec4<f32>,
+    @location(2) @interpolate(flat) border_colour: vec4<f32>,
+    @location(3) @interpolate(flat) params: vec4<f32>,
+};
+
+@vertex
+fn vs_body(in: BodyInput) -> BodyOutput {
+    var out: BodyOutput;
+    let clip_2dpos = in.position.xy * playfield.scale + playfield.offset;
+    // Lower layers are in front; within a slider the closest segment wins
+    let depth = (in.params.z + in.position.z * 0.999) / in.params.w;
+    out.clip_position = vec4<f32>(clip_2dpos, depth, 1.0);
+    out.distance = in.position.z;
+    out.track_colour = in.track_colour;
+    out.border_colour = in.border_colour;
+    out.params = in.params;
+    return out;
+}
+
+@fragment
+fn fs_body(in: BodyOutput) -> @location(0) vec4<f32> {
+    let border_start = 1.0 - in.params.y;
+    var colour: vec4<f32>;
+    if (in.distance >= border_start) {
+        colour = in.border_colour;
+    } else {
+        // Track gets lighter towards the middle
+        let inner = vec4<f32>(min(in.track_colour.rgb * 1.5, vec3<f32>(1.0)), in.track_colour.a);
+        colour = mix(inner, in.track_colour, in.distance / border_start);
+    }
+    let alpha = colour.a * in.params.x;
+    return vec4<f32>(colour.rgb * alpha, alpha);
+}
+
+// Composite of the offscreen body texture onto the frame
+
+@group(0) @binding(0)
+var t_bodies: texture_2d<f32>;
+@group(0) @binding(1)
+var s_bodies: sampler;
+
+struct CompositeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_composite(@builtin(vertex_index) index: u32) -> CompositeOutput {
+    // Single triangle covering the screen
+    var out: CompositeOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@fragment
+fn fs_composite(in: CompositeOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_bodies, s_bodies, in.tex_coords);
+}
+
+// Slider ball and follow circle
+
+struct BallInput {
+    @location(0) center: vec2<f32>,
+    @location(1) radius: f32,
+    // 0 draws a filled disc, otherwise a ring this thick (fraction of the radius)
+    @location(2) thickness: f32,
+    @location(3) colour: vec4<f32>,
+}
+
+struct BallOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) local: vec2<f32>,
+    @location(1) @interpolate(flat) thickness: f32,
+    @location(2) @interpolate(flat) colour: vec4<f32>,
+};
+
+@vertex
+fn vs_ball(@builtin(vertex_index) index: u32, in: BallInput) -> BallOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let local = corners[index];
+    var out: BallOutput;
+    let position = in.center + local * in.radius;
+    out.clip_position = vec4<f32>(position * playfield.scale + playfield.offset, 0.0, 1.0);
+    out.local = local;
+    out.thickness = in.thickness;
+    out.colour = in.colour;
+    return out;
+}
+
+@fragment
+fn fs_ball(in: BallOutput) -> @location(0) vec4<f32> {
+    let r = length(in.local);
+    let aa = fwidth(r);
+    var coverage = 1.0 - smoothstep(1.0 - aa, 1.0, r);
+    if (in.thickness > 0.0) {
+        let inner = 1.0 - in.thickness;
+        coverage *= smoothstep(inner - aa, inner, r);
+    }
+    let alpha = in.colour.a * coverage;
+    return vec4<f32>(in.colour.rgb * alpha, alpha);
+}
@@ -0,0 +1,403 @@
+use std::ops::Range;
+use crate::gpu::create_vertex_buffer;
+use crate::profiler::GpuProfiler;
+use crate::render_cache::{BindGroupLayoutId, PipelineDescriptor, PipelineId, RenderCache};
+use crate::skin::{normalize, SkinIni};
+use crate::slider::{body_mesh, SliderFrame, SliderPath, SliderVertex};
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Follow circle size relative to the circle radius while the slider is held.
+const FOLLOW_CIRCLE_SCALE: f32 = 2.4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SliderStyle {
+    /// Track colour of sliders that don't bring their own.
+    pub track_colour: [f32; 4],
+    /// Track colour used for every slider, over their own.
+    pub track_override: Option<[f32; 4]>,
+    pub border_colour: [f32; 4],
+    /// Border width as a fraction of the circle radius.
+    pub border_width: f32,
+    pub ball_colour: [f32; 4],
+    pub follow_circle_colour: [f32; 4],
+}
+
+impl Default for SliderStyle {
+    fn default() -> Self {
+        SliderStyle {
+            track_colour: [0.1, 0.1, 0.1, 0.8],
+            track_override: None,
+            border_colour: [1.0, 1.0, 1.0, 1.0],
+            border_width: 0.125,
+            ball_colour: [1.0, 1.0, 1.0, 1.0],
+            follow_circle_colour: [1.0, 0.6, 0.1, 1.0],
+        }
+    }
+}
+
+impl SliderStyle {
+    /// Default style with the skin's slider colours.
+    pub fn from_ini(ini: &SkinIni) -> SliderStyle {
+        SliderStyle {
+            track_override: ini.slider_track_override.map(normalize),
+            border_colour: normalize(ini.slider_border),
+            ball_colour: normalize(ini.slider_ball),
+            ..SliderStyle::default()
+        }
+    }
+}
+
+/// One slider as it should be drawn this frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SliderDrawable {
+    /// Visible part of the path, in osu!pixels.
+    pub points: Vec<(f32, f32)>,
+    pub radius: f32,
+    pub alpha: f32,
+    pub ball: Option<(f32, f32)>,
+    pub track_colour: Option<[f32; 4]>,
+    /// Whether the ball is being followed, which shows the follow circle.
+    pub tracking: bool,
+}
+
+impl SliderDrawable {
+    pub fn new(path: &SliderPath, frame: &SliderFrame, radius: f32) -> SliderDrawable {
+        SliderDrawable {
+            points: path.sub_path(frame.snake_start, frame.snake_end),
+            radius,
+            alpha: frame.alpha,
+            ball: frame.ball_progress.map(|progress| path.position_at(progress)),
+            track_colour: None,
+            tracking: false,
+        }
+    }
+
+    pub fn with_track_colour(mut self, colour: [f32; 4]) -> Self {
+        self.track_colour = Some(colour);
+        self
+    }
+
+    pub fn with_tracking(mut self, tracking: bool) -> Self {
+        self.tracking = tracking;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BodyInstance {
+    track_colour: [f32; 4],
+    border_colour: [f32; 4],
+    params: [f32; 4],
+}
+
+impl BodyInstance {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+            wgpu::vertex_attr_array![1 => Float32x4, 2 => Float32x4, 3 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<BodyInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BallInstance {
+    center: [f32; 2],
+    radius: f32,
+    thickness: f32,
+    colour: [f32; 4],
+}
+
+impl BallInstance {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+            wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32, 2 => Float32, 3 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<BallInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+struct BodyTarget {
+    view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Draws slider bodies into an offscreen texture first, so overlapping parts of a body
+/// are only blended once, then composites that texture onto the frame.
+pub struct SliderRenderer {
+    pub style: SliderStyle,
+    format: wgpu::TextureFormat,
+    body_pipeline: PipelineId,
+    composite_pipeline: PipelineId,
+    ball_pipeline: PipelineId,
+    composite_bind_group_layout: BindGroupLayoutId,
+    sampler: wgpu::Sampler,
+    target: BodyTarget,
+    body_vertices: Option<wgpu::Buffer>,
+    body_instances: Option<wgpu::Buffer>,
+    body_draws: Vec<Range<u32>>,
+    ball_instances: Option<wgpu::Buffer>,
+    num_balls: u32,
+}
+
+impl SliderRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        cache: &mut RenderCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        width: u32,
+        height: u32,
+        playfield_bind_group_layout: BindGroupLayoutId,
+    ) -> Self {
+        let composite_bind_group_layout = cache.texture_layout_id(device);
+
+        // Bodies are drawn single-sampled into their own target, then composited with the frame's sample count
+        let body_pipeline = cache.pipeline_id(
+            device,
+            &PipelineDescriptor::new("slider.wgsl", &[playfield_bind_group_layout], format)
+                .with_entry_points("vs_body", "fs_body")
+                .with_vertex_layouts(&[SliderVertex::desc(), BodyInstance::desc()])
+                // The depth test already picks a single fragment per pixel
+                .with_blend(Some(wgpu::BlendState::REPLACE))
+                .with_depth_stencil(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+        );
+        let composite_pipeline = cache.pipeline_id(
+            device,
+            &PipelineDescriptor::new("slider.wgsl", &[composite_bind_group_layout], format)
+                .with_entry_points("vs_composite", "fs_composite")
+                .with_sample_count(sample_count),
+        );
+        let ball_pipeline = cache.pipeline_id(
+            device,
+            &PipelineDescriptor::new("slider.wgsl", &[playfield_bind_group_layout], format)
+                .with_entry_points("vs_ball", "fs_ball")
+                .with_vertex_layouts(&[BallInstance::desc()])
+                .with_sample_count(sample_count),
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Slider Composite Sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let target = Self::create_target(device, format, width, height, cache.bind_group_layout(composite_bind_group_layout), &sampler);
+
+        Self {
+            style: SliderStyle::default(),
+            format,
+            body_pipeline,
+            composite_pipeline,
+            ball_pipeline,
+            composite_bind_group_layout,
+            sampler,
+            target,
+            body_vertices: None,
+            body_instances: None,
+            body_draws: Vec::new(),
+            ball_instances: None,
+            num_balls: 0,
+        }
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> BodyTarget {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Slider Body Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Slider Body Depth"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Slider Composite Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+        BodyTarget { view, depth_view, bind_group }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, cache: &RenderCache, width: u32, height: u32) {
+        self.target = Self::create_target(
+            device,
+            self.format,
+            width,
+            height,
+            cache.bind_group_layout(self.composite_bind_group_layout),
+            &self.sampler,
+        );
+    }
+
+    /// Uploads this frame's sliders. The first slider is drawn on top.
+    pub fn prepare(&mut self, device: &wgpu::Device, sliders: &[SliderDrawable]) {
+        let mut vertices: Vec<SliderVertex> = Vec::new();
+        let mut instances = Vec::new();
+        let mut balls = Vec::new();
+        self.body_draws.clear();
+
+        let layers = sliders.len().max(1) as f32;
+        for (layer, slider) in sliders.iter().enumerate() {
+            let start = vertices.len() as u32;
+            vertices.extend(body_mesh(&slider.points, slider.radius));
+            self.body_draws.push(start..vertices.len() as u32);
+            instances.push(BodyInstance {
+                track_colour: self.style.track_override.or(slider.track_colour).unwrap_or(self.style.track_colour),
+                border_colour: self.style.border_colour,
+                params: [slider.alpha, self.style.border_width, layer as f32, layers],
+            });
+
+            if let Some(center) = slider.ball {
+                let alpha = slider.alpha;
+                if slider.tracking {
+                    balls.push(BallInstance {
+                        center: [center.0, center.1],
+                        radius: slider.radius * FOLLOW_CIRCLE_SCALE,
+                        thickness: 0.08,
+                        colour: with_alpha(self.style.follow_circle_colour, alpha),
+                    });
+                }
+                balls.push(BallInstance {
+                    center: [center.0, center.1],
+                    radius: slider.radius * 0.8,
+                    thickness: 0.0,
+                    colour: with_alpha(self.style.ball_colour, alpha),
+                });
+            }
+        }
+
+        self.body_vertices = create_vertex_buffer(device, "Slider Body Vertex Buffer", &vertices);
+        self.body_instances = create_vertex_buffer(device, "Slider Body Instance Buffer", &instances);
+        self.ball_instances = create_vertex_buffer(device, "Slider Ball Instance Buffer", &balls);
+        self.num_balls = balls.len() as u32;
+    }
+
+    /// Renders the prepared bodies into the offscreen texture. Must run before the main pass.
+    pub fn render_bodies(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        cache: &RenderCache,
+        profiler: &mut GpuProfiler,
+        playfield_bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Slider Body Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.target.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: profiler.timestamp_writes("Slider Body Pass"),
+        });
+
+        if let (Some(vertices), Some(instances)) = (&self.body_vertices, &self.body_instances) {
+            render_pass.set_pipeline(cache.pipeline(self.body_pipeline));
+            render_pass.set_bind_group(0, playfield_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertices.slice(..));
+            render_pass.set_vertex_buffer(1, instances.slice(..));
+            for (index, range) in self.body_draws.iter().enumerate() {
+                let instance = index as u32;
+                render_pass.draw(range.clone(), instance..instance + 1);
+            }
+        }
+    }
+
+    pub fn draw_bodies<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, cache: &'a RenderCache) {
+        if self.body_draws.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(cache.pipeline(self.composite_pipeline));
+        render_pass.set_bind_group(0, &self.target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    pub fn draw_balls<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, cache: &'a RenderCache, playfield_bind_group: &'a wgpu::BindGroup) {
+        if let Some(instances) = &self.ball_instances {
+            render_pass.set_pipeline(cache.pipeline(self.ball_pipeline));
+            render_pass.set_bind_group(0, playfield_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, instances.slice(..));
+            render_pass.draw(0..6, 0..self.num_balls);
+        }
+    }
+}
+
+fn with_alpha(colour: [f32; 4], alpha: f32) -> [f32; 4] {
+    [colour[0], colour[1], colour[2], colour[3] * alpha]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_style_from_ini() {
+        let ini = SkinIni::parse("[Colours]\nSliderTrackOverride: 255,0,0\nSliderBorder: 0,0,255\n");
+        let style = SliderStyle::from_ini(&ini);
+        assert_eq!(style.track_override, Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(style.border_colour, [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(style.ball_colour, normalize(SkinIni::default().slider_ball));
+        assert_eq!(SliderStyle::from_ini(&SkinIni::default()).track_override, None);
+    }
+}
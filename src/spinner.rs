@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use crate::osu::OsuSpinner;
+use crate::playfield::{PLAYFIELD_HEIGHT, PLAYFIELD_WIDTH};
+
+/// Spinning faster than this (rotations per second) doesn't count, as in osu!.
+pub const MAX_ROTATIONS_PER_SECOND: f32 = 8.0;
+/// Window over which the RPM readout is averaged, in milliseconds.
+const RPM_WINDOW_MS: f32 = 500.0;
+
+/// Judgement-side state of a spinner: how far the cursor has spun it so far.
+#[derive(Clone, Debug)]
+pub struct SpinnerState {
+    pub start_time: f32,
+    pub end_time: f32,
+    pub center: (f32, f32),
+    pub spins_required: f32,
+    /// Total counted rotation in radians, regardless of direction.
+    pub total_rotation: f32,
+    /// Signed rotation of the disc in radians, for drawing.
+    pub disc_rotation: f32,
+    last_angle: Option<f32>,
+    last_time: f32,
+    history: VecDeque<(f32, f32)>,
+    /// Times at which each bonus spin was reached, for the feedback popups.
+    pub bonus_times: Vec<f32>,
+}
+
+impl SpinnerState {
+    pub fn new(spinner: &OsuSpinner, overall_difficulty: f32) -> SpinnerState {
+        let duration_seconds = spinner.end_time.saturating_sub(spinner.time) as f32 / 1000.0;
+        let rotations_per_second = difficulty_range(overall_difficulty, 1.5, 2.5, 3.75);
+        SpinnerState {
+            start_time: spinner.time as f32,
+            end_time: spinner.end_time as f32,
+            center: (PLAYFIELD_WIDTH / 2.0, PLAYFIELD_HEIGHT / 2.0),
+            spins_required: (duration_seconds * rotations_per_second).floor().max(1.0),
+            total_rotation: 0.0,
+            disc_rotation: 0.0,
+            last_angle: None,
+            last_time: spinner.time as f32,
+            history: VecDeque::new(),
+            bonus_times: Vec::new(),
+        }
+    }
+
+    pub fn is_active(&self, time: f32) -> bool {
+        time >= self.start_time && time <= self.end_time
+    }
+
+    /// Feeds a cursor sample in osu!pixels. Rotation only counts while a key is held.
+    pub fn update(&mut self, time: f32, cursor: (f32, f32), holding: bool) {
+        if !self.is_active(time) {
+            self.last_angle = None;
+            return;
+        }
+
+        let angle = (cursor.1 - self.center.1).atan2(cursor.0 - self.center.0);
+        if let (Some(last_angle), true) = (self.last_angle, holding) {
+            let mut delta = angle - last_angle;
+            if delta > PI {
+                delta -= 2.0 * PI;
+            } else if delta < -PI {
+                delta += 2.0 * PI;
+            }
+
+            let elapsed_seconds = (time - self.last_time).max(0.0) / 1000.0;
+            let max_delta = MAX_ROTATIONS_PER_SECOND * 2.0 * PI * elapsed_seconds;
+            let delta = delta.clamp(-max_delta, max_delta);
+
+            self.total_rotation += delta.abs();
+            self.disc_rotation += delta;
+        }
+        self.last_angle = if holding { Some(angle) } else { None };
+        while (self.bonus_times.len() as u32) < self.bonus_spins() {
+            self.bonus_times.push(time);
+        }
+        self.last_time = time;
+
+        self.history.push_back((time, self.total_rotation));
+        while let Some((oldest, _)) = self.history.front() {
+            if time - oldest > RPM_WINDOW_MS {
+                self.history.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn spins(&self) -> f32 {
+        self.total_rotation / (2.0 * PI)
+    }
+
+    pub fn progress(&self) -> f32 {
+        (self.spins() / self.spins_required).min(1.0)
+    }
+
+    pub fn is_cleared(&self) -> bool {
+        self.spins() >= self.spins_required
+    }
+
+    pub fn rpm(&self) -> f32 {
+        match (self.history.front(), self.history.back()) {
+            (Some((start_time, start)), Some((end_time, end))) if end_time > start_time => {
+                (end - start) / (2.0 * PI) / (end_time - start_time) * 60_000.0
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Whole spins beyond what's required to clear.
+    pub fn bonus_spins(&self) -> u32 {
+        (self.spins() - self.spins_required).max(0.0).floor() as u32
+    }
+}
+
+/// osu!'s difficulty interpolation: `min` at 0, `mid` at 5 and `max` at 10.
+pub fn difficulty_range(difficulty: f32, min: f32, mid: f32, max: f32) -> f32 {
+    if difficulty > 5.0 {
+        mid + (max - mid) * (difficulty - 5.0) / 5.0
+    } else if difficulty < 5.0 {
+        mid - (mid - min) * (5.0 - difficulty) / 5.0
+    } else {
+        mid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spin(state: &mut SpinnerState, from: f32, to: f32, rotations: f32, steps: u32) {
+        let radius = 100.0;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let angle = rotations * 2.0 * PI * t;
+            let cursor = (state.center.0 + radius * angle.cos(), state.center.1 + radius * angle.sin());
+            state.update(from + (to - from) * t, cursor, true);
+        }
+    }
+
+    #[test]
+    fn test_spins_required() {
+        let spinner = OsuSpinner { time: 0, end_time: 4000 };
+        assert_eq!(SpinnerState::new(&spinner, 5.0).spins_required, 10.0);
+        assert_eq!(SpinnerState::new(&spinner, 10.0).spins_required, 15.0);
+    }
+
+    #[test]
+    fn test_rotation_and_rpm() {
+        let spinner = OsuSpinner { time: 0, end_time: 4000 };
+        let mut state = SpinnerState::new(&spinner, 5.0);
+        // 3 rotations in 1 second = 180 rpm
+        spin(&mut state, 0.0, 1000.0, 3.0, 120);
+        assert!((state.spins() - 3.0).abs() < 0.01);
+        assert!((state.rpm() - 180.0).abs() < 1.0);
+        assert!(!state.is_cleared());
+        assert!((state.progress() - 0.3).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_rotation_needs_key_held() {
+        let spinner = OsuSpinner { time: 0, end_time: 4000 };
+        let mut state = SpinnerState::new(&spinner, 5.0);
+        state.update(0.0, (356.0, 192.0), false);
+        state.update(100.0, (256.0, 292.0), false);
+        assert_eq!(state.total_rotation, 0.0);
+    }
+
+    #[test]
+    fn test_rotation_speed_is_capped() {
+        let spinner = OsuSpinner { time: 0, end_time: 4000 };
+        let mut state = SpinnerState::new(&spinner, 5.0);
+        spin(&mut state, 0.0, 1000.0, 20.0, 2000);
+        assert!(state.spins() <= MAX_ROTATIONS_PER_SECOND + 0.01);
+    }
+
+    #[test]
+    fn test_bonus_spins() {
+        let spinner = OsuSpinner { time: 0, end_time: 1000 };
+        let mut state = SpinnerState::new(&spinner, 0.0);
+        assert_eq!(state.spins_required, 1.0);
+        spin(&mut state, 0.0, 1000.0, 3.5, 400);
+        assert_eq!(state.bonus_spins(), 2);
+    }
+}
@@ -0,0 +1,74 @@
+// Maps osu!pixels to clip space, see Playfield::uniform
+struct PlayfieldUniform {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+};
+@group(0) @binding(0)
+var<uniform> playfield: PlayfieldUniform;
+
+const KIND_DISC: f32 = 0.0;
+const KIND_ARC: f32 = 1.0;
+const TAU: f32 = 6.28318530718;
+
+struct ShapeInput {
+    @location(0) center: vec2<f32>,
+    @location(1) half_size: vec2<f32>,
+    // x: rotation, y: kind, z: arc fill (0..1), w: ring thickness (fraction of the radius)
+    @location(2) params: vec4<f32>,
+    @location(3) colour: vec4<f32>,
+}
+
+struct ShapeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Position relative to the shape's center in osu!pixels, before rotation
+    @location(0) local: vec2<f32>,
+    @location(1) @interpolate(flat) half_size: vec2<f32>,
+    @location(2) @interpolate(flat) params: vec4<f32>,
+    @location(3) @interpolate(flat) colour: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, in: ShapeInput) -> ShapeOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let local = corners[index] * in.half_size;
+    let c = cos(in.params.x);
+    let s = sin(in.params.x);
+    let rotated = vec2<f32>(local.x * c - local.y * s, local.x * s + local.y * c);
+
+    var out: ShapeOutput;
+    out.clip_position = vec4<f32>((in.center + rotated) * playfield.scale + playfield.offset, 0.0, 1.0);
+    out.local = local;
+    out.half_size = in.half_size;
+    out.params = in.params;
+    out.colour = in.colour;
+    return out;
+}
+
+@fragment
+fn fs_main(in: ShapeOutput) -> @location(0) vec4<f32> {
+    var colour = in.colour;
+    let radius = in.half_size.x;
+    let r = length(in.local);
+    let aa = fwidth(r);
+    var coverage = 1.0 - smoothstep(radius - aa, radius, r);
+    if (in.params.w > 0.0) {
+        let inner = radius * (1.0 - in.params.w);
+        coverage *= smoothstep(inner - aa, inner, r);
+    }
+
+    // Angle measured clockwise from the top, 0..1
+    let angle = fract(atan2(in.local.x, -in.local.y) / TAU + 1.0);
+    if (in.params.y == KIND_ARC && angle > in.params.z) {
+        coverage = 0.0;
+    } else if (in.params.y == KIND_DISC) {
+        // Alternating spokes so the rotation is visible
+        let spoke = step(0.5, fract(angle * 8.0));
+        colour = vec4<f32>(colour.rgb * (0.7 + 0.3 * spoke), colour.a);
+    }
+
+    let alpha = colour.a * coverage;
+    return vec4<f32>(colour.rgb * alpha, alpha);
+}
@@ -0,0 +1,181 @@
+use crate::gpu::create_vertex_buffer;
+use crate::render_cache::{BindGroupLayoutId, PipelineDescriptor, PipelineId, RenderCache};
+use crate::skin::{normalize, SkinIni};
+use crate::spinner::SpinnerState;
+
+const KIND_DISC: f32 = 0.0;
+const KIND_ARC: f32 = 1.0;
+
+const DISC_RADIUS: f32 = 150.0;
+const METER_RADIUS: f32 = 175.0;
+const RPM_TEXT_SIZE: f32 = 24.0;
+const BONUS_TEXT_SIZE: f32 = 40.0;
+/// How long a bonus popup stays on screen, in milliseconds.
+pub const BONUS_POPUP_MS: f32 = 600.0;
+const FADE_IN_MS: f32 = 300.0;
+const FADE_OUT_MS: f32 = 240.0;
+
+/// One spinner as it should be drawn this frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpinnerDrawable {
+    pub center: (f32, f32),
+    pub rotation: f32,
+    pub progress: f32,
+    pub rpm: f32,
+    pub cleared: bool,
+    pub alpha: f32,
+    /// Age in milliseconds of each bonus popup still on screen.
+    pub bonus_popups: Vec<f32>,
+}
+
+impl SpinnerDrawable {
+    /// The spinner at gameplay time `time`, or `None` while it isn't visible.
+    pub fn new(state: &SpinnerState, time: f32) -> Option<SpinnerDrawable> {
+        let fade_in = (time - (state.start_time - FADE_IN_MS)) / FADE_IN_MS;
+        let fade_out = 1.0 - (time - state.end_time) / FADE_OUT_MS;
+        let alpha = fade_in.min(fade_out).min(1.0);
+        if alpha <= 0.0 {
+            return None;
+        }
+
+        Some(SpinnerDrawable {
+            center: state.center,
+            rotation: state.disc_rotation,
+            progress: state.progress(),
+            rpm: state.rpm(),
+            cleared: state.is_cleared(),
+            alpha,
+            bonus_popups: state
+                .bonus_times
+                .iter()
+                .map(|bonus_time| time - bonus_time)
+                .filter(|age| *age >= 0.0 && *age < BONUS_POPUP_MS)
+                .collect(),
+        })
+    }
+}
+
+/// Text drawn with a spinner, in osu!pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpinnerLabel {
+    pub text: String,
+    /// Center of the text.
+    pub position: (f32, f32),
+    pub size: f32,
+    pub colour: [u8; 4],
+    pub alpha: f32,
+}
+
+impl SpinnerDrawable {
+    /// The RPM readout under the spinner and the bonus popups rising from its disc.
+    pub fn labels(&self) -> Vec<SpinnerLabel> {
+        let mut labels = vec![SpinnerLabel {
+            text: format!("{} RPM", self.rpm.round() as u32),
+            position: (self.center.0, self.center.1 + METER_RADIUS + RPM_TEXT_SIZE),
+            size: RPM_TEXT_SIZE,
+            colour: [255, 255, 255, 255],
+            alpha: self.alpha,
+        }];
+        // Bonus popups rise from the disc and fade out
+        for age in &self.bonus_popups {
+            let t = (age / BONUS_POPUP_MS).clamp(0.0, 1.0);
+            labels.push(SpinnerLabel {
+                text: "1000".to_string(),
+                position: (self.center.0, self.center.1 + 40.0 - 60.0 * t),
+                size: BONUS_TEXT_SIZE,
+                colour: [255, 230, 102, 255],
+                alpha: self.alpha * (1.0 - t),
+            });
+        }
+        labels
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShapeInstance {
+    center: [f32; 2],
+    half_size: [f32; 2],
+    params: [f32; 4],
+    colour: [f32; 4],
+}
+
+impl ShapeInstance {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+            wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShapeInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+
+    fn circle(center: (f32, f32), radius: f32, kind: f32, rotation: f32, fill: f32, thickness: f32, colour: [f32; 4]) -> Self {
+        ShapeInstance {
+            center: [center.0, center.1],
+            half_size: [radius, radius],
+            params: [rotation, kind, fill, thickness],
+            colour,
+        }
+    }
+}
+
+pub struct SpinnerRenderer {
+    /// Colour of the spinning disc, skin.ini's `SpinnerBackground`.
+    pub disc_colour: [f32; 4],
+    pipeline: PipelineId,
+    instances: Option<wgpu::Buffer>,
+    num_instances: u32,
+}
+
+impl SpinnerRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        cache: &mut RenderCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        playfield_bind_group_layout: BindGroupLayoutId,
+    ) -> Self {
+        let descriptor = PipelineDescriptor::new("spinner.wgsl", &[playfield_bind_group_layout], format)
+            .with_vertex_layouts(&[ShapeInstance::desc()])
+            .with_sample_count(sample_count);
+
+        Self {
+            disc_colour: normalize(SkinIni::default().spinner_background),
+            pipeline: cache.pipeline_id(device, &descriptor),
+            instances: None,
+            num_instances: 0,
+        }
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, spinners: &[SpinnerDrawable]) {
+        let shapes: Vec<ShapeInstance> = spinners.iter().flat_map(|spinner| spinner_shapes(spinner, self.disc_colour)).collect();
+        self.num_instances = shapes.len() as u32;
+        self.instances = create_vertex_buffer(device, "Spinner Instance Buffer", &shapes);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, cache: &'a RenderCache, playfield_bind_group: &'a wgpu::BindGroup) {
+        if let Some(instances) = &self.instances {
+            render_pass.set_pipeline(cache.pipeline(self.pipeline));
+            render_pass.set_bind_group(0, playfield_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, instances.slice(..));
+            render_pass.draw(0..6, 0..self.num_instances);
+        }
+    }
+}
+
+fn spinner_shapes(spinner: &SpinnerDrawable, disc_colour: [f32; 4]) -> Vec<ShapeInstance> {
+    let alpha = spinner.alpha;
+    let meter_colour = if spinner.cleared {
+        [0.4, 1.0, 0.5, alpha]
+    } else {
+        [1.0, 1.0, 1.0, alpha]
+    };
+
+    vec![
+        ShapeInstance::circle(spinner.center, DISC_RADIUS, KIND_DISC, spinner.rotation, 0.0, 0.0, [disc_colour[0], disc_colour[1], disc_colour[2], disc_colour[3] * alpha * 0.8]),
+        ShapeInstance::circle(spinner.center, METER_RADIUS, KIND_ARC, 0.0, 1.0, 0.08, [1.0, 1.0, 1.0, alpha * 0.2]),
+        ShapeInstance::circle(spinner.center, METER_RADIUS, KIND_ARC, 0.0, spinner.progress, 0.08, meter_colour),
+    ]
+}
@@ -0,0 +1,56 @@
+// Maps sprite positions to clip space, see Playfield::uniform
+struct TransformUniform {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+};
+@group(0) @binding(0)
+var<uniform> transform: TransformUniform;
+
+@group(1) @binding(0)
+var t_sprite: texture_2d<f32>;
+@group(1) @binding(1)
+var s_sprite: sampler;
+
+struct SpriteInput {
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    // Point of the sprite placed at `position`, (0, 0) top-left to (1, 1) bottom-right
+    @location(2) anchor: vec2<f32>,
+    @location(3) rotation: f32,
+    @location(4) uv_min: vec2<f32>,
+    @location(5) uv_max: vec2<f32>,
+    @location(6) tint: vec4<f32>,
+}
+
+struct SpriteOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) tint: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, in: SpriteInput) -> SpriteOutput {
+    // Unit quad, y-down like the texture coordinates
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0), vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[index];
+    let local = (corner - in.anchor) * in.size;
+    let c = cos(in.rotation);
+    let s = sin(in.rotation);
+    let rotated = vec2<f32>(local.x * c - local.y * s, local.x * s + local.y * c);
+
+    var out: SpriteOutput;
+    out.clip_position = vec4<f32>((in.position + rotated) * transform.scale + transform.offset, 0.0, 1.0);
+    out.tex_coords = mix(in.uv_min, in.uv_max, corner);
+    out.tint = in.tint;
+    return out;
+}
+
+@fragment
+fn fs_main(in: SpriteOutput) -> @location(0) vec4<f32> {
+    // Textures are premultiplied, so the tint is premultiplied too
+    let tint = vec4<f32>(in.tint.rgb * in.tint.a, in.tint.a);
+    return textureSample(t_sprite, s_sprite, in.tex_coords) * tint;
+}
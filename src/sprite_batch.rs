@@ -0,0 +1,391 @@
+use std::ops::{Range, RangeBounds};
+use std::rc::Rc;
+use crate::atlas::{Atlas, AtlasRegion};
+use crate::draw_order::{DrawOrder, Layer};
+use crate::render_cache::{BindGroupLayoutId, PipelineDescriptor, PipelineId, RenderCache};
+use crate::texture;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BlendMode {
+    /// Premultiplied-alpha "over" blending.
+    Alpha,
+    /// Adds the colour on top, for glows and lighting.
+    Additive,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 2] = [BlendMode::Alpha, BlendMode::Additive];
+
+    /// Blend state for premultiplied shader output.
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Alpha => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                // Leave the target's coverage alone
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        }
+    }
+}
+
+/// Handle to a texture registered with a `SpriteBatch`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureId(usize);
+
+/// One textured quad. Positions and sizes are in whatever space the batch's transform maps from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sprite {
+    pub texture: TextureId,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    /// Point of the sprite placed at `position`, (0, 0) top-left to (1, 1) bottom-right.
+    pub anchor: [f32; 2],
+    /// Clockwise rotation around the anchor, in radians.
+    pub rotation: f32,
+    /// Linear RGB tint; the alpha is the sprite's opacity.
+    pub tint: [f32; 4],
+    pub order: DrawOrder,
+    pub blend: BlendMode,
+}
+
+impl Sprite {
+    /// A centered, untinted sprite showing `region` at `size`.
+    pub fn new(texture: TextureId, region: &AtlasRegion, position: (f32, f32), size: (f32, f32)) -> Sprite {
+        Sprite {
+            texture,
+            uv_min: region.uv_min,
+            uv_max: region.uv_max,
+            position: [position.0, position.1],
+            size: [size.0, size.1],
+            anchor: [0.5, 0.5],
+            rotation: 0.0,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            order: DrawOrder::default(),
+            blend: BlendMode::Alpha,
+        }
+    }
+
+    pub fn with_anchor(mut self, x: f32, y: f32) -> Sprite {
+        self.anchor = [x, y];
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Sprite {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Tints with an sRGB colour as found in skin.ini, keeping the current alpha.
+    pub fn with_colour(mut self, colour: [u8; 4]) -> Sprite {
+        let linear = |value: u8| (value as f32 / 255.0).powf(2.2);
+        self.tint = [linear(colour[0]), linear(colour[1]), linear(colour[2]), self.tint[3] * colour[3] as f32 / 255.0];
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: f32) -> Sprite {
+        self.tint[3] = alpha;
+        self
+    }
+
+    /// Moves the sprite to `layer`, keeping its depth and part.
+    pub fn with_layer(mut self, layer: Layer) -> Sprite {
+        self.order.layer = layer;
+        self
+    }
+
+    pub fn with_order(mut self, order: DrawOrder) -> Sprite {
+        self.order = order;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Sprite {
+        self.blend = blend;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteInstance {
+    position: [f32; 2],
+    size: [f32; 2],
+    anchor: [f32; 2],
+    rotation: f32,
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    tint: [f32; 4],
+}
+
+impl SpriteInstance {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+            0 => Float32x2, 1 => Float32x2, 2 => Float32x2, 3 => Float32,
+            4 => Float32x2, 5 => Float32x2, 6 => Float32x4,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Consecutive instances sharing a layer, texture and blend mode.
+#[derive(Clone, Debug, PartialEq)]
+struct DrawCall {
+    layer: Layer,
+    texture: TextureId,
+    blend: BlendMode,
+    instances: Range<u32>,
+}
+
+/// Queues sprites during a frame and draws them by `DrawOrder` with as few draw calls as possible.
+/// Textures are expected to be premultiplied, like atlas pages.
+pub struct SpriteBatch {
+    /// One pipeline per blend mode, in `BlendMode::ALL` order, so switching is per draw call.
+    pipelines: Vec<PipelineId>,
+    textures: Vec<Rc<wgpu::BindGroup>>,
+    sprites: Vec<Sprite>,
+    instances: Option<wgpu::Buffer>,
+    instance_capacity: usize,
+    draw_calls: Vec<DrawCall>,
+}
+
+impl SpriteBatch {
+    pub fn new(
+        device: &wgpu::Device,
+        cache: &mut RenderCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        transform_bind_group_layout: BindGroupLayoutId,
+    ) -> Self {
+        let texture_layout = cache.texture_layout_id(device);
+        let pipelines = BlendMode::ALL
+            .iter()
+            .map(|mode| {
+                let descriptor = PipelineDescriptor::new("sprite.wgsl", &[transform_bind_group_layout, texture_layout], format)
+                    .with_vertex_layouts(&[SpriteInstance::desc()])
+                    .with_blend(Some(mode.blend_state()))
+                    .with_sample_count(sample_count);
+                cache.pipeline_id(device, &descriptor)
+            })
+            .collect();
+
+        Self {
+            pipelines,
+            textures: Vec::new(),
+            sprites: Vec::new(),
+            instances: None,
+            instance_capacity: 0,
+            draw_calls: Vec::new(),
+        }
+    }
+
+    pub fn register_texture(&mut self, device: &wgpu::Device, cache: &mut RenderCache, texture: &texture::Texture) -> TextureId {
+        self.textures.push(cache.texture_bind_group(device, texture));
+        TextureId(self.textures.len() - 1)
+    }
+
+    /// Forgets every registered texture, invalidating their ids.
+    pub fn clear_textures(&mut self) {
+        self.textures.clear();
+    }
+
+    /// Registers every page of `atlas`; the result is indexed by `AtlasRegion::page`.
+    pub fn register_atlas<K>(&mut self, device: &wgpu::Device, cache: &mut RenderCache, atlas: &Atlas<K>) -> Vec<TextureId> {
+        atlas.pages.iter().map(|page| self.register_texture(device, cache, page)).collect()
+    }
+
+    pub fn push(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// Sorts and uploads the queued sprites, then clears the queue for the next frame.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut sprites = std::mem::take(&mut self.sprites);
+        self.draw_calls = sort_into_draw_calls(&mut sprites);
+
+        let instances: Vec<SpriteInstance> = sprites
+            .iter()
+            .map(|sprite| SpriteInstance {
+                position: sprite.position,
+                size: sprite.size,
+                anchor: sprite.anchor,
+                rotation: sprite.rotation,
+                uv_min: sprite.uv_min,
+                uv_max: sprite.uv_max,
+                tint: sprite.tint,
+            })
+            .collect();
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instances = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Sprite Instance Buffer"),
+                size: (self.instance_capacity * std::mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let (Some(buffer), false) = (&self.instances, instances.is_empty()) {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(&instances));
+        }
+
+        // Keep the allocation for next frame
+        sprites.clear();
+        self.sprites = sprites;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, cache: &'a RenderCache, transform_bind_group: &'a wgpu::BindGroup) {
+        self.draw_layers(render_pass, cache, transform_bind_group, ..);
+    }
+
+    /// Draws only the sprites in `layers`, so other renderers can be interleaved between layers.
+    pub fn draw_layers<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        cache: &'a RenderCache,
+        transform_bind_group: &'a wgpu::BindGroup,
+        layers: impl RangeBounds<Layer>,
+    ) {
+        self.draw_filtered(render_pass, cache, transform_bind_group, |call| layers.contains(&call.layer));
+    }
+
+    /// Draws only the sprites blended with `blend`, e.g. to redraw additive ones for bloom.
+    pub fn draw_blend<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        cache: &'a RenderCache,
+        transform_bind_group: &'a wgpu::BindGroup,
+        blend: BlendMode,
+    ) {
+        self.draw_filtered(render_pass, cache, transform_bind_group, |call| call.blend == blend);
+    }
+
+    fn draw_filtered<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        cache: &'a RenderCache,
+        transform_bind_group: &'a wgpu::BindGroup,
+        filter: impl Fn(&DrawCall) -> bool,
+    ) {
+        let Some(instances) = &self.instances else {
+            return;
+        };
+        let mut calls = self.draw_calls.iter().filter(|call| filter(call)).peekable();
+        if calls.peek().is_none() {
+            return;
+        }
+        render_pass.set_bind_group(0, transform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, instances.slice(..));
+        for call in calls {
+            render_pass.set_pipeline(cache.pipeline(self.pipelines[call.blend as usize]));
+            render_pass.set_bind_group(1, &self.textures[call.texture.0], &[]);
+            render_pass.draw(0..6, call.instances.clone());
+        }
+    }
+}
+
+/// Sorts by draw order, then by blend mode and texture, and merges runs that can share a draw call.
+/// Only sprites with the same order, blend mode and texture keep their submission order, so
+/// overlapping parts of one object need distinct `DrawOrder::part`s.
+fn sort_into_draw_calls(sprites: &mut [Sprite]) -> Vec<DrawCall> {
+    sprites.sort_by_key(|sprite| (sprite.order, sprite.blend, sprite.texture));
+
+    let mut draw_calls: Vec<DrawCall> = Vec::new();
+    for (index, sprite) in sprites.iter().enumerate() {
+        let index = index as u32;
+        match draw_calls.last_mut() {
+            Some(call) if call.layer == sprite.order.layer && call.texture == sprite.texture && call.blend == sprite.blend => {
+                call.instances.end = index + 1;
+            }
+            _ => draw_calls.push(DrawCall {
+                layer: sprite.order.layer,
+                texture: sprite.texture,
+                blend: sprite.blend,
+                instances: index..index + 1,
+            }),
+        }
+    }
+    draw_calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(texture: usize, depth: i32, blend: BlendMode) -> Sprite {
+        let region = AtlasRegion { page: 0, uv_min: [0.0, 0.0], uv_max: [1.0, 1.0], width: 1, height: 1 };
+        Sprite::new(TextureId(texture), &region, (0.0, 0.0), (1.0, 1.0))
+            .with_order(DrawOrder { layer: Layer::HitObject, depth, part: 0 })
+            .with_blend(blend)
+    }
+
+    #[test]
+    fn test_draw_calls_are_merged() {
+        let mut sprites = vec![
+            sprite(0, 1, BlendMode::Alpha),
+            sprite(1, 0, BlendMode::Alpha),
+            sprite(0, 1, BlendMode::Additive),
+            sprite(0, 1, BlendMode::Alpha),
+            sprite(1, 0, BlendMode::Alpha),
+        ];
+        let calls = sort_into_draw_calls(&mut sprites);
+        assert_eq!(
+            calls,
+            vec![
+                DrawCall { layer: Layer::HitObject, texture: TextureId(1), blend: BlendMode::Alpha, instances: 0..2 },
+                DrawCall { layer: Layer::HitObject, texture: TextureId(0), blend: BlendMode::Alpha, instances: 2..4 },
+                DrawCall { layer: Layer::HitObject, texture: TextureId(0), blend: BlendMode::Additive, instances: 4..5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_keeps_submission_order() {
+        let mut sprites: Vec<Sprite> = (0..4)
+            .map(|i| sprite(0, 0, BlendMode::Alpha).with_rotation(i as f32))
+            .collect();
+        sprites.push(sprite(0, -1, BlendMode::Alpha));
+        sort_into_draw_calls(&mut sprites);
+        let rotations: Vec<f32> = sprites.iter().map(|sprite| sprite.rotation).collect();
+        assert_eq!(rotations, vec![0.0, 0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(sprites[0].order.depth, -1);
+    }
+
+    #[test]
+    fn test_order_ignores_submission_order() {
+        // Three overlapping circles, each a body and a number, plus their approach circles
+        let mut sprites = Vec::new();
+        for index in 0..3 {
+            for part in 0..2 {
+                let order = DrawOrder::hit_object(Layer::HitObject, index, part);
+                sprites.push(sprite(part as usize, 0, BlendMode::Alpha).with_order(order));
+            }
+            let order = DrawOrder::hit_object(Layer::ApproachCircle, index, 0);
+            sprites.push(sprite(0, 0, BlendMode::Alpha).with_order(order));
+        }
+        let mut reversed: Vec<Sprite> = sprites.iter().rev().copied().collect();
+        let calls = sort_into_draw_calls(&mut sprites);
+        assert_eq!(calls, sort_into_draw_calls(&mut reversed));
+        assert_eq!(sprites, reversed);
+
+        let orders: Vec<(Layer, i32, u8)> = sprites
+            .iter()
+            .map(|sprite| (sprite.order.layer, sprite.order.depth, sprite.order.part))
+            .collect();
+        assert_eq!(orders[..2], [(Layer::HitObject, -2, 0), (Layer::HitObject, -2, 1)]);
+        assert_eq!(orders[5], (Layer::HitObject, 0, 1));
+        assert_eq!(orders[6..], [(Layer::ApproachCircle, -2, 0), (Layer::ApproachCircle, -1, 0), (Layer::ApproachCircle, 0, 0)]);
+    }
+}
@@ -1,40 +1,94 @@
-use wgpu::util::DeviceExt;
-use winit::{event::*,
-            event_loop::{ControlFlow, EventLoop},
-            window::WindowBuilder,
-            window::Window,
-            dpi::{PhysicalSize, Size},
-            event
-};
-use crate::{circle, texture};
-use crate::uniforms::ScaleFactorUniform;
-use crate::vertex::Vertex;
+use winit::{event::*, window::Window};
+use crate::skin::Skin;
+use crate::audio::AudioStreamManager;
+use crate::clock::GameplayClock;
+use crate::input::{Action, ActionEvent, CursorMode, CursorPipeline, CursorSettings, InputBindings, InputCapture, InputMapper, RawInput};
+use winit::window::CursorGrabMode;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Instant;
+use crate::cursor_trail::{CursorTrail, TrailSettings};
+use crate::frame_pacing::{self, FrameLimit, FrameLimiter, FrameStats, PacingSettings};
+use crate::gpu::{self, DeviceLoss, DeviceRequest, GpuError};
+use crate::hit_circle::CircleDrawable;
+use crate::playfield::{PLAYFIELD_HEIGHT, PLAYFIELD_WIDTH};
+use crate::profiler::{Profile, Timings};
+use crate::renderer::{Hud, Renderer, Scene, TargetDescriptor};
+use crate::judgement;
+use crate::osu::OsuMap;
+use crate::replay::{self, Keys, Mods, ReplayRecorder, ReplayScore};
+use crate::slider_renderer::SliderDrawable;
+use crate::spinner::SpinnerState;
 
 pub struct State {
-    pub surface: wgpu::Surface,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
+    /// Kept to request a new adapter if the device is lost.
+    /// Shared with device requests made to recover from a lost device.
+    pub instance: Arc<wgpu::Instance>,
+    pub surface: Arc<wgpu::Surface>,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    pub clear_color: wgpu::Color,
+    pub renderer: Renderer,
     // The window must be declared after the surface so
     // it gets dropped after it as the surface contains
     // unsafe references to the window's resources.
     pub window: Window,
-    pub render_pipeline: wgpu::RenderPipeline,
-    pub num_vertices: u32,
-    pub vertex_buffer: wgpu::Buffer,
-    pub num_indices: u32,
-    pub index_buffer: wgpu::Buffer,
-    pub diffuse_bind_group: wgpu::BindGroup,
-    pub diffuse_texture: texture::Texture,
-    pub scale_factor_uniform: wgpu::Buffer,
-    pub scale_factor_bind_group: wgpu::BindGroup,
+    pub clock: GameplayClock,
+    /// Music of the current play. The gameplay clock follows its position while it plays.
+    pub audio: Option<AudioStreamManager>,
+    /// Beatmap being played, if any; plays are judged against it.
+    pub map: Option<OsuMap>,
+    /// MD5 of the beatmap file, written into replays to match them to it.
+    pub beatmap_hash: String,
+    pub input_capture: InputCapture,
+    pub input_mapper: InputMapper,
+    pub action_events: Vec<ActionEvent>,
+    pub cursor: CursorPipeline,
+    pub cursor_trail: CursorTrail,
+    /// Sliders to draw this frame, topmost first.
+    pub sliders: Vec<SliderDrawable>,
+    /// Spinners of the current play; fed with the cursor every update.
+    pub spinner_states: Vec<SpinnerState>,
+    /// Records the current play until it ends or is retried.
+    pub recorder: Option<ReplayRecorder>,
+    pub hud: Hud,
+    /// Present modes the surface supports.
+    present_modes: Vec<wgpu::PresentMode>,
+    pub pacing: PacingSettings,
+    pub frame_limiter: FrameLimiter,
+    pub frame_stats: FrameStats,
+    /// CPU time of update and render sections.
+    pub cpu_timings: Timings,
+    /// Whether the frame timing overlay is shown, toggled with F3.
+    pub show_timings: bool,
+    device_loss: DeviceLoss,
+    /// The replacement device while recovering from a lost one.
+    device_request: Option<DeviceRequest>,
 }
 
+pub const BINDINGS_PATH: &str = "bindings.cfg";
+pub const SKIN_PATH: &str = "skin";
+/// Directory finished plays are saved to as `.osr` files.
+pub const REPLAYS_PATH: &str = "replays";
+pub const PLAYER_NAME: &str = "Player";
+/// MSAA samples asked for by `State::new`: 1, 2, 4 or 8.
+pub const MSAA_SAMPLE_COUNT: u32 = 4;
+/// Step used by the offset adjust actions, in milliseconds.
+const OFFSET_STEP_MS: f64 = 5.0;
+/// Pacing cycled through with F5, from least tearing to lowest latency.
+const PACING_PRESETS: [PacingSettings; 3] = [
+    PacingSettings { present_mode: wgpu::PresentMode::Fifo, frame_limit: FrameLimit::Unlimited },
+    PacingSettings { present_mode: wgpu::PresentMode::Mailbox, frame_limit: FrameLimit::RefreshMultiple(2) },
+    PacingSettings { present_mode: wgpu::PresentMode::Immediate, frame_limit: FrameLimit::Fixed(1000) },
+];
+
 impl State {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window) -> Result<Self, GpuError> {
+        Self::with_sample_count(window, MSAA_SAMPLE_COUNT).await
+    }
+
+    /// Like `new`, with `sample_count` lowered to the closest count the adapter supports.
+    pub async fn with_sample_count(window: Window, sample_count: u32) -> Result<Self, GpuError> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -48,292 +102,115 @@ impl State {
         //
         // The surface needs to live as long as the window that created it.
         // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let surface = unsafe { instance.create_surface(&window) }.map_err(|_| GpuError::SurfaceError)?;
+        let (instance, surface) = (Arc::new(instance), Arc::new(surface));
 
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.unwrap();
-
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                // WebGL doesn't support all of wgpu's features, so if
-                // we're building for the web we'll have to disable some.
-                limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
-                label: None,
-            },
-            None, // Trace path
-        ).await.unwrap();
+        let (adapter, device, queue) = gpu::request_device(&instance, &surface).await?;
+        let device_loss = DeviceLoss::watch(&device);
 
         let surface_caps = surface.get_capabilities(&adapter);
-        // Shader code in this tutorial assumes an sRGB surface texture. Using a different
-        // one will result all the colors coming out darker. If you want to support non
-        // sRGB surfaces, you'll need to account for that when drawing to the frame.
-        let surface_format = surface_caps.formats.iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-        
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
+        let pacing = PacingSettings::default();
+        let config = surface_config(&surface_caps, size.width, size.height, pacing.present_mode)?;
         surface.configure(&device, &config);
 
-        let diffuse_bytes = include_bytes!("happy-tree.png");
-        let diffuse_texture = texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap();
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let diffuse_bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view), // CHANGED!
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler), // CHANGED!
-                    }
-                ],
-                label: Some("diffuse_bind_group"),
-            }
-        );
-
-        let window_width = size.width as f32;
-        let window_height = size.height as f32;
-        let aspect_ratio = window_width / window_height;
-        let scale_factor: ScaleFactorUniform = ScaleFactorUniform {
-            scale_factor: if aspect_ratio > 1.0 {
-                [1.0, aspect_ratio]
-            } else {
-                [1.0 / aspect_ratio, 1.0]
-            }
-        };
-
-        let scale_factor_uniform = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Scale Factor Uniform Buffer"),
-                contents: bytemuck::cast_slice(&scale_factor.scale_factor),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            }
-        );
-
-        let scale_factor_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                label: Some("Scale Factor Bind Group Layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }
-                ],
-            }
-        );
-
-        let scale_factor_bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                label: Some("Scale Factor Bind Group"),
-                layout: &scale_factor_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: scale_factor_uniform.as_entire_binding(),
-                    }
-                ],
-            }
-        );
-        
-        let clear_color = wgpu::Color::BLACK;
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+        let skin = Skin::load_or_default(SKIN_PATH);
+        let target = TargetDescriptor { format: config.format, width: size.width, height: size.height, sample_count };
+        let renderer = Renderer::new(&adapter, device, queue, target, skin)?;
 
-        let render_pipeline_layout = device.create_pipeline_layout(
-            &wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                    &scale_factor_bind_group_layout,
-                ], // NEW!
-                push_constant_ranges: &[],
+        let clock = GameplayClock::new();
+        let input_mapper = InputMapper::new(InputBindings::load_or_default(BINDINGS_PATH));
+        let mut cursor = CursorPipeline::new(CursorSettings::default(), size.width as f64, size.height as f64);
+        cursor.set_target_area(renderer.playfield.window_rect());
 
-            }
-        );
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Standard Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main", // 1.
-                buffers: &[
-                    Vertex::desc(),
-                ], // 2.
-            },
-            fragment: Some(wgpu::FragmentState { // 3.
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState { // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1, // 2.
-                mask: !0, // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-            multiview: None, // 5.
-        });
+        let cursor_trail = CursorTrail::new(TrailSettings::for_skin(&renderer.skin));
 
-        let circle_data = circle::circle_vertices(64, 0.5);
-        let num_vertices = circle_data.len() as u32;
-        
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Circle Vertex Buffer"),
-                contents: bytemuck::cast_slice(&circle_data),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
-
-        let circle_index_data = circle::n_gon_index_buffer(64);
-        let num_indices = circle_index_data.len() as u32;
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Circle Index Buffer"),
-                contents: bytemuck::cast_slice(&circle_index_data),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
-
-        /*let num_vertices = VERTICES.len() as u32;
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
-
-        let num_indices = INDICES.len() as u32;
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );*/
+        let frame_limiter = FrameLimiter::new(pacing.frame_limit, refresh_hz(&window));
 
-        Self {
+        Ok(Self {
             window,
+            instance,
             surface,
-            device,
-            queue,
             config,
             size,
-            clear_color,
-            render_pipeline,
-            num_vertices,
-            vertex_buffer,
-            num_indices,
-            index_buffer,
-            diffuse_bind_group,
-            diffuse_texture,
-            scale_factor_uniform,
-            scale_factor_bind_group,
-        }
-
+            renderer,
+            clock,
+            audio: None,
+            map: None,
+            beatmap_hash: String::new(),
+            input_capture: InputCapture::new(),
+            input_mapper,
+            action_events: Vec::new(),
+            cursor,
+            cursor_trail,
+            sliders: Vec::new(),
+            spinner_states: Vec::new(),
+            recorder: None,
+            hud: Hud::default(),
+            present_modes: surface_caps.present_modes,
+            pacing,
+            frame_limiter,
+            frame_stats: FrameStats::new(),
+            cpu_timings: Timings::new(),
+            show_timings: false,
+            device_loss,
+            device_request: None,
+        })
     }
 
     pub fn window(&self) -> &Window {
         &self.window
     }
 
+    pub fn set_cursor_settings(&mut self, settings: CursorSettings) {
+        self.cursor.settings = settings;
+
+        // Relative mode needs the OS cursor locked in place, otherwise it leaves the window
+        let grab_modes: &[CursorGrabMode] = match (settings.mode, settings.confine_to_window) {
+            (CursorMode::Relative, _) => &[CursorGrabMode::Locked, CursorGrabMode::Confined],
+            (_, true) => &[CursorGrabMode::Confined, CursorGrabMode::Locked],
+            (_, false) => &[CursorGrabMode::None],
+        };
+        let grabbed = grab_modes.iter().any(|mode| self.window.set_cursor_grab(*mode).is_ok());
+        if !grabbed {
+            log::warn!("Cursor grab is not supported on this platform");
+        }
+        self.window.set_cursor_visible(settings.mode == CursorMode::Absolute);
+    }
+
+    /// Switches present mode and frame limit. An unsupported present mode falls back to the closest one.
+    pub fn set_pacing(&mut self, settings: PacingSettings) {
+        let present_mode = frame_pacing::select_present_mode(settings.present_mode, &self.present_modes);
+        if present_mode != settings.present_mode {
+            log::warn!("{:?} presentation is not supported, using {:?}", settings.present_mode, present_mode);
+        }
+        if present_mode != self.config.present_mode {
+            self.config.present_mode = present_mode;
+            self.surface.configure(&self.renderer.device, &self.config);
+        }
+        self.frame_limiter = FrameLimiter::new(settings.frame_limit, refresh_hz(&self.window));
+        self.pacing = settings;
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            
-            let aspect_ratio = self.size.width as f32 / self.size.height as f32;
-            let scale_factor = if aspect_ratio > 1.0 {
-                [1.0, aspect_ratio]
-            } else {
-                [1.0 / aspect_ratio, 1.0]
-            };
-            
-            self.queue.write_buffer(
-                &self.scale_factor_uniform,
-                0,
-                bytemuck::cast_slice(&scale_factor)
-            );
+            self.surface.configure(&self.renderer.device, &self.config);
+            self.renderer.resize(new_size.width, new_size.height);
+            self.cursor.set_window_size(new_size.width as f64, new_size.height as f64);
+            self.cursor.set_target_area(self.renderer.playfield.window_rect());
         }
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
+        // Stamp before doing anything else; conversion to gameplay time happens in update()
+        if let Some(RawInput::Button { binding, .. }) = self.input_capture.capture(event) {
+            if self.input_mapper.bindings.action_for(binding).is_some() {
+                return true;
+            }
+        }
+
         match event {
             WindowEvent::KeyboardInput {
                 input:
@@ -344,7 +221,7 @@ impl State {
                 },
                 ..
             } => {
-                self.clear_color = if *state == ElementState::Pressed {
+                self.renderer.clear_color = if *state == ElementState::Pressed {
                     wgpu::Color::BLUE
                 } else {
                     wgpu::Color::BLACK
@@ -352,10 +229,58 @@ impl State {
                 true
             },
 
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F3),
+                    ..
+                },
+                ..
+            } => {
+                self.show_timings = !self.show_timings;
+                true
+            },
+
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F4),
+                    ..
+                },
+                ..
+            } => {
+                let mode = match self.cursor.settings.mode {
+                    CursorMode::Absolute => CursorMode::Relative,
+                    CursorMode::Relative => CursorMode::Tablet,
+                    CursorMode::Tablet => CursorMode::Absolute,
+                };
+                log::info!("Cursor mode: {:?}", mode);
+                self.set_cursor_settings(CursorSettings { mode, ..self.cursor.settings });
+                true
+            },
+
+            WindowEvent::KeyboardInput {
+                input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F5),
+                    ..
+                },
+                ..
+            } => {
+                let current = PACING_PRESETS.iter().position(|preset| *preset == self.pacing).unwrap_or(0);
+                let pacing = PACING_PRESETS[(current + 1) % PACING_PRESETS.len()];
+                log::info!("Pacing: {:?}, from {:.0} fps", pacing, self.frame_stats.fps().unwrap_or(0.0));
+                self.set_pacing(pacing);
+                true
+            },
+
             WindowEvent::CursorMoved { position, .. } => {
-                self.clear_color = wgpu::Color {
-                    r: position.x as f64 / self.size.width as f64,
-                    g: position.y as f64 / self.size.height as f64,
+                self.renderer.clear_color = wgpu::Color {
+                    r: position.x / self.size.width as f64,
+                    g: position.y / self.size.height as f64,
                     b: 1.0,
                     a: 1.0,
                 };
@@ -366,49 +291,359 @@ impl State {
         }
     }
 
+    /// Cursor position in osu!pixels, for hit-testing against beatmap objects.
+    pub fn cursor_osu_position(&self) -> (f32, f32) {
+        let (x, y) = self.cursor.position();
+        self.renderer.playfield.window_to_osu(x as f32, y as f32)
+    }
+
+    pub fn device_input(&mut self, event: &DeviceEvent) -> bool {
+        self.input_capture.capture_device(event).is_some()
+    }
+
     pub fn update(&mut self) {
-        
+        let start = Instant::now();
+        for timed in self.input_capture.drain() {
+            let time = self.clock.time_at(timed.at);
+            match timed.input {
+                RawInput::Button { binding, pressed } => {
+                    if let Some(event) = self.input_mapper.map_binding(binding, pressed, time) {
+                        self.action_events.push(event);
+                    }
+                }
+                RawInput::FocusLost => {
+                    let released = self.input_mapper.release_all(time);
+                    self.action_events.extend(released);
+                }
+                RawInput::CursorMoved { x, y } => self.cursor.on_cursor_moved(x, y),
+                RawInput::MouseMotion { dx, dy } => self.cursor.on_mouse_motion(dx, dy),
+            }
+            let (x, y) = self.cursor.position();
+            let position = self.renderer.playfield.window_to_osu(x as f32, y as f32);
+            if let RawInput::CursorMoved { .. } | RawInput::MouseMotion { .. } = timed.input {
+                // Every sample goes into the trail so it stays smooth with high-polling mice
+                self.cursor_trail.push(timed.at, position);
+            }
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(time as i64, position.0, position.1, replay_keys(&self.input_mapper));
+            }
+        }
+
+        let input_done = Instant::now();
+        self.cpu_timings.record("Input", input_done - start);
+
+        if let Some(audio) = &self.audio {
+            // Once the music runs out the clock carries on by itself
+            match (audio.is_finished(), audio.get_time()) {
+                (Ok(false), Ok(seconds)) => self.clock.sync_to_audio(seconds as f64 * 1000.0, Instant::now()),
+                (Ok(true), _) => self.save_replay(),
+                (Err(error), _) | (_, Err(error)) => log::warn!("Couldn't read the music position: {}", error),
+            }
+        }
+
+        let audio_done = Instant::now();
+        self.cpu_timings.record("Audio", audio_done - input_done);
+
+        for event in std::mem::take(&mut self.action_events) {
+            match (event.action, event.pressed) {
+                (Action::Pause, true) => self.toggle_pause(),
+                (Action::OffsetIncrease, true) => self.clock.offset_ms += OFFSET_STEP_MS,
+                (Action::OffsetDecrease, true) => self.clock.offset_ms -= OFFSET_STEP_MS,
+                (Action::Retry, true) | (Action::QuickRestart, true) => self.start_play(),
+                _ => log::debug!("{:?}", event),
+            }
+        }
+
+        let time = self.clock.time_ms() as f32;
+        let cursor = self.cursor_osu_position();
+        let holding = self.holding();
+        for spinner in &mut self.spinner_states {
+            spinner.update(time, cursor, holding);
+        }
+        self.cpu_timings.record("Update", audio_done.elapsed());
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
+    /// Whether any gameplay key is held.
+    fn holding(&self) -> bool {
+        Action::ALL
+            .into_iter()
+            .any(|action| action.is_gameplay_key() && self.input_mapper.is_held(action))
+    }
+
+    /// Opens a beatmap along with its music, or any other file as music to play along to.
+    /// Whatever fails to load is logged and left out.
+    pub fn open(&mut self, path: &str) {
+        let music = if path.ends_with(".osu") {
+            let (map, hash) = match (OsuMap::from_file(path), replay::beatmap_hash(path)) {
+                (Ok(map), Ok(hash)) => (map, hash),
+                (Err(error), _) => {
+                    log::error!("Couldn't open {}: {}", path, error);
+                    return;
+                }
+                (_, Err(error)) => {
+                    log::error!("Couldn't open {}: {}", path, error);
+                    return;
+                }
+            };
+            log::info!("Playing {} - {} ({})", map.artist, map.name, map.creator);
+            let directory = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new(""));
+            let music = map.audio_filename.as_ref().map(|filename| directory.join(filename).to_string_lossy().into_owned());
+            self.map = Some(map);
+            self.beatmap_hash = hash;
+            music
+        } else {
+            Some(path.to_string())
+        };
+        if let Some(music) = music {
+            match AudioStreamManager::from_file(&music) {
+                Ok(audio) => self.audio = Some(audio),
+                Err(error) => log::error!("Couldn't open {}: {}", music, error),
+            }
+        }
+    }
+
+    /// Starts the play from the beginning, with the music and the clock started together.
+    pub fn start_play(&mut self) {
+        self.save_replay();
+        self.recorder = Some(ReplayRecorder::new(&self.beatmap_hash, PLAYER_NAME, Mods::NONE));
+        self.clock.reset();
+        if let Some(audio) = &mut self.audio {
+            if let Err(error) = audio.set_time(0.0).and_then(|_| audio.play()) {
+                log::error!("Couldn't play the music: {}", error);
+            }
+        }
+        self.clock.start();
+    }
+
+    /// Ends the recording of the current play and writes it to `REPLAYS_PATH`, if anything was recorded.
+    pub fn save_replay(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        if recorder.frames().is_empty() {
+            return;
+        }
+        // Without a beatmap there's nothing to judge the play against
+        let score = match &self.map {
+            Some(map) => judgement::judge_play(map, recorder.frames()),
+            None => ReplayScore::default(),
+        };
+        let replay = recorder.finish(score);
+        let path = format!("{}/{}.osr", REPLAYS_PATH, replay.timestamp);
+        let result = std::fs::create_dir_all(REPLAYS_PATH)
+            .map_err(|error| error.to_string())
+            .and_then(|_| replay.save(&path).map_err(|error| error.to_string()));
+        match result {
+            Ok(()) => log::info!("Saved replay to {}", path),
+            Err(error) => log::error!("Couldn't save replay to {}: {}", path, error),
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        self.clock.toggle_pause();
+        if let Some(audio) = &mut self.audio {
+            let result = if self.clock.is_running() { audio.play() } else { audio.pause() };
+            if let Err(error) = result {
+                log::error!("Couldn't pause or resume the music: {}", error);
+            }
+        }
+    }
+
+    /// Stand-in hit circle in the middle of the playfield until beatmaps are loaded.
+    fn placeholder_circle(time: f32) -> CircleDrawable {
+        CircleDrawable {
+            position: (PLAYFIELD_WIDTH / 2.0, PLAYFIELD_HEIGHT / 2.0),
+            radius: 64.0,
+            index: 0,
+            combo_number: 1,
+            combo_colour: 0,
+            alpha: 1.0,
+            approach_scale: Some(1.0 + 3.0 * (1.0 - (time / 1000.0).rem_euclid(1.0))),
+        }
+    }
+
+    /// Reacts to a frame the surface couldn't give out. The surface is reconfigured, and if
+    /// that keeps failing the device is treated as lost. Running out of memory can't be
+    /// recovered from and is returned.
+    pub fn surface_error(&mut self, error: wgpu::SurfaceError) -> Result<(), GpuError> {
+        match error {
+            wgpu::SurfaceError::Timeout => log::warn!("Timed out waiting for a frame"),
+            wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost => {
+                if !self.device_loss.surface_failed() {
+                    self.surface.configure(&self.renderer.device, &self.config);
+                }
+            }
+            wgpu::SurfaceError::OutOfMemory => return Err(GpuError::OutOfMemory),
+        }
+        Ok(())
+    }
+
+    /// Recreates the device and every GPU resource if the device was lost, keeping the
+    /// renderer's skin and settings. The new device is requested without blocking, so this
+    /// returns `Ok(false)` until it arrives and nothing should be drawn in the meantime.
+    /// Fails only if no new device can be made.
+    pub fn recover_device(&mut self) -> Result<bool, GpuError> {
+        if !self.device_loss.is_lost() {
+            return Ok(true);
+        }
+        let request = self.device_request.get_or_insert_with(|| {
+            log::warn!("Graphics device lost, recreating it");
+            DeviceRequest::new(self.instance.clone(), self.surface.clone())
         });
+        let Poll::Ready(result) = request.poll() else {
+            return Ok(false);
+        };
+        self.device_request = None;
+        let (adapter, device, queue) = result?;
+        self.device_loss = DeviceLoss::watch(&device);
+
+        let surface_caps = self.surface.get_capabilities(&adapter);
+        self.config = surface_config(&surface_caps, self.config.width, self.config.height, self.pacing.present_mode)?;
+        self.present_modes = surface_caps.present_modes;
+        self.surface.configure(&device, &self.config);
+
+        let skin = std::mem::replace(&mut self.renderer.skin, Skin::new());
+        let target = TargetDescriptor {
+            format: self.config.format,
+            width: self.config.width,
+            height: self.config.height,
+            sample_count: self.renderer.msaa.sample_count,
+        };
+        let mut renderer = Renderer::new(&adapter, device, queue, target, skin)?;
+        renderer.clear_color = self.renderer.clear_color;
+        renderer.post_process.settings = self.renderer.post_process.settings;
+        renderer.slider_renderer.style = self.renderer.slider_renderer.style;
+        self.renderer = renderer;
+        Ok(true)
+    }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[
-                    // This is what @location(0) in the fragment shader targets
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(self.clear_color),
-                            store: wgpu::StoreOp::Store,
-                        }
-                    })
-                ],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]); // NEW!
-            render_pass.set_bind_group(1, &self.scale_factor_bind_group, &[]); // NEW!
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        // Nothing to draw into while minimized
+        if self.size.width == 0 || self.size.height == 0 {
+            return Ok(());
         }
+        let output = self.surface.get_current_texture()?;
+        self.device_loss.surface_ok();
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
+        let now = Instant::now();
+        self.cursor_trail.prune(now);
+        self.frame_stats.record(now);
+        self.hud.offset_ms = self.clock.offset_ms;
+        self.hud.frame_time_ms = self.frame_stats.frame_times.average();
+        let time = self.clock.time_ms() as f32;
+        self.renderer.timing_overlay.scale_factor = self.window.scale_factor() as f32;
+        let profile = Profile {
+            frame_times: &self.frame_stats.frame_times,
+            cpu: &self.cpu_timings,
+        };
+        let scene = Scene {
+            time,
+            circles: &[Self::placeholder_circle(time)],
+            sliders: &self.sliders,
+            spinners: &self.spinner_states,
+            cursor: self.cursor_osu_position(),
+            cursor_trail: &self.cursor_trail,
+            cursor_pressed: self.holding(),
+            now,
+            hud: self.hud,
+            profile: self.show_timings.then_some(profile),
+        };
+        self.renderer.render(&view, &scene);
+        self.cpu_timings.record("Render", now.elapsed());
         output.present();
 
         Ok(())
     }
 }
+
+/// Replay key bits of the gameplay keys held in `mapper`.
+fn replay_keys(mapper: &InputMapper) -> Keys {
+    let mut keys = Keys::NONE;
+    for (action, bits) in [(Action::K1, Keys::K1), (Action::K2, Keys::K2), (Action::M1, Keys::M1), (Action::M2, Keys::M2)] {
+        if mapper.is_held(action) {
+            keys.insert(bits);
+        }
+    }
+    keys
+}
+
+/// Refresh rate of the monitor the window is on.
+fn refresh_hz(window: &Window) -> f64 {
+    window
+        .current_monitor()
+        .and_then(|monitor| monitor.refresh_rate_millihertz())
+        .map(|millihertz| millihertz as f64 / 1000.0)
+        .unwrap_or(frame_pacing::DEFAULT_REFRESH_HZ)
+}
+
+/// Configuration for presenting to a surface with `caps`, in sRGB where possible.
+fn surface_config(
+    caps: &wgpu::SurfaceCapabilities,
+    width: u32,
+    height: u32,
+    present_mode: wgpu::PresentMode,
+) -> Result<wgpu::SurfaceConfiguration, GpuError> {
+    // Shader code in this tutorial assumes an sRGB surface texture. Using a different
+    // one will result all the colors coming out darker. If you want to support non
+    // sRGB surfaces, you'll need to account for that when drawing to the frame.
+    let format = caps.formats.iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .or(caps.formats.first().copied())
+        .ok_or(GpuError::SurfaceError)?;
+
+    Ok(wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width,
+        height,
+        present_mode: frame_pacing::select_present_mode(present_mode, &caps.present_modes),
+        alpha_mode: caps.alpha_modes.first().copied().unwrap_or(wgpu::CompositeAlphaMode::Auto),
+        view_formats: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_surface_config() {
+        let mut caps = wgpu::SurfaceCapabilities {
+            formats: vec![wgpu::TextureFormat::Bgra8Unorm, wgpu::TextureFormat::Bgra8UnormSrgb],
+            present_modes: vec![wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate],
+            alpha_modes: vec![wgpu::CompositeAlphaMode::Opaque],
+            usages: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        };
+        let config = surface_config(&caps, 800, 600, wgpu::PresentMode::Mailbox).unwrap();
+        assert_eq!(config.format, wgpu::TextureFormat::Bgra8UnormSrgb);
+        assert_eq!(config.present_mode, wgpu::PresentMode::Immediate);
+        assert_eq!((config.width, config.height), (800, 600));
+
+        // An adapter that can't present to the window
+        caps.formats.clear();
+        assert!(matches!(surface_config(&caps, 800, 600, wgpu::PresentMode::Fifo), Err(GpuError::SurfaceError)));
+    }
+
+    #[test]
+    fn test_pacing_presets_start_from_default() {
+        assert!(PACING_PRESETS.contains(&PacingSettings::default()));
+    }
+
+    #[test]
+    fn test_replay_keys() {
+        use crate::input::Binding;
+        use winit::event::MouseButton;
+        let mut bindings = InputBindings::empty();
+        bindings.bind(Binding::Key(VirtualKeyCode::Z), Action::K1);
+        bindings.bind(Binding::Mouse(MouseButton::Right), Action::M2);
+        let mut mapper = InputMapper::new(bindings);
+        assert_eq!(replay_keys(&mapper), Keys::NONE);
+
+        mapper.map_binding(Binding::Key(VirtualKeyCode::Z), true, 0.0);
+        mapper.map_binding(Binding::Mouse(MouseButton::Right), true, 0.0);
+        // K1 carries M1, so the keys read back as K1 | M2
+        assert_eq!(replay_keys(&mapper), Keys(Keys::K1.0 | Keys::M2.0));
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::hash::Hash;
+use ab_glyph::{Font as _, FontVec, ScaleFont};
+use image::{Rgba, RgbaImage};
+use crate::atlas::{Atlas, AtlasBuilder, DEFAULT_PADDING, DEFAULT_PAGE_SIZE};
+use crate::render_cache::RenderCache;
+use crate::skin::{self, Skin};
+use crate::sprite_batch::{Sprite, SpriteBatch, TextureId};
+
+/// Printable ASCII, rasterized for UI fonts by default.
+pub const ASCII: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// One glyph of laid-out text, relative to the top-left of the text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedGlyph<K> {
+    pub key: K,
+    pub position: (f32, f32),
+    pub size: (f32, f32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextLayout<K> {
+    pub glyphs: Vec<PlacedGlyph<K>>,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl<K: Hash + Eq> TextLayout<K> {
+    /// Sprites for the text with its `anchor` point, (0, 0) top-left to (1, 1) bottom-right, at `position`.
+    pub fn sprites(&self, atlas: &Atlas<K>, pages: &[TextureId], position: (f32, f32), anchor: (f32, f32)) -> Vec<Sprite> {
+        let origin = (position.0 - anchor.0 * self.width, position.1 - anchor.1 * self.height);
+        self.glyphs
+            .iter()
+            .filter_map(|glyph| {
+                let region = atlas.region(&glyph.key)?;
+                let glyph_position = (origin.0 + glyph.position.0, origin.1 + glyph.position.1);
+                Some(Sprite::new(pages[region.page], region, glyph_position, glyph.size).with_anchor(0.0, 0.0))
+            })
+            .collect()
+    }
+}
+
+/// Lays out `text` with a skin font such as the score digits. `overlap` comes from skin.ini and
+/// is in unscaled pixels; characters the font doesn't have are skipped.
+pub fn layout_skin_text(skin: &Skin, prefix: &str, overlap: i32, text: &str, scale: f32) -> TextLayout<(String, usize)> {
+    let mut glyphs = Vec::new();
+    let (mut x, mut width, mut height) = (0.0f32, 0.0f32, 0.0f32);
+    for character in text.chars() {
+        let (Some(name), Some(image)) = (skin::glyph_name(prefix, character), skin.glyph(prefix, character)) else {
+            continue;
+        };
+        let size = (image.size().0 * scale, image.size().1 * scale);
+        glyphs.push(PlacedGlyph {
+            key: (name, 0),
+            position: (x, 0.0),
+            size,
+        });
+        width = width.max(x + size.0);
+        height = height.max(size.1);
+        x += size.0 - overlap as f32 * scale;
+    }
+    TextLayout { glyphs, width, height }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct GlyphMetrics {
+    /// Top-left of the glyph image relative to the pen position at the top of the line.
+    offset: (f32, f32),
+    size: (f32, f32),
+}
+
+/// A TTF/OTF font rasterized at one pixel size; text is scaled from there.
+pub struct Font {
+    font: FontVec,
+    pixel_size: f32,
+    glyphs: HashMap<char, GlyphMetrics>,
+}
+
+impl Font {
+    pub fn from_bytes(bytes: Vec<u8>, pixel_size: f32, charset: &str) -> Result<Font, FontError> {
+        let font = FontVec::try_from_vec(bytes).map_err(|_| FontError::ParseError)?;
+        let mut glyphs = HashMap::new();
+        {
+            let scaled = font.as_scaled(pixel_size);
+            for character in charset.chars() {
+                let glyph = scaled.scaled_glyph(character);
+                if let Some(outline) = font.outline_glyph(glyph) {
+                    let bounds = outline.px_bounds();
+                    glyphs.insert(character, GlyphMetrics {
+                        offset: (bounds.min.x, bounds.min.y + scaled.ascent()),
+                        size: (bounds.width(), bounds.height()),
+                    });
+                }
+            }
+        }
+        Ok(Font { font, pixel_size, glyphs })
+    }
+
+    pub fn from_file(path: &str, pixel_size: f32, charset: &str) -> Result<Font, FontError> {
+        let bytes = std::fs::read(path).map_err(|_| FontError::FileError)?;
+        Font::from_bytes(bytes, pixel_size, charset)
+    }
+
+    /// egui's bundled UI font, so there's always something to draw labels with.
+    pub fn default_ui(pixel_size: f32) -> Font {
+        let definitions = egui::FontDefinitions::default();
+        let bytes = definitions.font_data["Ubuntu-Light"].font.to_vec();
+        Font::from_bytes(bytes, pixel_size, ASCII).expect("bundled font is valid")
+    }
+
+    /// White glyph images with coverage in alpha, ready for an atlas.
+    pub fn rasterize(&self) -> AtlasBuilder<char> {
+        let mut builder = AtlasBuilder::new(DEFAULT_PAGE_SIZE, DEFAULT_PADDING);
+        let scaled = self.font.as_scaled(self.pixel_size);
+        for (character, metrics) in &self.glyphs {
+            let Some(outline) = self.font.outline_glyph(scaled.scaled_glyph(*character)) else {
+                continue;
+            };
+            let (width, height) = (metrics.size.0 as u32, metrics.size.1 as u32);
+            let mut image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 0]));
+            outline.draw(|x, y, coverage| {
+                if x < width && y < height {
+                    image.get_pixel_mut(x, y)[3] = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            });
+            builder.add(*character, image);
+        }
+        builder
+    }
+
+    /// Lays out a single line of text `size` units tall, with kerning.
+    pub fn layout(&self, text: &str, size: f32) -> TextLayout<char> {
+        let scaled = self.font.as_scaled(self.pixel_size);
+        let scale = size / self.pixel_size;
+        let mut glyphs = Vec::new();
+        let mut x = 0.0;
+        let mut previous = None;
+        for character in text.chars() {
+            let id = self.font.glyph_id(character);
+            if let Some(previous) = previous {
+                x += scaled.kern(previous, id);
+            }
+            if let Some(metrics) = self.glyphs.get(&character) {
+                glyphs.push(PlacedGlyph {
+                    key: character,
+                    position: ((x + metrics.offset.0) * scale, metrics.offset.1 * scale),
+                    size: (metrics.size.0 * scale, metrics.size.1 * scale),
+                });
+            }
+            x += scaled.h_advance(id);
+            previous = Some(id);
+        }
+        TextLayout {
+            glyphs,
+            width: x * scale,
+            height: scaled.height() * scale,
+        }
+    }
+}
+
+/// A font with its glyphs uploaded and registered with a sprite batch.
+pub struct FontAtlas {
+    pub font: Font,
+    pub atlas: Atlas<char>,
+    pub pages: Vec<TextureId>,
+}
+
+impl FontAtlas {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, cache: &mut RenderCache, batch: &mut SpriteBatch, font: Font) -> anyhow::Result<FontAtlas> {
+        let atlas = font.rasterize().build(device, queue, cache)?;
+        let pages = batch.register_atlas(device, cache, &atlas);
+        Ok(FontAtlas { font, atlas, pages })
+    }
+
+    pub fn sprites(&self, text: &str, size: f32, position: (f32, f32), anchor: (f32, f32)) -> Vec<Sprite> {
+        self.font.layout(text, size).sprites(&self.atlas, &self.pages, position, anchor)
+    }
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    FileError,
+    ParseError,
+}
+
+impl Display for FontError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FontError::FileError => write!(f, "FileError"),
+            FontError::ParseError => write!(f, "ParseError"),
+        }
+    }
+}
+
+impl Error for FontError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skin_text_overlap() {
+        let skin = Skin::new();
+        // Default digits are 48x64; a negative overlap spaces them out
+        let layout = layout_skin_text(&skin, "default", -2, "1?2", 0.5);
+        assert_eq!(layout.glyphs.len(), 2);
+        assert_eq!(layout.glyphs[0].key, ("default-1".to_string(), 0));
+        assert_eq!(layout.glyphs[1].position, (25.0, 0.0));
+        assert_eq!((layout.width, layout.height), (49.0, 32.0));
+
+        let layout = layout_skin_text(&skin, "score", 10, "00", 1.0);
+        assert_eq!(layout.glyphs[1].position, (38.0, 0.0));
+    }
+
+    #[test]
+    fn test_font_layout() {
+        let font = Font::default_ui(32.0);
+        let layout = font.layout("A B", 16.0);
+        // The space has no image but still advances
+        assert_eq!(layout.glyphs.len(), 2);
+        assert!(layout.glyphs[1].position.0 > layout.glyphs[0].position.0 + layout.glyphs[0].size.0);
+        assert!(layout.width > layout.glyphs[1].position.0);
+        assert!(layout.height > 10.0 && layout.height < 32.0);
+
+        let (pages, regions) = font.rasterize().build_images();
+        assert_eq!(pages.len(), 1);
+        assert!(regions.contains_key(&'A') && !regions.contains_key(&' '));
+    }
+}
@@ -1,10 +1,78 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use image::GenericImageView;
 use anyhow::*;
+use crate::mipmap::{self, MipmapGenerator};
+use crate::render_cache::RenderCache;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipmapMode {
+    /// Only the full-size level, for images that are never drawn much smaller.
+    None,
+    /// Rendered on the GPU, falling back to the CPU if the format can't be rendered to.
+    Gpu,
+    Cpu,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextureOptions {
+    pub mipmaps: MipmapMode,
+    /// Caps the mip chain, e.g. so atlas padding still separates regions at the smallest level.
+    pub max_mip_levels: Option<u32>,
+    /// Multiplies colours by alpha on upload, for premultiplied-alpha blending.
+    /// Turn off for images that already are, like atlas pages.
+    pub premultiply: bool,
+    pub sampler: wgpu::SamplerDescriptor<'static>,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            mipmaps: MipmapMode::Gpu,
+            max_mip_levels: None,
+            premultiply: true,
+            sampler: trilinear_sampler(16),
+        }
+    }
+}
+
+/// Linear filtering between and within mip levels. Anisotropy is ignored where unsupported.
+pub fn trilinear_sampler(anisotropy: u16) -> wgpu::SamplerDescriptor<'static> {
+    wgpu::SamplerDescriptor {
+        label: Some("Trilinear Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        anisotropy_clamp: anisotropy.max(1),
+        ..Default::default()
+    }
+}
+
+/// Premultiplies sRGB pixels in linear space, so an sRGB texture decodes to the right value.
+pub fn premultiply_alpha(image: &mut image::RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as f32 / 255.0;
+        for channel in 0..3 {
+            let linear = (pixel[channel] as f32 / 255.0).powf(2.2) * alpha;
+            pixel[channel] = (linear.powf(1.0 / 2.2) * 255.0).round() as u8;
+        }
+    }
+}
+
+/// Format of textures loaded from images.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Source of `Texture::id`s.
+static NEXT_TEXTURE_ID: AtomicU64 = AtomicU64::new(0);
 
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler
+    pub sampler: wgpu::Sampler,
+    /// Unique for the life of the process, so caches can key on the texture.
+    pub id: u64,
 }
 
 impl Texture {
@@ -24,8 +92,57 @@ impl Texture {
         img: &image::DynamicImage,
         label: Option<&str>
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
+        let options = TextureOptions {
+            mipmaps: MipmapMode::None,
+            ..Default::default()
+        };
+        Self::upload(device, queue, None, img, label, &options)
+    }
+
+    /// Like `from_image`, with mipmaps rendered by `cache`'s generator for the format.
+    pub fn from_image_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cache: &mut RenderCache,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions
+    ) -> Result<Self> {
+        let generator = (options.mipmaps == MipmapMode::Gpu && MipmapGenerator::supports(device, FORMAT))
+            .then(|| cache.mipmap_generator(device, FORMAT));
+        Self::upload(device, queue, generator, img, label, options)
+    }
+
+    /// Without a `generator`, GPU mipmaps are built on the CPU instead.
+    fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        generator: Option<&MipmapGenerator>,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions
+    ) -> Result<Self> {
+        let mut rgba = img.to_rgba8();
+        if options.premultiply {
+            premultiply_alpha(&mut rgba);
+        }
         let dimensions = img.dimensions();
+        let format = FORMAT;
+
+        let mipmaps = match options.mipmaps {
+            MipmapMode::Gpu if generator.is_none() => MipmapMode::Cpu,
+            mipmaps => mipmaps,
+        };
+        let mip_level_count = match mipmaps {
+            MipmapMode::None => 1,
+            _ => mipmap::mip_level_count(dimensions.0, dimensions.1)
+                .min(options.max_mip_levels.unwrap_or(u32::MAX))
+                .max(1),
+        };
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if mipmaps == MipmapMode::Gpu {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
@@ -36,11 +153,11 @@ impl Texture {
             &wgpu::TextureDescriptor {
                 label,
                 size,
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                format,
+                usage,
                 view_formats: &[]
             }
         );
@@ -61,20 +178,85 @@ impl Texture {
             size
         );
 
+        match (mipmaps, generator) {
+            (MipmapMode::Gpu, Some(generator)) if mip_level_count > 1 => generator.generate(device, queue, &texture),
+            (MipmapMode::Cpu, _) => {
+                for (index, level) in mipmap::cpu_mip_chain(&rgba, mip_level_count).iter().enumerate() {
+                    let (width, height) = level.dimensions();
+                    queue.write_texture(
+                        wgpu::ImageCopyTexture {
+                            aspect: wgpu::TextureAspect::All,
+                            texture: &texture,
+                            mip_level: index as u32 + 1,
+                            origin: wgpu::Origin3d::ZERO
+                        },
+                        level,
+                        wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: Some(4 * width),
+                            rows_per_image: Some(height),
+                        },
+                        wgpu::Extent3d { width, height, depth_or_array_layers: 1 }
+                    );
+                }
+            }
+            _ => {}
+        }
+
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
-            &wgpu::SamplerDescriptor {
-                label: Some("Texture Sampler"),
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                ..Default::default()
+        let mut sampler_descriptor = options.sampler.clone();
+        // wgpu rejects anisotropy unless every filter is linear
+        let all_linear = [sampler_descriptor.mag_filter, sampler_descriptor.min_filter, sampler_descriptor.mipmap_filter]
+            .iter()
+            .all(|filter| *filter == wgpu::FilterMode::Linear);
+        if !all_linear {
+            sampler_descriptor.anisotropy_clamp = 1;
+        }
+        let sampler = device.create_sampler(&sampler_descriptor);
+
+        Ok(Self { texture, view, sampler, id: NEXT_TEXTURE_ID.fetch_add(1, Ordering::Relaxed) })
+    }
+
+    /// Single-level texture meant to be rewritten with `write`, like video frames.
+    pub fn streaming(device: &wgpu::Device, width: u32, height: u32, label: Option<&str>) -> Self {
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[]
             }
         );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&trilinear_sampler(1));
+        Self { texture, view, sampler, id: NEXT_TEXTURE_ID.fetch_add(1, Ordering::Relaxed) }
+    }
 
-        Ok(Self { texture, view, sampler })
+    /// Replaces the top level with tightly packed RGBA8 pixels, which should already be premultiplied.
+    pub fn write(&self, queue: &wgpu::Queue, pixels: &[u8]) {
+        let size = self.texture.size();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size
+        );
     }
 }
\ No newline at end of file
@@ -0,0 +1,111 @@
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use crate::frame_pacing::RollingStats;
+use crate::profiler::{Profile, Timings};
+
+/// Table of rolling frame, CPU and GPU timings drawn with egui over the finished frame.
+/// It only displays, so it takes no input.
+pub struct TimingOverlay {
+    context: egui::Context,
+    render_pass: RenderPass,
+    /// Textures egui is done with once the last prepared frame is drawn.
+    retired: Option<egui::TexturesDelta>,
+    /// Window pixels per egui point.
+    pub scale_factor: f32,
+}
+
+impl TimingOverlay {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        TimingOverlay {
+            context: egui::Context::default(),
+            render_pass: RenderPass::new(device, format, 1),
+            retired: None,
+            scale_factor: 1.0,
+        }
+    }
+
+    /// Lays out the table and uploads what drawing it needs.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        profile: &Profile,
+        gpu: Option<&Timings>,
+    ) -> Vec<egui::ClippedPrimitive> {
+        if let Some(retired) = self.retired.take() {
+            if let Err(error) = self.render_pass.remove_textures(retired) {
+                log::warn!("Couldn't free overlay textures: {}", error);
+            }
+        }
+        let screen = self.screen(width, height);
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(width as f32, height as f32) / self.scale_factor,
+            )),
+            pixels_per_point: Some(self.scale_factor),
+            ..Default::default()
+        };
+        let output = self.context.run(input, |context| {
+            egui::Window::new("Frame timings")
+                .anchor(egui::Align2::LEFT_TOP, [8.0, 40.0])
+                .resizable(false)
+                .collapsible(false)
+                .show(context, |ui| {
+                    egui::Grid::new("timings").striped(true).num_columns(6).show(ui, |ui| {
+                        for heading in ["ms", "min", "avg", "p99", "max", "samples"] {
+                            ui.strong(heading);
+                        }
+                        ui.end_row();
+                        stats_row(ui, "Frame", profile.frame_times);
+                        section_rows(ui, "CPU", profile.cpu);
+                        match gpu {
+                            Some(gpu) => section_rows(ui, "GPU", gpu),
+                            None => {
+                                ui.label("GPU timestamps unsupported");
+                                ui.end_row();
+                            }
+                        }
+                    });
+                });
+        });
+        let primitives = self.context.tessellate(output.shapes);
+        if let Err(error) = self.render_pass.add_textures(device, queue, &output.textures_delta) {
+            log::warn!("Couldn't upload overlay textures: {}", error);
+        }
+        self.render_pass.update_buffers(device, queue, &primitives, &screen);
+        self.retired = Some(output.textures_delta);
+        primitives
+    }
+
+    /// Draws primitives from `prepare` into a single-sampled pass.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, primitives: &[egui::ClippedPrimitive], width: u32, height: u32) {
+        if let Err(error) = self.render_pass.execute_with_renderpass(render_pass, primitives, &self.screen(width, height)) {
+            log::warn!("Couldn't draw the timing overlay: {}", error);
+        }
+    }
+
+    fn screen(&self, width: u32, height: u32) -> ScreenDescriptor {
+        ScreenDescriptor {
+            physical_width: width,
+            physical_height: height,
+            scale_factor: self.scale_factor,
+        }
+    }
+}
+
+fn section_rows(ui: &mut egui::Ui, group: &str, timings: &Timings) {
+    for (name, stats) in timings.sections() {
+        stats_row(ui, &format!("{} {}", group, name), stats);
+    }
+}
+
+fn stats_row(ui: &mut egui::Ui, name: &str, stats: &RollingStats) {
+    ui.label(name);
+    for value in [stats.min(), stats.average(), stats.percentile(99.0), stats.max()] {
+        ui.monospace(value.map_or("-".to_string(), |ms| format!("{:.2}", ms)));
+    }
+    ui.monospace(stats.len().to_string());
+    ui.end_row();
+}
@@ -1,11 +1,23 @@
+/// osu!pixel -> clip space transform, see `Playfield::uniform`.
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct ScaleFactorUniform {
-    pub scale_factor: [f32; 2],
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PlayfieldUniform {
+    pub scale: [f32; 2],
+    pub offset: [f32; 2],
+}
+
+impl PlayfieldUniform {
+    /// Window pixel -> clip space, for UI drawn outside the playfield.
+    pub fn screen(width: f32, height: f32) -> PlayfieldUniform {
+        PlayfieldUniform {
+            scale: [2.0 / width, -2.0 / height],
+            offset: [-1.0, 1.0],
+        }
+    }
 }
 
 // #[repr(C)]
 // #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 // pub struct PositionsUniform {
 //     pub positions: [[f32; 2]; 100]
-// }
\ No newline at end of file
+// }
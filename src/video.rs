@@ -0,0 +1,247 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use crate::texture::Texture;
+
+/// Frames decoded ahead of the one on screen.
+const FRAMES_AHEAD: usize = 4;
+/// Seeking further ahead than this restarts the decoder instead of decoding every frame in between.
+const MAX_SKIP_MS: f64 = 2000.0;
+
+/// Size and frame rate of a video's first video stream.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+}
+
+impl VideoInfo {
+    pub fn probe(path: &Path) -> Result<VideoInfo, VideoError> {
+        let output = Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=width,height,r_frame_rate", "-of", "csv=p=0"])
+            .arg(path)
+            .output()
+            .map_err(|_| VideoError::DecoderMissing)?;
+        if !output.status.success() {
+            return Err(VideoError::FileError);
+        }
+        Self::parse(&String::from_utf8_lossy(&output.stdout)).ok_or(VideoError::FileError)
+    }
+
+    /// Parses ffprobe's `width,height,numerator/denominator` line.
+    fn parse(line: &str) -> Option<VideoInfo> {
+        let mut fields = line.trim().split(',');
+        let width = fields.next()?.parse().ok()?;
+        let height = fields.next()?.parse().ok()?;
+        let rate = fields.next()?;
+        let fps = match rate.split_once('/') {
+            Some((numerator, denominator)) => numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?,
+            None => rate.parse().ok()?,
+        };
+        (width > 0 && height > 0 && fps.is_finite() && fps > 0.0).then_some(VideoInfo { width, height, fps })
+    }
+
+    /// Bytes in one decoded RGBA frame.
+    pub fn frame_bytes(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+
+    /// Index of the frame on screen `video_ms` into the video.
+    pub fn frame_at(&self, video_ms: f64) -> u64 {
+        (video_ms * self.fps / 1000.0).floor().max(0.0) as u64
+    }
+
+    pub fn frame_time(&self, frame: u64) -> f64 {
+        frame as f64 * 1000.0 / self.fps
+    }
+}
+
+/// An ffmpeg process decoding frames from some point on, read by a thread into a bounded channel.
+struct DecodeStream {
+    child: Child,
+    frames: Receiver<Vec<u8>>,
+    /// Index of the next frame the channel yields.
+    next_frame: u64,
+}
+
+impl DecodeStream {
+    fn start(path: &Path, info: &VideoInfo, first_frame: u64) -> Result<DecodeStream, VideoError> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-v", "error", "-ss"])
+            .arg(format!("{:.3}", info.frame_time(first_frame) / 1000.0))
+            .arg("-i")
+            .arg(path)
+            .args(["-an", "-f", "rawvideo", "-pix_fmt", "rgba", "-r"])
+            .arg(info.fps.to_string())
+            .arg("-")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|_| VideoError::DecoderMissing)?;
+
+        let mut stdout = child.stdout.take().ok_or(VideoError::DecoderMissing)?;
+        let frame_bytes = info.frame_bytes();
+        let (sender, frames) = mpsc::sync_channel(FRAMES_AHEAD);
+        // Ends once ffmpeg does, or once the stream is dropped and sending fails
+        std::thread::spawn(move || loop {
+            let mut frame = vec![0; frame_bytes];
+            if stdout.read_exact(&mut frame).is_err() || sender.send(frame).is_err() {
+                break;
+            }
+        });
+        Ok(DecodeStream { child, frames, next_frame: first_frame })
+    }
+}
+
+impl Drop for DecodeStream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Streams a video into a texture, following the gameplay clock. Decoding is done by an
+/// `ffmpeg` process, which is restarted at the new position when the clock jumps.
+pub struct VideoPlayer {
+    path: PathBuf,
+    pub info: VideoInfo,
+    /// Gameplay time the video starts at, in milliseconds.
+    pub start_time: f64,
+    /// Block until the frame for the current time is decoded, for offline rendering.
+    pub wait_for_frames: bool,
+    pub texture: Texture,
+    stream: Option<DecodeStream>,
+    /// Frame currently in the texture.
+    shown_frame: Option<u64>,
+    /// Frame the last stream stopped at, so a finished or broken video isn't restarted every frame.
+    end_frame: Option<u64>,
+}
+
+impl VideoPlayer {
+    pub fn open(device: &wgpu::Device, path: &Path, start_time: f64) -> Result<VideoPlayer, VideoError> {
+        let info = VideoInfo::probe(path)?;
+        let texture = Texture::streaming(device, info.width, info.height, Some("Video Texture"));
+        Ok(VideoPlayer {
+            path: path.to_path_buf(),
+            info,
+            start_time,
+            wait_for_frames: false,
+            texture,
+            stream: None,
+            shown_frame: None,
+            end_frame: None,
+        })
+    }
+
+    /// Uploads the frame for gameplay `time`. Returns whether the texture shows the video at
+    /// that time, which it doesn't before the video starts, after it ends, or while the decoder catches up after a seek.
+    pub fn update(&mut self, queue: &wgpu::Queue, time: f64) -> bool {
+        let video_time = time - self.start_time;
+        if video_time < 0.0 {
+            return false;
+        }
+        let wanted = self.info.frame_at(video_time);
+        if self.end_frame.is_some_and(|end| wanted >= end) {
+            return false;
+        }
+
+        let restart = match &self.stream {
+            Some(stream) => wanted + 1 < stream.next_frame || self.info.frame_time(wanted.saturating_sub(stream.next_frame)) > MAX_SKIP_MS,
+            None => true,
+        };
+        if restart {
+            self.stream = None;
+            self.shown_frame = None;
+            match DecodeStream::start(&self.path, &self.info, wanted) {
+                Ok(stream) => self.stream = Some(stream),
+                Err(error) => {
+                    log::warn!("Couldn't decode {}: {}", self.path.display(), error);
+                    self.end_frame = Some(wanted);
+                    return false;
+                }
+            }
+        }
+
+        let Some(stream) = &mut self.stream else {
+            return false;
+        };
+        let mut latest = None;
+        let mut ended = false;
+        while stream.next_frame <= wanted {
+            let frame = if self.wait_for_frames {
+                stream.frames.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                stream.frames.try_recv()
+            };
+            match frame {
+                Ok(frame) => {
+                    latest = Some(frame);
+                    stream.next_frame += 1;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    ended = true;
+                    break;
+                }
+            }
+        }
+        if let Some(frame) = latest {
+            self.texture.write(queue, &frame);
+            self.shown_frame = Some(stream.next_frame - 1);
+        }
+        if ended {
+            self.end_frame = Some(stream.next_frame);
+            self.stream = None;
+            return false;
+        }
+        self.shown_frame.is_some_and(|shown| shown <= wanted)
+    }
+}
+
+#[derive(Debug)]
+pub enum VideoError {
+    /// ffmpeg or ffprobe couldn't be run.
+    DecoderMissing,
+    FileError,
+}
+
+impl Display for VideoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VideoError::DecoderMissing => write!(f, "DecoderMissing"),
+            VideoError::FileError => write!(f, "FileError"),
+        }
+    }
+}
+
+impl Error for VideoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_video_info() {
+        let info = VideoInfo::parse("1280,720,30000/1001\n").unwrap();
+        assert_eq!((info.width, info.height, info.frame_bytes()), (1280, 720, 1280 * 720 * 4));
+        assert!((info.fps - 29.97).abs() < 0.01);
+        assert_eq!(VideoInfo::parse("640,480,25").unwrap().fps, 25.0);
+        assert_eq!(VideoInfo::parse("640,480,0/0"), None);
+        assert_eq!(VideoInfo::parse(""), None);
+
+        let info = VideoInfo { width: 1, height: 1, fps: 25.0 };
+        assert_eq!(info.frame_at(-10.0), 0);
+        assert_eq!(info.frame_at(79.0), 1);
+        assert_eq!(info.frame_at(80.0), 2);
+        assert_eq!(info.frame_time(2), 80.0);
+    }
+}